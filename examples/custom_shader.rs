@@ -0,0 +1,116 @@
+// Copyright (c) 2021 Okko Hakola
+// Licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT
+// license <LICENSE-MIT or https://opensource.org/licenses/MIT>,
+// at your option. All files in the project carrying such
+// notice may not be copied, modified, or distributed except
+// according to those terms.
+
+// Draws the egui demo with a fragment shader tinting the whole ui green, like an old terminal.
+// The built-in vertex shader is kept.
+use egui_winit_vulkano::{Gui, GuiConfig, GuiShaders};
+use vulkano_util::{
+    context::{VulkanoConfig, VulkanoContext},
+    window::{VulkanoWindows, WindowDescriptor},
+};
+use winit::{
+    event::{Event, WindowEvent},
+    event_loop::{ControlFlow, EventLoop},
+};
+
+mod tint_fs {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        src: "
+#version 450
+
+layout(location = 0) in vec4 v_color;
+layout(location = 1) in vec2 v_tex_coords;
+
+layout(location = 0) out vec4 f_color;
+
+layout(binding = 0, set = 0) uniform sampler2D font_texture;
+
+// Must match the push constants of the built-in vertex shader
+layout(push_constant) uniform PushConstants {
+    vec2 screen_size;
+    int need_srgb_conv;
+    int texture_conv;
+} push_constants;
+
+void main() {
+    // Premultiplied alpha, in linear space as the target is sRGB
+    vec4 color = v_color * texture(font_texture, v_tex_coords);
+    float luminance = dot(color.rgb, vec3(0.2126, 0.7152, 0.0722));
+    f_color = vec4(luminance * vec3(0.2, 1.0, 0.3), color.a);
+}"
+    }
+}
+
+pub fn main() {
+    // Winit event loop
+    let event_loop = EventLoop::new();
+    // Vulkano context
+    let context = VulkanoContext::new(VulkanoConfig::default());
+    // Vulkano windows (create one)
+    let mut windows = VulkanoWindows::default();
+    windows.create_window(&event_loop, &context, &WindowDescriptor::default(), |ci| {
+        ci.image_format = Some(vulkano::format::Format::B8G8R8A8_SRGB);
+        ci.min_image_count = ci.min_image_count.max(2);
+    });
+    // Create gui as main render pass (no overlay means it clears the image each frame)
+    let mut gui = {
+        let renderer = windows.get_primary_renderer_mut().unwrap();
+        Gui::new(&event_loop, renderer.surface(), renderer.graphics_queue(), GuiConfig::default())
+    };
+    // Replace the fragment shader, errors tell what doesn't match egui's pipeline
+    let fragment = tint_fs::load(gui.device()).expect("failed to create shader module");
+    gui.set_shaders(GuiShaders { vertex: None, fragment: Some(fragment) })
+        .unwrap_or_else(|err| panic!("Failed to set shaders: {}", err));
+    let mut demo_app = egui_demo_lib::DemoWindows::default();
+    event_loop.run(move |event, _, control_flow| {
+        let renderer = windows.get_primary_renderer_mut().unwrap();
+        match event {
+            Event::WindowEvent { event, window_id } if window_id == renderer.window().id() => {
+                // Update Egui integration so the UI works!
+                let _pass_events_to_game = !gui.update(&event);
+                match event {
+                    WindowEvent::Resized(_) => {
+                        renderer.resize();
+                    }
+                    WindowEvent::ScaleFactorChanged { .. } => {
+                        renderer.resize();
+                    }
+                    WindowEvent::CloseRequested => {
+                        *control_flow = ControlFlow::Exit;
+                    }
+                    _ => (),
+                }
+            }
+            Event::RedrawRequested(_) => {
+                gui.immediate_ui(|gui| {
+                    let ctx = gui.context();
+                    demo_app.ui(&ctx);
+                });
+                // Render UI
+                // Acquire swapchain future
+                let before_future = renderer.acquire().unwrap();
+                gui.swapchain_recreated(
+                    renderer.swapchain_image_size().into(),
+                    Some(renderer.swapchain_format()),
+                )
+                .expect("Gui can't draw to the new swapchain format");
+                // Render gui
+                let after_future =
+                    gui.draw_on_image(before_future, renderer.swapchain_image_view());
+                // Present swapchain
+                renderer.present(after_future, true);
+            }
+            Event::MainEventsCleared => {
+                renderer.window().request_redraw();
+            }
+            _ => (),
+        }
+    });
+}
@@ -0,0 +1,120 @@
+// Copyright (c) 2021 Okko Hakola
+// Licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT
+// license <LICENSE-MIT or https://opensource.org/licenses/MIT>,
+// at your option. All files in the project carrying such
+// notice may not be copied, modified, or distributed except
+// according to those terms.
+
+// Plots the CPU time of each phase of the gui's frames next to the egui demo windows
+use std::collections::VecDeque;
+
+use egui::plot::{Legend, Line, Plot, PlotPoints};
+use egui_winit_vulkano::{FrameTimings, Gui, GuiConfig};
+use vulkano_util::{
+    context::{VulkanoConfig, VulkanoContext},
+    window::{VulkanoWindows, WindowDescriptor},
+};
+use winit::{
+    event::{Event, WindowEvent},
+    event_loop::{ControlFlow, EventLoop},
+};
+
+/// Frames shown in the plot
+const HISTORY: usize = 300;
+
+/// Reads one phase's duration out of a frame's timings
+type Phase = fn(&FrameTimings) -> std::time::Duration;
+
+fn timings_plot(ui: &mut egui::Ui, history: &VecDeque<FrameTimings>) {
+    let phases: [(&str, Phase); 5] = [
+        ("input", |timings| timings.input),
+        ("ui closure", |timings| timings.ui_closure),
+        ("tessellation", |timings| timings.tessellation),
+        ("upload", |timings| timings.upload),
+        ("record", |timings| timings.record),
+    ];
+    Plot::new("frame_timings").legend(Legend::default()).include_y(0.0).show(ui, |plot_ui| {
+        for (name, phase) in phases.iter() {
+            let points: PlotPoints = history
+                .iter()
+                .enumerate()
+                .map(|(frame, timings)| [frame as f64, phase(timings).as_secs_f64() * 1000.0])
+                .collect();
+            plot_ui.line(Line::new(points).name(format!("{} (ms)", name)));
+        }
+    });
+}
+
+pub fn main() {
+    // Winit event loop
+    let event_loop = EventLoop::new();
+    // Vulkano context
+    let context = VulkanoContext::new(VulkanoConfig::default());
+    // Vulkano windows (create one)
+    let mut windows = VulkanoWindows::default();
+    windows.create_window(&event_loop, &context, &WindowDescriptor::default(), |ci| {
+        ci.image_format = Some(vulkano::format::Format::B8G8R8A8_SRGB);
+        ci.min_image_count = ci.min_image_count.max(2);
+    });
+    // Create gui as main render pass (no overlay means it clears the image each frame)
+    let mut gui = {
+        let renderer = windows.get_primary_renderer_mut().unwrap();
+        Gui::new(&event_loop, renderer.surface(), renderer.graphics_queue(), GuiConfig::default())
+    };
+    let mut demo_app = egui_demo_lib::DemoWindows::default();
+    let mut history = VecDeque::with_capacity(HISTORY);
+    event_loop.run(move |event, _, control_flow| {
+        let renderer = windows.get_primary_renderer_mut().unwrap();
+        match event {
+            Event::WindowEvent { event, window_id } if window_id == renderer.window().id() => {
+                // Update Egui integration so the UI works!
+                let _pass_events_to_game = !gui.update(&event);
+                match event {
+                    WindowEvent::Resized(_) => {
+                        renderer.resize();
+                    }
+                    WindowEvent::ScaleFactorChanged { .. } => {
+                        renderer.resize();
+                    }
+                    WindowEvent::CloseRequested => {
+                        *control_flow = ControlFlow::Exit;
+                    }
+                    _ => (),
+                }
+            }
+            Event::RedrawRequested(_) => {
+                gui.immediate_ui(|gui| {
+                    let ctx = gui.context();
+                    demo_app.ui(&ctx);
+                    egui::Window::new("Frame timings")
+                        .default_size([400.0, 250.0])
+                        .show(&ctx, |ui| timings_plot(ui, &history));
+                });
+                // Render UI
+                // Acquire swapchain future
+                let before_future = renderer.acquire().unwrap();
+                gui.swapchain_recreated(
+                    renderer.swapchain_image_size().into(),
+                    Some(renderer.swapchain_format()),
+                )
+                .expect("Gui can't draw to the new swapchain format");
+                // Render gui
+                let after_future =
+                    gui.draw_on_image(before_future, renderer.swapchain_image_view());
+                // Present swapchain
+                renderer.present(after_future, true);
+                // Timings are complete once the frame is drawn
+                if history.len() == HISTORY {
+                    history.pop_front();
+                }
+                history.push_back(gui.last_frame_timings());
+            }
+            Event::MainEventsCleared => {
+                renderer.window().request_redraw();
+            }
+            _ => (),
+        }
+    });
+}
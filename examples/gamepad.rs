@@ -0,0 +1,116 @@
+// Copyright (c) 2021 Okko Hakola
+// Licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT
+// license <LICENSE-MIT or https://opensource.org/licenses/MIT>,
+// at your option. All files in the project carrying such
+// notice may not be copied, modified, or distributed except
+// according to those terms.
+
+// Navigates the egui demo windows with simulated gamepad input. Replace `SimulatedGamepad`
+// with your own input backend, e.g. gilrs.
+use std::time::Instant;
+
+use egui_winit_vulkano::{
+    gamepad::{GamepadButton, GamepadCursor, GamepadInput},
+    Gui, GuiConfig,
+};
+use vulkano_util::{
+    context::{VulkanoConfig, VulkanoContext},
+    window::{VulkanoWindows, WindowDescriptor},
+};
+use winit::{
+    event::{Event, WindowEvent},
+    event_loop::{ControlFlow, EventLoop},
+};
+
+/// Circles the stick, taps the D-pad down every second and clicks every three seconds
+struct SimulatedGamepad {
+    time: f32,
+}
+
+impl GamepadInput for SimulatedGamepad {
+    fn stick(&self) -> egui::Vec2 {
+        egui::vec2(self.time.cos(), self.time.sin()) * 0.5
+    }
+
+    fn is_pressed(&self, button: GamepadButton) -> bool {
+        match button {
+            GamepadButton::Primary => self.time % 3.0 < 0.1,
+            GamepadButton::DPadDown => self.time % 1.0 < 0.1,
+            _ => false,
+        }
+    }
+}
+
+pub fn main() {
+    // Winit event loop
+    let event_loop = EventLoop::new();
+    // Vulkano context
+    let context = VulkanoContext::new(VulkanoConfig::default());
+    // Vulkano windows (create one)
+    let mut windows = VulkanoWindows::default();
+    windows.create_window(&event_loop, &context, &WindowDescriptor::default(), |ci| {
+        ci.image_format = Some(vulkano::format::Format::B8G8R8A8_SRGB);
+        ci.min_image_count = ci.min_image_count.max(2);
+    });
+    // Create gui as main render pass (no overlay means it clears the image each frame)
+    let mut gui = {
+        let renderer = windows.get_primary_renderer_mut().unwrap();
+        Gui::new(&event_loop, renderer.surface(), renderer.graphics_queue(), GuiConfig::default())
+    };
+    let mut demo_app = egui_demo_lib::DemoWindows::default();
+    let mut cursor = GamepadCursor::default();
+    cursor.pos = egui::pos2(200.0, 200.0);
+    let start = Instant::now();
+    let mut last_frame = Instant::now();
+    event_loop.run(move |event, _, control_flow| {
+        let renderer = windows.get_primary_renderer_mut().unwrap();
+        match event {
+            Event::WindowEvent { event, window_id } if window_id == renderer.window().id() => {
+                // Update Egui integration so the UI works!
+                let _pass_events_to_game = !gui.update(&event);
+                match event {
+                    WindowEvent::Resized(_) => {
+                        renderer.resize();
+                    }
+                    WindowEvent::ScaleFactorChanged { .. } => {
+                        renderer.resize();
+                    }
+                    WindowEvent::CloseRequested => {
+                        *control_flow = ControlFlow::Exit;
+                    }
+                    _ => (),
+                }
+            }
+            Event::RedrawRequested(_) => {
+                // Feed gamepad input before the frame begins
+                let gamepad = SimulatedGamepad { time: start.elapsed().as_secs_f32() };
+                cursor.update(&mut gui, &gamepad, last_frame.elapsed().as_secs_f32());
+                last_frame = Instant::now();
+                gui.immediate_ui(|gui| {
+                    let ctx = gui.context();
+                    demo_app.ui(&ctx);
+                    // The cursor draws nothing itself, so show where it is
+                    ctx.layer_painter(egui::LayerId::new(
+                        egui::Order::Tooltip,
+                        egui::Id::new("gamepad_cursor"),
+                    ))
+                    .circle_filled(cursor.pos, 5.0, egui::Color32::RED);
+                });
+                // Render UI
+                // Acquire swapchain future
+                let before_future = renderer.acquire().unwrap();
+                // Render gui
+                let after_future =
+                    gui.draw_on_image(before_future, renderer.swapchain_image_view());
+                // Present swapchain
+                renderer.present(after_future, true);
+            }
+            Event::MainEventsCleared => {
+                renderer.window().request_redraw();
+            }
+            _ => (),
+        }
+    });
+}
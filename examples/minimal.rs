@@ -92,6 +92,13 @@ pub fn main() {
                 // Render UI
                 // Acquire swapchain future
                 let before_future = renderer.acquire().unwrap();
+                // The swapchain is recreated on acquire after a resize, let the gui know (cheap if
+                // nothing changed)
+                gui.swapchain_recreated(
+                    renderer.swapchain_image_size().into(),
+                    Some(renderer.swapchain_format()),
+                )
+                .expect("Gui can't draw to the new swapchain format");
                 // Render gui
                 let after_future =
                     gui.draw_on_image(before_future, renderer.swapchain_image_view());
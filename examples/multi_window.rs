@@ -0,0 +1,131 @@
+// Copyright (c) 2021 Okko Hakola
+// Licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT
+// license <LICENSE-MIT or https://opensource.org/licenses/MIT>,
+// at your option. All files in the project carrying such
+// notice may not be copied, modified, or distributed except
+// according to those terms.
+
+use std::collections::HashMap;
+
+use egui_winit_vulkano::{Gui, GuiConfig};
+use vulkano_util::{
+    context::{VulkanoConfig, VulkanoContext},
+    window::{VulkanoWindows, WindowDescriptor},
+};
+use winit::{
+    event::{Event, WindowEvent},
+    event_loop::{ControlFlow, EventLoop},
+};
+
+pub fn main() {
+    // Winit event loop
+    let event_loop = EventLoop::new();
+    // Vulkano context
+    let context = VulkanoContext::new(VulkanoConfig::default());
+    // Vulkano windows (create two)
+    let mut windows = VulkanoWindows::default();
+    let main_window = windows.create_window(
+        &event_loop,
+        &context,
+        &WindowDescriptor { title: "Main".to_string(), ..WindowDescriptor::default() },
+        |ci| {
+            ci.image_format = Some(vulkano::format::Format::B8G8R8A8_SRGB);
+            ci.min_image_count = ci.min_image_count.max(2);
+        },
+    );
+    let inspector_window = windows.create_window(
+        &event_loop,
+        &context,
+        &WindowDescriptor {
+            title: "Inspector".to_string(),
+            width: 400.0,
+            height: 600.0,
+            ..WindowDescriptor::default()
+        },
+        |ci| {
+            ci.image_format = Some(vulkano::format::Format::B8G8R8A8_SRGB);
+            ci.min_image_count = ci.min_image_count.max(2);
+        },
+    );
+    // One gui per window, each with its own input, scale factor & cursor state
+    let mut guis = HashMap::new();
+    for window_id in [main_window, inspector_window] {
+        let renderer = windows.get_renderer_mut(window_id).unwrap();
+        guis.insert(
+            window_id,
+            Gui::new(
+                &event_loop,
+                renderer.surface(),
+                renderer.graphics_queue(),
+                GuiConfig::default(),
+            ),
+        );
+    }
+    // Create gui state (shared between windows)
+    let mut name = "World".to_owned();
+    let mut clicks = 0;
+    event_loop.run(move |event, _, control_flow| match event {
+        Event::WindowEvent { event, window_id } => {
+            // Route events only to the gui of the window they belong to
+            let (renderer, gui) =
+                match (windows.get_renderer_mut(window_id), guis.get_mut(&window_id)) {
+                    (Some(renderer), Some(gui)) => (renderer, gui),
+                    _ => return,
+                };
+            // Update Egui integration so the UI works!
+            let _pass_events_to_game = !gui.update(&event);
+            match event {
+                WindowEvent::Resized(_) => {
+                    renderer.resize();
+                }
+                WindowEvent::ScaleFactorChanged { .. } => {
+                    renderer.resize();
+                }
+                WindowEvent::CloseRequested => {
+                    *control_flow = ControlFlow::Exit;
+                }
+                _ => (),
+            }
+        }
+        Event::RedrawRequested(window_id) => {
+            let (renderer, gui) =
+                match (windows.get_renderer_mut(window_id), guis.get_mut(&window_id)) {
+                    (Some(renderer), Some(gui)) => (renderer, gui),
+                    _ => return,
+                };
+            // Set immediate UI in redraw here
+            gui.immediate_ui(|gui| {
+                let ctx = gui.context();
+                egui::CentralPanel::default().show(&ctx, |ui| {
+                    if window_id == main_window {
+                        ui.heading(format!("Hello {}!", name));
+                        if ui.button("Click me").clicked() {
+                            clicks += 1;
+                        }
+                    } else {
+                        ui.heading("Inspector");
+                        ui.text_edit_singleline(&mut name);
+                        ui.label(format!("Clicks: {}", clicks));
+                    }
+                });
+            });
+            // Render UI
+            // Acquire swapchain future
+            let before_future = renderer.acquire().unwrap();
+            // Render gui
+            let after_future = gui.draw_on_image(before_future, renderer.swapchain_image_view());
+            // Present swapchain
+            renderer.present(after_future, true);
+        }
+        Event::MainEventsCleared => {
+            for window_id in [main_window, inspector_window] {
+                if let Some(renderer) = windows.get_renderer_mut(window_id) {
+                    renderer.window().request_redraw();
+                }
+            }
+        }
+        _ => (),
+    });
+}
@@ -62,12 +62,20 @@ pub fn main() {
         ci.image_usage = ImageUsage::TRANSFER_DST | ci.image_usage;
         ci.min_image_count = ci.min_image_count.max(2);
     });
+    // Use the highest sample count (up to 8) supported by the device
+    let supported_samples =
+        context.device().physical_device().properties().framebuffer_color_sample_counts;
+    let samples = [SampleCount::Sample8, SampleCount::Sample4, SampleCount::Sample2]
+        .iter()
+        .copied()
+        .find(|samples| supported_samples.contains_enum(*samples))
+        .unwrap_or(SampleCount::Sample1);
     // Create out gui pipeline
     let mut pipeline = MSAAPipeline::new(
         context.graphics_queue().clone(),
         windows.get_primary_renderer_mut().unwrap().swapchain_format(),
         context.memory_allocator(),
-        SampleCount::Sample4,
+        samples,
     );
     // Create gui subpass
     let mut gui = Gui::new_with_subpass(
@@ -78,7 +86,7 @@ pub fn main() {
         GuiConfig {
             preferred_format: Some(vulkano::format::Format::B8G8R8A8_SRGB),
             // Must match your pipeline's sample count
-            samples: SampleCount::Sample4,
+            samples,
             ..Default::default()
         },
     );
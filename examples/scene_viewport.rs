@@ -0,0 +1,395 @@
+// Copyright (c) 2021 Okko Hakola
+// Licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT
+// license <LICENSE-MIT or https://opensource.org/licenses/MIT>,
+// at your option. All files in the project carrying such
+// notice may not be copied, modified, or distributed except
+// according to those terms.
+
+// Renders a spinning cube into a region of an egui window, with the viewport & scissor from
+// `Gui::rect_to_viewport`. The scene is drawn in the subpass before the gui, and the window's
+// background is transparent so the scene shows through.
+use std::{
+    convert::{TryFrom, TryInto},
+    sync::Arc,
+    time::Instant,
+};
+
+use cgmath::{Deg, Matrix4, Point3, Rad, Vector3};
+use egui::{Color32, Frame, Sense, Window};
+use egui_winit_vulkano::{Gui, GuiConfig};
+use vulkano::{
+    buffer::{Buffer, BufferContents, BufferCreateInfo, BufferUsage, Subbuffer},
+    command_buffer::{
+        allocator::StandardCommandBufferAllocator, AutoCommandBufferBuilder,
+        CommandBufferInheritanceInfo, CommandBufferUsage, RenderPassBeginInfo, SubpassContents,
+    },
+    device::{Device, Queue},
+    format::Format,
+    image::{ImageAccess, SampleCount},
+    memory::allocator::{AllocationCreateInfo, MemoryUsage, StandardMemoryAllocator},
+    pipeline::{
+        graphics::{
+            input_assembly::InputAssemblyState,
+            rasterization::{CullMode, RasterizationState},
+            vertex_input::Vertex,
+            viewport::{Scissor, Viewport, ViewportState},
+        },
+        GraphicsPipeline, Pipeline,
+    },
+    render_pass::{Framebuffer, FramebufferCreateInfo, RenderPass, Subpass},
+    sync::GpuFuture,
+};
+use vulkano_util::{
+    context::{VulkanoConfig, VulkanoContext},
+    renderer::SwapchainImageView,
+    window::{VulkanoWindows, WindowDescriptor},
+};
+use winit::{
+    event::{Event, WindowEvent},
+    event_loop::{ControlFlow, EventLoop},
+};
+
+pub fn main() {
+    // Winit event loop
+    let event_loop = EventLoop::new();
+    // Vulkano context
+    let context = VulkanoContext::new(VulkanoConfig::default());
+    // Vulkano windows (create one)
+    let mut windows = VulkanoWindows::default();
+    windows.create_window(&event_loop, &context, &WindowDescriptor::default(), |ci| {
+        ci.image_format = Some(vulkano::format::Format::B8G8R8A8_SRGB);
+        ci.min_image_count = ci.min_image_count.max(2);
+    });
+    let mut scene = CubeScene::new(
+        context.graphics_queue().clone(),
+        windows.get_primary_renderer_mut().unwrap().swapchain_format(),
+        context.memory_allocator(),
+    );
+    // Create gui on the subpass after the scene
+    let mut gui = Gui::new_with_subpass(
+        &event_loop,
+        windows.get_primary_renderer_mut().unwrap().surface(),
+        windows.get_primary_renderer_mut().unwrap().graphics_queue(),
+        scene.gui_pass(),
+        GuiConfig {
+            preferred_format: Some(vulkano::format::Format::B8G8R8A8_SRGB),
+            ..Default::default()
+        },
+    );
+    let start = Instant::now();
+    // Last cursor position in physical pixels
+    let mut cursor_pos = None;
+    event_loop.run(move |event, _, control_flow| {
+        let renderer = windows.get_primary_renderer_mut().unwrap();
+        match event {
+            Event::WindowEvent { event, window_id } if window_id == renderer.window().id() => {
+                // Update Egui integration so the UI works!
+                let _pass_events_to_game = !gui.update(&event);
+                match event {
+                    WindowEvent::CursorMoved { position, .. } => {
+                        cursor_pos = Some([position.x as f32, position.y as f32]);
+                    }
+                    WindowEvent::Resized(_) => {
+                        renderer.resize();
+                    }
+                    WindowEvent::ScaleFactorChanged { .. } => {
+                        renderer.resize();
+                    }
+                    WindowEvent::CloseRequested => {
+                        *control_flow = ControlFlow::Exit;
+                    }
+                    _ => (),
+                }
+            }
+            Event::RedrawRequested(_) => {
+                // Where in the scene the pointer is, from the window's physical pixels
+                let pointer = cursor_pos.map(|pos| gui.physical_pos_to_egui_pos(pos));
+                let mut scene_rect = None;
+                gui.immediate_ui(|gui| {
+                    let ctx = gui.context();
+                    Window::new("Scene")
+                        .default_size([400.0, 300.0])
+                        .frame(Frame::window(&ctx.style()).fill(Color32::TRANSPARENT))
+                        .show(&ctx, |ui| {
+                            let label = match pointer {
+                                Some(pos) if ui.max_rect().contains(pos) => {
+                                    format!("Pointer at {:.0?}", pos - ui.max_rect().min)
+                                }
+                                _ => "Pointer outside".to_owned(),
+                            };
+                            ui.label(label);
+                            // Reserve the rest of the window for the scene
+                            let (rect, _response) =
+                                ui.allocate_exact_size(ui.available_size(), Sense::drag());
+                            scene_rect = Some(rect);
+                        });
+                });
+                // Render
+                // Acquire swapchain future
+                let before_future = renderer.acquire().unwrap();
+                let viewport = scene_rect.map(|rect| gui.rect_to_viewport(rect));
+                let after_future = scene.render(
+                    before_future,
+                    renderer.swapchain_image_view(),
+                    viewport,
+                    start.elapsed().as_secs_f32(),
+                    &mut gui,
+                );
+                // Present swapchain
+                renderer.present(after_future, true);
+            }
+            Event::MainEventsCleared => {
+                renderer.window().request_redraw();
+            }
+            _ => (),
+        }
+    });
+}
+
+struct CubeScene {
+    queue: Arc<Queue>,
+    render_pass: Arc<RenderPass>,
+    pipeline: Arc<GraphicsPipeline>,
+    subpass: Subpass,
+    vertex_buffer: Subbuffer<[CubeVertex]>,
+    command_buffer_allocator: StandardCommandBufferAllocator,
+}
+
+impl CubeScene {
+    pub fn new(
+        queue: Arc<Queue>,
+        image_format: vulkano::format::Format,
+        allocator: &StandardMemoryAllocator,
+    ) -> Self {
+        let render_pass = Self::create_render_pass(queue.device().clone(), image_format);
+        let (pipeline, subpass) =
+            Self::create_pipeline(queue.device().clone(), render_pass.clone());
+        let vertex_buffer = Buffer::from_iter(
+            allocator,
+            BufferCreateInfo { usage: BufferUsage::VERTEX_BUFFER, ..Default::default() },
+            AllocationCreateInfo { usage: MemoryUsage::Upload, ..Default::default() },
+            cube_vertices(),
+        )
+        .unwrap();
+        let command_buffer_allocator =
+            StandardCommandBufferAllocator::new(queue.device().clone(), Default::default());
+        Self { queue, render_pass, pipeline, subpass, vertex_buffer, command_buffer_allocator }
+    }
+
+    fn create_render_pass(device: Arc<Device>, format: Format) -> Arc<RenderPass> {
+        vulkano::ordered_passes_renderpass!(
+            device,
+            attachments: {
+                color: {
+                    load: Clear,
+                    store: Store,
+                    format: format,
+                    samples: SampleCount::Sample1,
+                }
+            },
+            passes: [
+                { color: [color], depth_stencil: {}, input: [] }, // Scene
+                { color: [color], depth_stencil: {}, input: [] } // Gui
+            ]
+        )
+        .unwrap()
+    }
+
+    fn gui_pass(&self) -> Subpass {
+        Subpass::from(self.render_pass.clone(), 1).unwrap()
+    }
+
+    fn create_pipeline(
+        device: Arc<Device>,
+        render_pass: Arc<RenderPass>,
+    ) -> (Arc<GraphicsPipeline>, Subpass) {
+        let vs = vs::load(device.clone()).expect("failed to create shader module");
+        let fs = fs::load(device.clone()).expect("failed to create shader module");
+        let subpass = Subpass::from(render_pass, 0).unwrap();
+        (
+            GraphicsPipeline::start()
+                .vertex_input_state(CubeVertex::per_vertex())
+                .vertex_shader(vs.entry_point("main").unwrap(), ())
+                .input_assembly_state(InputAssemblyState::new())
+                .fragment_shader(fs.entry_point("main").unwrap(), ())
+                .viewport_state(ViewportState::viewport_dynamic_scissor_dynamic(1))
+                // The cube is convex, so culling back faces replaces a depth buffer
+                .rasterization_state(RasterizationState::new().cull_mode(CullMode::Back))
+                .render_pass(subpass.clone())
+                .build(device)
+                .unwrap(),
+            subpass,
+        )
+    }
+
+    pub fn render(
+        &mut self,
+        before_future: Box<dyn GpuFuture>,
+        image: SwapchainImageView,
+        viewport: Option<([f32; 2], [f32; 2], Scissor)>,
+        time: f32,
+        gui: &mut Gui,
+    ) -> Box<dyn GpuFuture> {
+        let mut builder = AutoCommandBufferBuilder::primary(
+            &self.command_buffer_allocator,
+            self.queue.queue_family_index(),
+            CommandBufferUsage::OneTimeSubmit,
+        )
+        .unwrap();
+        let dimensions = image.image().dimensions().width_height();
+        let framebuffer = Framebuffer::new(self.render_pass.clone(), FramebufferCreateInfo {
+            attachments: vec![image],
+            ..Default::default()
+        })
+        .unwrap();
+        builder
+            .begin_render_pass(
+                RenderPassBeginInfo {
+                    clear_values: vec![Some([0.1, 0.1, 0.1, 1.0].into())],
+                    ..RenderPassBeginInfo::framebuffer(framebuffer)
+                },
+                SubpassContents::SecondaryCommandBuffers,
+            )
+            .unwrap();
+
+        // Draw the cube inside the rect reserved in the gui
+        let mut secondary_builder = AutoCommandBufferBuilder::secondary(
+            &self.command_buffer_allocator,
+            self.queue.queue_family_index(),
+            CommandBufferUsage::MultipleSubmit,
+            CommandBufferInheritanceInfo {
+                render_pass: Some(self.subpass.clone().into()),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        if let Some((origin, extent, scissor)) = viewport.filter(|(_, extent, _)| {
+            // A zero-sized viewport can't be drawn to, e.g. of a collapsed window
+            extent[0] > 0.0 && extent[1] > 0.0
+        }) {
+            let push_constants =
+                vs::PushConstants { mvp: cube_transform(time, extent[0] / extent[1]).into() };
+            secondary_builder
+                .bind_pipeline_graphics(self.pipeline.clone())
+                .set_viewport(0, vec![Viewport {
+                    origin,
+                    dimensions: extent,
+                    depth_range: 0.0..1.0,
+                }])
+                .set_scissor(0, vec![scissor])
+                .push_constants(self.pipeline.layout().clone(), 0, push_constants)
+                .bind_vertex_buffers(0, self.vertex_buffer.clone())
+                .draw(self.vertex_buffer.len() as u32, 1, 0, 0)
+                .unwrap();
+        }
+        let cb = secondary_builder.build().unwrap();
+        builder.execute_commands(cb).unwrap();
+
+        // Move on to next subpass for gui
+        builder.next_subpass(SubpassContents::SecondaryCommandBuffers).unwrap();
+        let cb = gui.draw_on_subpass_image(dimensions);
+        builder.execute_commands(cb).unwrap();
+
+        builder.end_render_pass().unwrap();
+        let command_buffer = builder.build().unwrap();
+        let after_future = before_future.then_execute(self.queue.clone(), command_buffer).unwrap();
+        after_future.boxed()
+    }
+}
+
+// Model view projection of the cube spinning over `time` seconds
+fn cube_transform(time: f32, aspect_ratio: f32) -> Matrix4<f32> {
+    let projection = cgmath::perspective(Deg(60.0), aspect_ratio, 0.1, 100.0);
+    let view = Matrix4::look_at_rh(
+        Point3::new(0.0, 0.0, 2.5),
+        Point3::new(0.0, 0.0, 0.0),
+        Vector3::new(0.0, 1.0, 0.0),
+    );
+    let model = Matrix4::from_angle_y(Rad(time)) * Matrix4::from_angle_x(Rad(time * 0.5));
+    projection * view * model
+}
+
+/// Normal, two axes & color of a cube face
+type Face = ([f32; 3], [f32; 3], [f32; 3], [f32; 4]);
+
+// Two counterclockwise triangles per face, seen from outside the cube, a color per face
+fn cube_vertices() -> Vec<CubeVertex> {
+    // Normal and two axes of each face, with `u × v = normal`
+    let faces: [Face; 6] = [
+        ([1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0], [0.9, 0.2, 0.2, 1.0]),
+        ([-1.0, 0.0, 0.0], [0.0, 0.0, 1.0], [0.0, 1.0, 0.0], [0.2, 0.9, 0.9, 1.0]),
+        ([0.0, 1.0, 0.0], [0.0, 0.0, 1.0], [1.0, 0.0, 0.0], [0.2, 0.9, 0.2, 1.0]),
+        ([0.0, -1.0, 0.0], [1.0, 0.0, 0.0], [0.0, 0.0, 1.0], [0.9, 0.2, 0.9, 1.0]),
+        ([0.0, 0.0, 1.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.2, 0.2, 0.9, 1.0]),
+        ([0.0, 0.0, -1.0], [0.0, 1.0, 0.0], [1.0, 0.0, 0.0], [0.9, 0.9, 0.2, 1.0]),
+    ];
+    let mut vertices = vec![];
+    for &(normal, u, v, color) in faces.iter() {
+        let corner = |su: f32, sv: f32| CubeVertex {
+            position: [
+                (normal[0] + su * u[0] + sv * v[0]) * 0.5,
+                (normal[1] + su * u[1] + sv * v[1]) * 0.5,
+                (normal[2] + su * u[2] + sv * v[2]) * 0.5,
+            ],
+            color,
+        };
+        vertices.extend([
+            corner(-1.0, -1.0),
+            corner(1.0, -1.0),
+            corner(1.0, 1.0),
+            corner(-1.0, -1.0),
+            corner(1.0, 1.0),
+            corner(-1.0, 1.0),
+        ]);
+    }
+    vertices
+}
+
+#[repr(C)]
+#[derive(BufferContents, Vertex)]
+struct CubeVertex {
+    #[format(R32G32B32_SFLOAT)]
+    position: [f32; 3],
+    #[format(R32G32B32A32_SFLOAT)]
+    color: [f32; 4],
+}
+
+mod vs {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        src: "
+#version 450
+layout(location = 0) in vec3 position;
+layout(location = 1) in vec4 color;
+
+layout(location = 0) out vec4 v_color;
+
+layout(push_constant) uniform PushConstants {
+    mat4 mvp;
+} push_constants;
+
+void main() {
+    gl_Position = push_constants.mvp * vec4(position, 1.0);
+    // Cgmath's projection is OpenGL's, whose y axis points up
+    gl_Position.y = -gl_Position.y;
+    v_color = color;
+}"
+    }
+}
+
+mod fs {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        src: "
+#version 450
+layout(location = 0) in vec4 v_color;
+
+layout(location = 0) out vec4 f_color;
+
+void main() {
+    f_color = v_color;
+}"
+    }
+}
@@ -0,0 +1,36 @@
+// Copyright (c) 2021 Okko Hakola
+// Licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT
+// license <LICENSE-MIT or https://opensource.org/licenses/MIT>,
+// at your option. All files in the project carrying such
+// notice may not be copied, modified, or distributed except
+// according to those terms.
+
+// Renders the egui demo without a window and saves it as a png
+use egui_winit_vulkano::{Gui, GuiConfig};
+use vulkano::sync::{self, GpuFuture};
+use vulkano_util::context::{VulkanoConfig, VulkanoContext};
+
+const SIZE: [u32; 2] = [1280, 720];
+
+pub fn main() {
+    // Vulkano context
+    let context = VulkanoContext::new(VulkanoConfig::default());
+    // Gui without a window, laid out for SIZE pixels
+    let mut gui = Gui::new_headless(context.graphics_queue().clone(), SIZE, GuiConfig::default());
+    let mut demo_app = egui_demo_lib::DemoWindows::default();
+    let mut future = sync::now(context.device().clone()).boxed();
+    // Egui needs a few frames to lay out windows whose size isn't known yet
+    for _ in 0..3 {
+        gui.immediate_ui(|gui| {
+            let ctx = gui.context();
+            demo_app.ui(&ctx);
+        });
+        let (after_future, _texture) = gui.draw_on_texture(future, SIZE);
+        future = after_future;
+    }
+    let (width, height, pixels) = gui.read_back_last_frame(future).unwrap();
+    image::save_buffer("screenshot.png", &pixels, width, height, image::ColorType::Rgba8).unwrap();
+    println!("Saved screenshot.png");
+}
@@ -12,11 +12,12 @@
 use std::sync::Arc;
 
 use egui::{Context, Visuals};
-use egui_winit_vulkano::{Gui, GuiConfig};
+use egui_winit_vulkano::{Gui, GuiConfig, TextureLoadOptions};
 use vulkano::{
     command_buffer::allocator::StandardCommandBufferAllocator,
     format::Format,
     image::{ImageUsage, StorageImage},
+    sampler::SamplerCreateInfo,
 };
 use vulkano_util::{
     context::{VulkanoConfig, VulkanoContext},
@@ -49,16 +50,21 @@ pub struct GuiState {
 impl GuiState {
     pub fn new(gui: &mut Gui, scene_image: DeviceImageView, scene_view_size: [u32; 2]) -> GuiState {
         // tree.png asset is from https://github.com/sotrh/learn-wgpu/tree/master/docs/beginner/tutorial5-textures
-        let image_texture_id1 = gui.register_user_image(
-            include_bytes!("./assets/tree.png"),
-            Format::R8G8B8A8_SRGB,
-            Default::default(),
-        );
-        let image_texture_id2 = gui.register_user_image(
-            include_bytes!("./assets/doge2.png"),
-            Format::R8G8B8A8_SRGB,
-            Default::default(),
-        );
+        let image_texture_id1 = gui
+            .try_register_user_image(
+                include_bytes!("./assets/tree.png"),
+                Format::R8G8B8A8_SRGB,
+                Default::default(),
+            )
+            .unwrap();
+        // Shown small, so it's mipmapped to not shimmer
+        let (image_texture_id2, _) = gui
+            .register_user_image_with_options(
+                include_bytes!("./assets/doge2.png"),
+                SamplerCreateInfo::simple_repeat_linear(),
+                TextureLoadOptions { mipmaps: true, ..Default::default() },
+            )
+            .unwrap();
 
         GuiState {
             show_texture_window1: true,
@@ -4,3 +4,11 @@ cargo run --example subpass --release
 cargo run --example demo_app --release
 cargo run --example paint_callback --release
 cargo run --example multisample --release
+cargo run --example multi_window --release
+cargo run --example persistence --features persistence --release
+cargo run --example screenshot --release
+cargo run --example gamepad --features gamepad --release
+cargo run --example frame_timings --release
+cargo run --example scene_viewport --release
+cargo run --example prepared_subpass --release
+cargo run --example custom_shader --release
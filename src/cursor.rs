@@ -0,0 +1,75 @@
+// Copyright (c) 2021 Okko Hakola
+// Licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT
+// license <LICENSE-MIT or https://opensource.org/licenses/MIT>,
+// at your option. All files in the project carrying such
+// notice may not be copied, modified, or distributed except
+// according to those terms.
+
+use egui::{epaint::Mesh, vec2, Color32, CursorIcon, Painter, Pos2, Rect, Shape, Stroke};
+
+/// Layer of the software cursor, above all windows, tooltips and debug painting of egui
+pub(crate) fn software_cursor_layer() -> egui::LayerId {
+    egui::LayerId::new(egui::Order::Debug, egui::Id::new("egui_winit_vulkano_software_cursor"))
+}
+
+/// Rect any icon painted at `pos` stays within, including its outline
+pub(crate) fn cursor_rect(pos: Pos2) -> Rect {
+    Rect::from_min_max(pos + vec2(-6.0, -10.0), pos + vec2(13.0, 20.0))
+}
+
+/// Paints `icon` with its hot spot at `pos`, see `Gui::set_software_cursor`. Text icons are
+/// drawn as an I-beam, hands as a pointing hand, `CursorIcon::None` as nothing and the rest as
+/// an arrow.
+pub(crate) fn paint_cursor(painter: &Painter, pos: Pos2, icon: CursorIcon) {
+    let (fill, stroke) = (Color32::WHITE, Stroke::new(1.0, Color32::BLACK));
+    match icon {
+        CursorIcon::None => (),
+        CursorIcon::Text | CursorIcon::VerticalText => paint_i_beam(painter, pos, fill, stroke),
+        CursorIcon::PointingHand | CursorIcon::Grab | CursorIcon::Grabbing => {
+            paint_hand(painter, pos, fill, stroke)
+        }
+        _ => paint_arrow(painter, pos, fill, stroke),
+    }
+}
+
+// Arrow with its tip at `pos`
+fn paint_arrow(painter: &Painter, pos: Pos2, fill: Color32, stroke: Stroke) {
+    let outline =
+        [(0.0, 0.0), (0.0, 16.0), (4.5, 12.0), (7.5, 18.5), (9.5, 17.5), (6.5, 11.5), (11.5, 11.5)]
+            .iter()
+            .map(|&(x, y)| pos + vec2(x, y))
+            .collect::<Vec<_>>();
+    // The outline is concave at the notch above the tail, so fill it with triangles
+    let mut mesh = Mesh::default();
+    for &point in &outline {
+        mesh.colored_vertex(point, fill);
+    }
+    for &[a, b, c] in [[0, 1, 2], [0, 2, 5], [0, 5, 6], [2, 3, 4], [2, 4, 5]].iter() {
+        mesh.add_triangle(a, b, c);
+    }
+    painter.add(Shape::mesh(mesh));
+    painter.add(Shape::closed_line(outline, stroke));
+}
+
+// I-beam centered on `pos`
+fn paint_i_beam(painter: &Painter, pos: Pos2, fill: Color32, stroke: Stroke) {
+    let segments =
+        [[(0.0, -8.0), (0.0, 8.0)], [(-3.0, -8.0), (3.0, -8.0)], [(-3.0, 8.0), (3.0, 8.0)]];
+    // Outlined in the fill color, so the beam shows on dark and light backgrounds
+    for &color_stroke in [Stroke::new(3.0, fill), stroke].iter() {
+        for &[(x0, y0), (x1, y1)] in segments.iter() {
+            painter.line_segment([pos + vec2(x0, y0), pos + vec2(x1, y1)], color_stroke);
+        }
+    }
+}
+
+// Hand with the tip of its index finger at `pos`
+fn paint_hand(painter: &Painter, pos: Pos2, fill: Color32, stroke: Stroke) {
+    let finger = Rect::from_min_max(pos + vec2(-1.5, 0.0), pos + vec2(1.5, 9.0));
+    let palm = Rect::from_min_max(pos + vec2(-5.0, 7.0), pos + vec2(5.0, 16.0));
+    painter.rect(finger, 1.5, fill, stroke);
+    // The palm covers the finger's base
+    painter.rect(palm, 2.0, fill, stroke);
+}
@@ -0,0 +1,116 @@
+// Copyright (c) 2021 Okko Hakola
+// Licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT
+// license <LICENSE-MIT or https://opensource.org/licenses/MIT>,
+// at your option. All files in the project carrying such
+// notice may not be copied, modified, or distributed except
+// according to those terms.
+
+use crate::Gui;
+
+/// Buttons used by `GamepadCursor`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GamepadButton {
+    /// Clicks at the cursor
+    Primary,
+    /// Moves focus to the previous widget (shift + tab)
+    DPadUp,
+    /// Moves focus to the next widget (tab)
+    DPadDown,
+    DPadLeft,
+    DPadRight,
+}
+
+/// Gamepad state read by `GamepadCursor` each frame, implement this for your input backend
+/// (e.g. gilrs)
+pub trait GamepadInput {
+    /// Stick moving the cursor, each axis from -1 to 1 with y pointing down
+    fn stick(&self) -> egui::Vec2;
+    /// Whether `button` is currently held
+    fn is_pressed(&self, button: GamepadButton) -> bool;
+}
+
+/// Drives egui with a gamepad through `Gui`'s input injection. The stick moves the pointer
+/// with acceleration, `Primary` clicks and the D-pad moves keyboard focus between widgets
+/// (up & down) or presses the arrow keys (left & right). Draws nothing itself, so show a cursor
+/// yourself if needed, e.g. at `pos`.
+#[derive(Debug, Clone)]
+pub struct GamepadCursor {
+    /// Pointer position in points
+    pub pos: egui::Pos2,
+    /// Pointer speed at full stick deflection in points per second
+    pub speed: f32,
+    /// Speed multiplier gained per second the stick is held, up to `max_acceleration`
+    pub acceleration: f32,
+    pub max_acceleration: f32,
+    /// Stick deflection below which the stick is ignored
+    pub dead_zone: f32,
+    held_time: f32,
+    pressed: [bool; 5],
+}
+
+const BUTTONS: [GamepadButton; 5] = [
+    GamepadButton::Primary,
+    GamepadButton::DPadUp,
+    GamepadButton::DPadDown,
+    GamepadButton::DPadLeft,
+    GamepadButton::DPadRight,
+];
+
+impl Default for GamepadCursor {
+    fn default() -> Self {
+        GamepadCursor {
+            pos: egui::Pos2::ZERO,
+            speed: 400.0,
+            acceleration: 2.0,
+            max_acceleration: 3.0,
+            dead_zone: 0.15,
+            held_time: 0.0,
+            pressed: [false; 5],
+        }
+    }
+}
+
+impl GamepadCursor {
+    /// Injects input for the next frame from `input`, `dt` seconds after the previous update.
+    /// Call this each frame before beginning it.
+    pub fn update(&mut self, gui: &mut Gui, input: &impl GamepadInput, dt: f32) {
+        let stick = input.stick();
+        if stick.length() > self.dead_zone {
+            self.held_time += dt;
+            let acceleration =
+                (1.0 + self.acceleration * self.held_time).min(self.max_acceleration);
+            let screen_rect = gui.context().screen_rect();
+            self.pos = screen_rect.clamp(
+                self.pos
+                    + stick.clamp(-egui::Vec2::splat(1.0), egui::Vec2::splat(1.0))
+                        * self.speed
+                        * acceleration
+                        * dt,
+            );
+            gui.inject_pointer_moved(self.pos);
+        } else {
+            self.held_time = 0.0;
+        }
+
+        for (i, &button) in BUTTONS.iter().enumerate() {
+            let pressed = input.is_pressed(button);
+            if pressed == self.pressed[i] {
+                continue;
+            }
+            self.pressed[i] = pressed;
+            let (key, modifiers) = match button {
+                GamepadButton::Primary => {
+                    gui.inject_pointer_button(self.pos, egui::PointerButton::Primary, pressed);
+                    continue;
+                }
+                GamepadButton::DPadUp => (egui::Key::Tab, egui::Modifiers::SHIFT),
+                GamepadButton::DPadDown => (egui::Key::Tab, egui::Modifiers::NONE),
+                GamepadButton::DPadLeft => (egui::Key::ArrowLeft, egui::Modifiers::NONE),
+                GamepadButton::DPadRight => (egui::Key::ArrowRight, egui::Modifiers::NONE),
+            };
+            gui.inject_key(key, pressed, modifiers);
+        }
+    }
+}
@@ -6,25 +6,53 @@
 // at your option. All files in the project carrying such
 // notice may not be copied, modified, or distributed except
 // according to those terms.
-use std::sync::Arc;
+use std::{
+    sync::{mpsc, Arc, Mutex, MutexGuard},
+    time::{Duration, Instant},
+};
 
+use ahash::AHashMap;
 use egui::{ClippedPrimitive, TexturesDelta};
 use egui_winit::winit::event_loop::EventLoopWindowTarget;
+use raw_window_handle::{HasRawDisplayHandle, RawDisplayHandle, WindowsDisplayHandle};
 use vulkano::{
-    command_buffer::SecondaryAutoCommandBuffer,
-    device::Queue,
-    format::{Format, NumericType},
-    image::{ImageViewAbstract, SampleCount},
-    render_pass::Subpass,
-    sampler::SamplerCreateInfo,
+    command_buffer::{
+        allocator::CommandBufferAllocator, AutoCommandBufferBuilder, SecondaryAutoCommandBuffer,
+    },
+    device::{Device, Queue},
+    format::{Format, FormatFeatures, NumericType},
+    image::{ImageAspects, ImageViewAbstract, SampleCount},
+    pipeline::graphics::viewport::Scissor,
+    render_pass::{RenderPass, Subpass},
+    sampler::{SamplerCreateInfo, SamplerMipmapMode, LOD_CLAMP_NONE},
     swapchain::Surface,
     sync::GpuFuture,
 };
-use winit::window::Window;
+use winit::{
+    dpi::PhysicalSize,
+    error::ExternalError,
+    event::{ElementState, KeyboardInput, MouseButton, VirtualKeyCode, WindowEvent},
+    monitor::MonitorHandle,
+    window::{Theme, Window},
+};
 
 use crate::{
-    renderer::{RenderResources, Renderer},
-    utils::{immutable_texture_from_bytes, immutable_texture_from_file},
+    cursor::{cursor_rect, paint_cursor, software_cursor_layer},
+    renderer::{
+        context_texture_id, rect_scissor, subpass_color_format, GuiShaders, MeshBufferSizes,
+        ParallelRecording, RenderResources, RenderStats, Renderer, SharedRenderer, TextureDraw,
+        UiViewport, DEFAULT_FRAMES_IN_FLIGHT,
+    },
+    textures::{RegisteredImage, TextureRegistry},
+    uploader::{TextureUploader, UploadToken},
+    utils::{
+        check_device_support, immutable_texture_from_bytes_deferred,
+        immutable_texture_from_file_deferred, immutable_texture_from_file_with_options_deferred,
+        immutable_textures_from_bytes_deferred, max_image_dimension, read_texture_to_vec,
+        Allocators, ClearMode, DeferredTexture, EguiVulkanoError, FontError, GuiCreationError,
+        ImageRegistrationError, ReadbackError, TextureLoadOptions, UserImageOptions, WorkerThreads,
+    },
+    winit_input::InputTranslation,
 };
 
 fn get_surface_image_format(
@@ -48,63 +76,432 @@ fn get_surface_image_format(
 pub struct GuiConfig {
     /// Preferred target image format. This should match the surface format. Sometimes the user
     /// may prefer linear color space rather than non linear. Hence the option. SRGB is selected by
-    /// default.
+    /// default. With UNORM formats egui's output is sRGB encoded in the shader. When using a
+    /// subpass, the format of its color attachment is used instead.
     pub preferred_format: Option<Format>,
-    /// Whether to render gui as overlay. Only relevant in the case of `Gui::new`, not when using
-    /// subpass. Determines whether the pipeline should clear the target image.
-    pub is_overlay: bool,
+    /// Whether the target image is cleared or its contents preserved (to render gui as overlay)
+    /// when the integration begins its own render pass. Only relevant in the case of `Gui::new`,
+    /// not when using subpass. Defaults to clearing to transparent black.
+    pub clear_mode: ClearMode,
     /// Multisample count. Defaults to 1. If you use more than 1, you'll have to ensure your
-    /// pipeline and target image matches that.
+    /// pipeline and target image matches that. `Gui::try_new` returns an error if the device
+    /// doesn't support the count for color attachments.
     pub samples: SampleCount,
+    /// Draw from device local vertex & index buffers, which are filled from host visible staging
+    /// buffers each frame. Can improve GPU throughput of large UIs on discrete GPUs, while the
+    /// default host visible buffers are better for integrated GPUs and small UIs. When drawing on
+    /// a subpass, the copies are submitted and waited on separately before recording the draws.
+    pub device_local_buffers: bool,
+    /// Number of frames drawn after `Gui::unregister_user_image` before the image is dropped.
+    /// Should be at least the number of frames your renderer keeps in flight, e.g. 3 when triple
+    /// buffering. Defaults to 2. Not used once you report finished frames with
+    /// `Gui::frame_completed`.
+    pub frames_in_flight: u64,
+    /// Record frames with many meshes (e.g. huge node graphs) on several threads, each chunk of
+    /// meshes into its own secondary command buffer, executed in paint order. Only applies to
+    /// the integration's own render pass and frames without paint callbacks. Threads are
+    /// started once and kept, still handing meshes to them only pays off for very large frames.
+    /// Disabled by default.
+    pub parallel_recording: Option<ParallelRecording>,
+    /// Sizes of the vertex & index buffers frames' meshes are written to, which are reused
+    /// across frames. They grow (doubling) to fit the largest frame drawn and never get smaller
+    /// than these sizes, so a larger floor avoids allocations while a large UI first shows up,
+    /// and a smaller one saves memory for small UIs. See `RenderStats::mesh_buffer_allocations`.
+    pub min_mesh_buffer_sizes: MeshBufferSizes,
+    /// Registry of the images of the gui's textures, e.g. shared with guis using separate
+    /// renderers so user texture ids are valid in all of them. A private registry if `None`.
+    pub texture_registry: Option<Arc<TextureRegistry>>,
 }
 
 impl Default for GuiConfig {
     fn default() -> Self {
-        GuiConfig { preferred_format: None, is_overlay: false, samples: SampleCount::Sample1 }
+        GuiConfig {
+            preferred_format: None,
+            clear_mode: ClearMode::Clear([0.0; 4]),
+            samples: SampleCount::Sample1,
+            device_local_buffers: false,
+            frames_in_flight: DEFAULT_FRAMES_IN_FLIGHT,
+            parallel_recording: None,
+            min_mesh_buffer_sizes: MeshBufferSizes::default(),
+            texture_registry: None,
+        }
+    }
+}
+
+type OpenUrlHandler = dyn Fn(&egui::output::OpenUrl) + Send;
+type AccessibilityHandler = dyn FnMut(&egui::output::OutputEvent) + Send;
+type RawInputHook = dyn FnMut(&mut egui::RawInput) + Send;
+type ShapeTransform = dyn FnMut(&mut Vec<egui::epaint::ClippedShape>) + Send;
+type EvictionHandler = dyn FnMut(egui::TextureId) + Send;
+type WindowCommandErrorHandler = dyn FnMut(&WindowCommand, &ExternalError) + Send;
+
+/// Input state of a gui without a window, replacing what egui-winit takes from the window
+struct Headless {
+    /// Screen size in pixels
+    screen_size: [u32; 2],
+    start_time: Instant,
+    max_texture_side: usize,
+}
+
+/// Display handle for egui-winit in headless mode. Egui-winit 0.22 can only create its state,
+/// which `Gui::egui_winit` holds, from a display handle, and only uses it to pick the Wayland
+/// clipboard. Headless input doesn't go through egui-winit, and as this isn't a Wayland display
+/// the unused clipboard falls back to the default provider.
+struct HeadlessDisplay;
+
+unsafe impl HasRawDisplayHandle for HeadlessDisplay {
+    fn raw_display_handle(&self) -> RawDisplayHandle {
+        RawDisplayHandle::Windows(WindowsDisplayHandle::empty())
+    }
+}
+
+/// Window action requested by the ui, e.g. of a borderless window with a title bar drawn by
+/// egui, see `Gui::queue_window_command`
+#[derive(Debug, Clone, PartialEq)]
+pub enum WindowCommand {
+    /// Moves the window with the cursor until the left mouse button is released. Queue it while
+    /// the button is held, e.g. when dragging the title bar starts.
+    StartDrag,
+    SetTitle(String),
+    SetMinimized(bool),
+    SetMaximized(bool),
+    /// Sets the size of the window's client area in physical pixels
+    SetInnerSize([u32; 2]),
+    /// Asks the app to close the window, see `Gui::close_requested`, as winit windows are closed
+    /// by dropping them
+    Close,
+}
+
+/// When IME is allowed on the window, which also shows the OS's on-screen keyboard on
+/// platforms that tie it to IME, see `Gui::set_virtual_keyboard_policy`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum VirtualKeyboardPolicy {
+    /// While a text field has focus, the default
+    #[default]
+    WhileEditing,
+    /// Never, e.g. for apps with their own on-screen keyboard. IME composition is disabled too.
+    Never,
+    /// Always, e.g. for kiosk apps that are only typed in
+    Always,
+}
+
+/// Key repeat done by the integration instead of the OS, see `Gui::set_key_repeat`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyRepeat {
+    /// Time a key is held before it starts repeating
+    pub delay: Duration,
+    /// Time between repeats
+    pub interval: Duration,
+}
+
+impl Default for KeyRepeat {
+    fn default() -> Self {
+        KeyRepeat { delay: Duration::from_millis(500), interval: Duration::from_millis(33) }
     }
 }
 
+/// CPU time spent in each phase of the last drawn frame, see `Gui::last_frame_timings`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FrameTimings {
+    /// Handling window events in `update` and gathering egui's input when the frame began
+    pub input: Duration,
+    /// Running the ui in `immediate_ui`'s layout function
+    pub ui_closure: Duration,
+    /// Ending the egui frame and tessellating its shapes, the worker thread's time for frames
+    /// ended with `Gui::end_frame_async`
+    pub tessellation: Duration,
+    /// Uploading egui's textures and writing vertices & indices, see `RenderStats::upload_time`
+    pub upload: Duration,
+    /// Recording & submitting the frame's commands, see `RenderStats::record_time`
+    pub record: Duration,
+}
+
+/// Meshes of the last drawn frame, see `Gui::redraw_last_frame`
+struct LastFrame {
+    clipped_meshes: Vec<ClippedPrimitive>,
+    pixels_per_point: f32,
+    /// Dimensions of the image the frame was drawn on
+    dimensions: [u32; 2],
+}
+
+/// Output of a frame split by layers, kept until all layers are drawn or the next frame begins,
+/// see `Gui::draw_layers_on_image`
+struct LayeredFrame {
+    /// Layers in paint order with their meshes, `None` once drawn
+    layers: Vec<(egui::LayerId, Option<Vec<ClippedPrimitive>>)>,
+    /// Uploaded with the first layers drawn
+    textures_delta: TexturesDelta,
+    pixels_per_point: f32,
+}
+
+/// Frame whose textures were uploaded by `Gui::prepare`, kept until `Gui::render` draws it
+struct PreparedFrame {
+    clipped_meshes: Vec<ClippedPrimitive>,
+    /// Textures freed by the frame, once it is drawn
+    freed_textures: Vec<egui::TextureId>,
+    pixels_per_point: f32,
+}
+
+/// Frame whose shapes a worker thread tessellates, see `Gui::end_frame_async`
+struct PendingTessellation {
+    /// Receives the meshes and the time tessellating took
+    worker: mpsc::Receiver<std::thread::Result<(Vec<ClippedPrimitive>, Duration)>>,
+    textures_delta: TexturesDelta,
+    /// Scale factor of the frame, which the meshes are tessellated for
+    pixels_per_point: f32,
+}
+
+/// Bytes a user image was registered from, kept to upload it again in `Gui::rebuild`
+struct RetainedImage {
+    bytes: Vec<u8>,
+    source: RetainedSource,
+    sampler_create_info: SamplerCreateInfo,
+}
+
+/// How the bytes of a `RetainedImage` are uploaded
+#[derive(Clone, Copy)]
+enum RetainedSource {
+    File {
+        format: Format,
+    },
+    FileWithOptions(TextureLoadOptions),
+    Bytes {
+        dimensions: [u32; 2],
+        format: Format,
+    },
+    /// A variant of `base` (see `Gui::register_user_image_variant`), without bytes of its own
+    Variant {
+        base: egui::TextureId,
+    },
+}
+
+/// Paint callback marking the end of a layer's shapes, as egui's output doesn't tell layers apart
+struct LayerMarker(egui::LayerId);
+
+/// Key held down while key repeat is done by the integration
+struct HeldKey {
+    keycode: VirtualKeyCode,
+    /// Press event replayed to egui-winit on each repeat
+    event: WindowEvent<'static>,
+    /// Character typed by the press, repeated with it
+    text: Option<char>,
+    next_repeat: Instant,
+}
+
+/// Most repeats replayed per frame, so a long frame doesn't flood egui with them
+const MAX_REPEATS_PER_FRAME: u32 = 4;
+/// Largest zoom factor applied per frame
+const MAX_ZOOM_DELTA: f32 = 2.0;
+
 pub struct Gui {
     pub egui_ctx: egui::Context,
     pub egui_winit: egui_winit::State,
-    renderer: Renderer,
-    surface: Arc<Surface>,
+    /// The integration's translation of events passed on to `egui_winit`, see `WinitInputState`
+    input: InputTranslation,
+    renderer: SharedRenderer,
+    /// Id of the gui's egui context in the renderer, see `new_shared`
+    context: u64,
+    allocators: Arc<Allocators>,
+    /// `None` in headless mode
+    surface: Option<Arc<Surface>>,
+    headless: Option<Headless>,
 
     shapes: Vec<egui::epaint::ClippedShape>,
     textures_delta: egui::TexturesDelta,
+    scale_factor_override: Option<f32>,
+    /// Multiplies the scale factor, see `set_ui_zoom`
+    ui_zoom: f32,
+    /// Whether visuals follow the window's theme, see `set_follow_system_theme`
+    follow_system_theme: bool,
+    /// Theme of the last `ThemeChanged` event
+    system_theme: Option<Theme>,
+    /// Dark & light visuals of `set_theme_visuals`, egui's defaults if `None`
+    theme_visuals: Option<Box<(egui::Visuals, egui::Visuals)>>,
+    /// Whether pointer events are passed to the app only, see `set_pointer_passthrough`
+    pointer_passthrough: bool,
+    /// Whether window input reaches egui, see `set_input_enabled`
+    input_enabled: bool,
+    /// Set when pointer passthrough ends, egui-winit's pointer position is stale until the next
+    /// `CursorMoved`
+    awaiting_cursor_moved: bool,
+    /// Mouse buttons held in the window, which keeps receiving the pointer while any is held
+    held_mouse_buttons: Vec<MouseButton>,
+    /// Set when the cursor left the window during a drag, egui sees the pointer as gone once the
+    /// drag ends
+    cursor_left_while_held: bool,
+    /// Whether egui's cursor icon is set on the window, see `set_cursor_management`
+    cursor_management: bool,
+    /// Last cursor icon handed to egui-winit, which only sets icons on the window when they change
+    handed_cursor_icon: Option<egui::CursorIcon>,
+    /// Whether egui draws the cursor, see `set_software_cursor`
+    software_cursor: bool,
+    /// Whether textured quads are snapped to pixels, see `set_pixel_snapping`
+    pixel_snapping: bool,
+    frame_begun: bool,
+    ime_allowed: bool,
+    virtual_keyboard_policy: VirtualKeyboardPolicy,
+    /// Whether the window has focus, IME is only allowed while it has
+    window_focused: bool,
+    open_url_handler: Option<Box<OpenUrlHandler>>,
+    accessibility_handler: Option<Box<AccessibilityHandler>>,
+    eviction_handler: Option<Box<EvictionHandler>>,
+    /// Window commands queued during the frame, run once it ends
+    window_commands: Vec<WindowCommand>,
+    window_command_error_handler: Option<Box<WindowCommandErrorHandler>>,
+    /// Whether the ui queued `WindowCommand::Close`
+    close_requested: bool,
+    /// Handle shared with the uploaders given out by `texture_uploader`, created on first use
+    uploader: Option<TextureUploader>,
+    /// Bytes of user images, kept while `set_retain_image_data` is enabled
+    retained_images: Option<AHashMap<egui::TextureId, RetainedImage>>,
+    raw_input_hook: Option<Box<RawInputHook>>,
+    shape_transform: Option<Box<ShapeTransform>>,
+    needs_repaint: bool,
+    repaint_deadline: Option<Instant>,
+    predicted_frame_time: Option<f32>,
+    /// Frame time of the refresh rate of the window's monitor, if known
+    monitor_frame_time: Option<f32>,
+    /// Monitor the window was last seen on, see `track_monitor`
+    monitor: Option<MonitorHandle>,
+    wants_pointer_input: bool,
+    wants_keyboard_input: bool,
+    pointer_over_ui: bool,
+    /// Regions the last ended frame painted, see `ui_covered_regions`
+    covered_regions: Vec<egui::Rect>,
+    last_output: egui::PlatformOutput,
+    /// Events injected since the last frame began, appended after winit's events
+    injected_events: Vec<egui::Event>,
+    injected_modifiers: egui::Modifiers,
+    /// Touchpad rotation since the last frame began, and during the current frame
+    pending_rotation: f32,
+    rotation_delta: f32,
+    suspended: bool,
+    /// Texture changes of frames skipped while suspended, applied on the next draw
+    suspended_textures_delta: egui::TexturesDelta,
+    ui_viewport: Option<UiViewport>,
+    last_frame: Option<LastFrame>,
+    /// Timings of the frame being built, and of the last drawn one
+    timings: FrameTimings,
+    last_timings: FrameTimings,
+    layered_frame: Option<LayeredFrame>,
+    prepared_frame: Option<PreparedFrame>,
+    pending_tessellation: Option<PendingTessellation>,
+    /// Thread tessellating frames of `end_frame_async`, started by its first call
+    tessellation_worker: Option<WorkerThreads>,
+    /// Last cursor position in physical pixels
+    cursor_pos: Option<(f64, f64)>,
+    /// Font definitions of `add_font` & `remove_font`, starting from egui's defaults
+    font_definitions: Option<egui::FontDefinitions>,
+    key_repeat: Option<KeyRepeat>,
+    held_key: Option<HeldKey>,
+    /// Whether the character of a swallowed OS repeat is still to come
+    skip_repeated_char: bool,
 }
 
+// Guis are moved to render threads, so what they hold must stay `Send`
+const _: fn() = || {
+    fn assert_send<T: Send>() {}
+    assert_send::<Gui>();
+};
+
 impl Gui {
     /// Creates new Egui to Vulkano integration by setting the necessary parameters
     /// This is to be called once we have access to vulkano_win's winit window surface
     /// and gfx queue. Created with this, the renderer will own a render pass which is useful to e.g. place your render pass' images
     /// onto egui windows
+    ///
+    /// Panics if the device lacks what the integration needs, see `try_new`.
     pub fn new<T>(
         event_loop: &EventLoopWindowTarget<T>,
         surface: Arc<Surface>,
         gfx_queue: Arc<Queue>,
         config: GuiConfig,
     ) -> Gui {
+        Self::try_new(event_loop, surface, gfx_queue, config)
+            .unwrap_or_else(|err| panic!("Failed to create gui: {}", err))
+    }
+
+    /// Like `new`, but returns an error instead of panicking if the device or queue lacks what
+    /// the integration needs, e.g. a queue without graphics support, a format egui can't blend
+    /// into or an unsupported `GuiConfig::samples`, rather than failing later when drawing
+    pub fn try_new<T>(
+        event_loop: &EventLoopWindowTarget<T>,
+        surface: Arc<Surface>,
+        gfx_queue: Arc<Queue>,
+        config: GuiConfig,
+    ) -> Result<Gui, GuiCreationError> {
+        Self::new_with_display(event_loop, surface, gfx_queue, config)
+    }
+
+    /// Creates the integration with the least setup: it owns a render pass that draws on top of
+    /// the image's contents in `output_format`, e.g. your swapchain's format. The window's size
+    /// and scale factor are read from `surface`, and no event loop is needed. Draw with
+    /// `draw_on_image`. Use `new` or `new_with_subpass` for more control.
+    ///
+    /// Returns an error if `output_format` can't be rendered to as a color attachment, or if the
+    /// device lacks what the integration needs (see `try_new`).
+    pub fn new_simple(
+        surface: &Arc<Surface>,
+        gfx_queue: Arc<Queue>,
+        output_format: Format,
+    ) -> Result<Gui, GuiCreationError> {
+        let renderable = output_format.aspects().intersects(ImageAspects::COLOR)
+            && gfx_queue
+                .device()
+                .physical_device()
+                .format_properties(output_format)
+                .map(|properties| {
+                    properties.optimal_tiling_features.intersects(FormatFeatures::COLOR_ATTACHMENT)
+                })
+                .unwrap_or(false);
+        if !renderable {
+            return Err(GuiCreationError::NotColorRenderable(output_format));
+        }
+        Self::new_with_display(surface_window(surface), surface.clone(), gfx_queue, GuiConfig {
+            preferred_format: Some(output_format),
+            clear_mode: ClearMode::Preserve,
+            ..Default::default()
+        })
+    }
+
+    // Same as `new`, egui-winit only needs a display handle, e.g. of the event loop or window
+    fn new_with_display(
+        display: &dyn HasRawDisplayHandle,
+        surface: Arc<Surface>,
+        gfx_queue: Arc<Queue>,
+        config: GuiConfig,
+    ) -> Result<Gui, GuiCreationError> {
         // Pick preferred format if provided, otherwise use the default one
         let format = get_surface_image_format(&surface, config.preferred_format, &gfx_queue);
+        check_device_support(&gfx_queue, format, config.samples, 1)?;
         let max_texture_side =
             gfx_queue.device().physical_device().properties().max_image_array_layers as usize;
-        let renderer =
-            Renderer::new_with_render_pass(gfx_queue, format, config.is_overlay, config.samples);
-        let mut egui_winit = egui_winit::State::new(event_loop);
+        let mut renderer = Renderer::new_with_render_pass(
+            gfx_queue,
+            format,
+            config.clear_mode,
+            config.samples,
+            config.device_local_buffers,
+        );
+        renderer.set_frames_in_flight(config.frames_in_flight);
+        renderer.set_parallel_recording(config.parallel_recording);
+        renderer.set_min_mesh_buffer_sizes(config.min_mesh_buffer_sizes);
+        if let Some(texture_registry) = config.texture_registry {
+            renderer.set_texture_registry(texture_registry);
+        }
+        let mut egui_winit = egui_winit::State::new(display);
         egui_winit.set_max_texture_side(max_texture_side);
         egui_winit.set_pixels_per_point(surface_window(&surface).scale_factor() as f32);
-        Gui {
-            egui_ctx: Default::default(),
-            egui_winit,
-            renderer,
-            surface,
-            shapes: vec![],
-            textures_delta: Default::default(),
-        }
+        let allocators = renderer.shared_allocators();
+        let renderer = SharedRenderer(Arc::new(Mutex::new(renderer)));
+        Ok(Self::from_parts(egui_winit, renderer, 0, allocators, Some(surface), None))
     }
 
-    /// Same as `new` but instead of integration owning a render pass, egui renders on your subpass
+    /// Same as `new` but instead of integration owning a render pass, egui renders on your subpass.
+    /// The subpass does not need a depth attachment; if it has one, egui ignores it.
+    /// With multiple color attachments, egui draws only to the first one.
+    ///
+    /// Panics if the device lacks what the integration needs, see `try_new_with_subpass`.
     pub fn new_with_subpass<T>(
         event_loop: &EventLoopWindowTarget<T>,
         surface: Arc<Surface>,
@@ -112,189 +509,3076 @@ impl Gui {
         subpass: Subpass,
         config: GuiConfig,
     ) -> Gui {
+        Self::try_new_with_subpass(event_loop, surface, gfx_queue, subpass, config)
+            .unwrap_or_else(|err| panic!("Failed to create gui: {}", err))
+    }
+
+    /// Like `new_with_subpass`, but returns an error instead of panicking if the device or queue
+    /// lacks what the integration needs, see `try_new`. The format of the subpass' first color
+    /// attachment must support blending.
+    pub fn try_new_with_subpass<T>(
+        event_loop: &EventLoopWindowTarget<T>,
+        surface: Arc<Surface>,
+        gfx_queue: Arc<Queue>,
+        subpass: Subpass,
+        config: GuiConfig,
+    ) -> Result<Gui, GuiCreationError> {
         // Pick preferred format if provided, otherwise use the default one
         let format = get_surface_image_format(&surface, config.preferred_format, &gfx_queue);
+        check_device_support(
+            &gfx_queue,
+            subpass_color_format(&subpass).unwrap_or(format),
+            subpass.num_samples().unwrap_or(SampleCount::Sample1),
+            subpass.num_color_attachments() as usize,
+        )?;
         let max_texture_side =
             gfx_queue.device().physical_device().properties().max_image_array_layers as usize;
-        let renderer = Renderer::new_with_subpass(gfx_queue, format, subpass);
+        let mut renderer =
+            Renderer::new_with_subpass(gfx_queue, format, subpass, config.device_local_buffers);
+        renderer.set_frames_in_flight(config.frames_in_flight);
+        renderer.set_parallel_recording(config.parallel_recording);
+        renderer.set_min_mesh_buffer_sizes(config.min_mesh_buffer_sizes);
+        if let Some(texture_registry) = config.texture_registry {
+            renderer.set_texture_registry(texture_registry);
+        }
         let mut egui_winit = egui_winit::State::new(event_loop);
         egui_winit.set_max_texture_side(max_texture_side);
         egui_winit.set_pixels_per_point(surface_window(&surface).scale_factor() as f32);
+        let allocators = renderer.shared_allocators();
+        let renderer = SharedRenderer(Arc::new(Mutex::new(renderer)));
+        Ok(Self::from_parts(egui_winit, renderer, 0, allocators, Some(surface), None))
+    }
+
+    /// Same as `new`, but uploads textures (font atlas & user images) on `transfer_queue`, e.g. a
+    /// queue of a dedicated transfer queue family, so they overlap with graphics work. The
+    /// graphics queue waits on a semaphore before sampling them. Images generating mipmaps are
+    /// still uploaded on `gfx_queue`, as that requires blits. If both are the same queue, this is
+    /// the same as `new`.
+    pub fn new_with_queues<T>(
+        event_loop: &EventLoopWindowTarget<T>,
+        surface: Arc<Surface>,
+        gfx_queue: Arc<Queue>,
+        transfer_queue: Arc<Queue>,
+        config: GuiConfig,
+    ) -> Gui {
+        let gui = Self::new(event_loop, surface, gfx_queue, config);
+        gui.renderer().set_transfer_queue(transfer_queue);
+        gui
+    }
+
+    /// Creates the integration without a window, e.g. to render ui on a server or in tests with
+    /// `draw_on_texture` or `draw_on_image`. The screen is `screen_size` pixels (see
+    /// `set_screen_size`) at a scale factor of 1 (see `set_scale_factor`) unless changed, and
+    /// input comes from the `inject_*` functions. Cursor icons, IME, clipboard and
+    /// opening urls (unless handled with `set_open_url_handler`) are ignored.
+    ///
+    /// The image format is `GuiConfig::preferred_format`, or `R8G8B8A8_SRGB` if not set.
+    pub fn new_headless(gfx_queue: Arc<Queue>, screen_size: [u32; 2], config: GuiConfig) -> Gui {
+        let format = config.preferred_format.unwrap_or(Format::R8G8B8A8_SRGB);
+        let max_texture_side =
+            gfx_queue.device().physical_device().properties().max_image_array_layers as usize;
+        let mut renderer = Renderer::new_with_render_pass(
+            gfx_queue,
+            format,
+            config.clear_mode,
+            config.samples,
+            config.device_local_buffers,
+        );
+        renderer.set_frames_in_flight(config.frames_in_flight);
+        renderer.set_parallel_recording(config.parallel_recording);
+        renderer.set_min_mesh_buffer_sizes(config.min_mesh_buffer_sizes);
+        if let Some(texture_registry) = config.texture_registry {
+            renderer.set_texture_registry(texture_registry);
+        }
+        Self::new_headless_with_renderer(
+            SharedRenderer(Arc::new(Mutex::new(renderer))),
+            0,
+            screen_size,
+            max_texture_side,
+        )
+    }
+
+    /// Creates another gui drawing with the renderer of `renderer`, get it with
+    /// `Gui::shared_renderer`. The gui has its own egui context (memory, input & frames) and font
+    /// atlas, while the pipeline, buffer pools and user textures are shared, so user texture ids
+    /// are valid in all guis of the renderer. Each gui draws on its own offscreen target with
+    /// `draw_on_texture`, so both can be drawn in the same frame.
+    ///
+    /// The gui is headless (see `new_headless`), e.g. for a diegetic ui drawn onto a texture in
+    /// your scene with input from the `inject_*` functions. The screen is `screen_size` pixels at
+    /// `scale_factor`.
+    pub fn new_shared(renderer: SharedRenderer, screen_size: [u32; 2], scale_factor: f32) -> Gui {
+        let (context, max_texture_side) = {
+            let mut shared = renderer.0.lock().unwrap();
+            (shared.add_context(), max_image_dimension(&shared.queue()) as usize)
+        };
+        let mut gui =
+            Self::new_headless_with_renderer(renderer, context, screen_size, max_texture_side);
+        gui.set_scale_factor(Some(scale_factor as f64));
+        gui
+    }
+
+    fn new_headless_with_renderer(
+        renderer: SharedRenderer,
+        context: u64,
+        screen_size: [u32; 2],
+        max_texture_side: usize,
+    ) -> Gui {
+        let allocators = renderer.0.lock().unwrap().shared_allocators();
+        let egui_winit = egui_winit::State::new(&HeadlessDisplay);
+        Self::from_parts(
+            egui_winit,
+            renderer,
+            context,
+            allocators,
+            None,
+            Some(Headless { screen_size, start_time: Instant::now(), max_texture_side }),
+        )
+    }
+
+    // Creates a gui drawing with context `context` of `renderer`, either for the window of
+    // `surface` or `headless`
+    fn from_parts(
+        egui_winit: egui_winit::State,
+        renderer: SharedRenderer,
+        context: u64,
+        allocators: Arc<Allocators>,
+        surface: Option<Arc<Surface>>,
+        headless: Option<Headless>,
+    ) -> Gui {
+        let window = surface.as_deref().map(surface_window);
+        let monitor_frame_time = window.and_then(monitor_frame_time);
+        let monitor = window.and_then(|window| window.current_monitor());
         Gui {
             egui_ctx: Default::default(),
             egui_winit,
+            input: InputTranslation::new(),
             renderer,
+            context,
+            allocators,
             surface,
+            headless,
             shapes: vec![],
             textures_delta: Default::default(),
+            scale_factor_override: None,
+            ui_zoom: 1.0,
+            follow_system_theme: false,
+            system_theme: None,
+            theme_visuals: None,
+            pointer_passthrough: false,
+            input_enabled: true,
+            awaiting_cursor_moved: false,
+            held_mouse_buttons: vec![],
+            cursor_left_while_held: false,
+            cursor_management: true,
+            handed_cursor_icon: None,
+            software_cursor: false,
+            frame_begun: false,
+            ime_allowed: false,
+            pixel_snapping: false,
+            virtual_keyboard_policy: VirtualKeyboardPolicy::default(),
+            window_focused: true,
+            open_url_handler: None,
+            accessibility_handler: None,
+            eviction_handler: None,
+            window_commands: vec![],
+            window_command_error_handler: None,
+            close_requested: false,
+            uploader: None,
+            retained_images: None,
+            raw_input_hook: None,
+            shape_transform: None,
+            needs_repaint: true,
+            repaint_deadline: None,
+            predicted_frame_time: None,
+            monitor_frame_time,
+            monitor,
+            wants_pointer_input: false,
+            wants_keyboard_input: false,
+            pointer_over_ui: false,
+            covered_regions: vec![],
+            last_output: Default::default(),
+            injected_events: vec![],
+            injected_modifiers: egui::Modifiers::NONE,
+            pending_rotation: 0.0,
+            rotation_delta: 0.0,
+            suspended: false,
+            suspended_textures_delta: Default::default(),
+            ui_viewport: None,
+            last_frame: None,
+            timings: FrameTimings::default(),
+            last_timings: FrameTimings::default(),
+            layered_frame: None,
+            prepared_frame: None,
+            pending_tessellation: None,
+            tessellation_worker: None,
+            cursor_pos: None,
+            font_definitions: None,
+            key_repeat: None,
+            held_key: None,
+            skip_repeated_char: false,
         }
     }
 
-    /// Returns a set of resources used to construct the render pipeline. These can be reused
-    /// to create additional pipelines and buffers to be rendered in a `PaintCallback`.
-    pub fn render_resources(&self) -> RenderResources {
-        self.renderer.render_resources()
+    /// Sets the screen size in pixels egui lays out the ui for in headless mode. Panics if the
+    /// integration has a window, whose size is used instead.
+    pub fn set_screen_size(&mut self, size_in_pixels: [u32; 2]) {
+        match &mut self.headless {
+            Some(headless) => headless.screen_size = size_in_pixels,
+            None => {
+                panic!("Screen size can only be set in headless mode, the window's size is used")
+            }
+        }
     }
 
-    /// Updates context state by winit window event.
-    /// Returns `true` if egui wants exclusive use of this event
-    /// (e.g. a mouse click on an egui window, or entering text into a text field).
-    /// For instance, if you use egui for a game, you want to first call this
-    /// and only when this returns `false` pass on the events to your game.
+    /// Call this right after recreating the swapchain, e.g. on resize or when the window moves
+    /// to a display with another surface format. Drops cached framebuffers of the old swapchain
+    /// images, and recreates the render pass & pipeline if `new_format` differs from the format
+    /// the integration draws to. Cheap if nothing changed.
     ///
-    /// Note that egui uses `tab` to move focus between elements, so this will always return `true` for tabs.
-    pub fn update(&mut self, winit_event: &winit::event::WindowEvent<'_>) -> bool {
-        self.egui_winit.on_event(&self.egui_ctx, winit_event).consumed
+    /// The window's size is taken each frame, so `new_size` only sets the screen size in headless
+    /// mode. Returns an error if the device can't draw to `new_format` (see `try_new`), in which
+    /// case the integration keeps drawing with the previous format.
+    ///
+    /// Integrations created with `new_with_subpass` draw to your render pass, so `new_format` is
+    /// ignored. Pass your render pass' new subpass to `set_subpass` instead.
+    pub fn swapchain_recreated(
+        &mut self,
+        new_size: PhysicalSize<u32>,
+        new_format: Option<Format>,
+    ) -> Result<(), GuiCreationError> {
+        let new_size = [new_size.width, new_size.height];
+        if let Some(headless) = &mut self.headless {
+            headless.screen_size = new_size;
+        }
+        self.renderer().swapchain_recreated(new_size, new_format)
     }
 
-    /// Begins Egui frame & determines what will be drawn later. This must be called before draw, and after `update` (winit event).
-    pub fn immediate_ui(&mut self, layout_function: impl FnOnce(&mut Self)) {
-        let raw_input = self.egui_winit.take_egui_input(surface_window(&self.surface));
-        self.egui_ctx.begin_frame(raw_input);
-        // Render Egui
-        layout_function(self);
+    /// Returns whether the integration was created without a window, see `new_headless`
+    pub fn is_headless(&self) -> bool {
+        self.headless.is_some()
     }
 
-    /// If you wish to better control when to begin frame, do so by calling this function
-    /// (Finish by drawing)
-    pub fn begin_frame(&mut self) {
-        let raw_input = self.egui_winit.take_egui_input(surface_window(&self.surface));
-        self.egui_ctx.begin_frame(raw_input);
+    /// Call this on `Event::Suspended`, when the surface is destroyed (e.g. on Android). Held
+    /// pointer buttons and keys are released and the modifiers cleared, so none are stuck down
+    /// after resuming, and framebuffers of the surface's images are dropped. Until `resume`,
+    /// `draw_on_image` only ends the frame and returns `before_future`; subpass draws record
+    /// nothing as if the window was minimized. Drawing on textures keeps working.
+    pub fn suspend(&mut self) {
+        if self.suspended {
+            return;
+        }
+        self.suspended = true;
+        self.release_pointer();
+        self.release_keys();
+        // Clears the modifiers
+        let _ =
+            self.input.on_event(&mut self.egui_winit, &self.egui_ctx, &WindowEvent::Focused(false));
+        self.pending_rotation = 0.0;
+        self.renderer().release_targets();
     }
 
-    /// Renders ui on `final_image` & Updates cursor icon
-    /// Finishes Egui frame
-    /// - `before_future` = Vulkano's GpuFuture
-    /// - `final_image` = Vulkano's image (render target)
-    pub fn draw_on_image<F>(
-        &mut self,
-        before_future: F,
-        final_image: Arc<dyn ImageViewAbstract + 'static>,
-    ) -> Box<dyn GpuFuture>
-    where
-        F: GpuFuture + 'static,
-    {
-        if !self.renderer.has_renderpass() {
-            panic!(
-                "Gui integration has been created with subpass, use `draw_on_subpass_image` \
-                 instead"
-            )
+    /// Call this on `Event::Resumed` after a `suspend`, with the recreated surface. The scale
+    /// factor is taken from its window unless set with `set_scale_factor`, and its size is read
+    /// each frame as usual. Panics in headless mode.
+    pub fn resume(&mut self, surface: Arc<Surface>) {
+        if self.headless.is_some() {
+            panic!("A headless integration has no surface to resume")
         }
+        let window_scale_factor = surface_window(&surface).scale_factor() as f32;
+        self.set_pixels_per_point(window_scale_factor);
+        self.monitor_frame_time = monitor_frame_time(surface_window(&surface));
+        self.monitor = surface_window(&surface).current_monitor();
+        self.surface = Some(surface);
+        // The new window starts without IME allowed
+        self.ime_allowed = false;
+        self.suspended = false;
+        self.needs_repaint = true;
+    }
 
-        let (clipped_meshes, textures_delta) = self.extract_draw_data_at_frame_end();
+    // Releases the keys egui sees as held, whose release the window may never report
+    fn release_keys(&mut self) {
+        let keys = self.egui_ctx.input(|i| i.keys_down.clone());
+        for key in keys {
+            self.inject_event(egui::Event::Key {
+                key,
+                pressed: false,
+                repeat: false,
+                modifiers: egui::Modifiers::NONE,
+            });
+        }
+        self.injected_modifiers = egui::Modifiers::NONE;
+        self.held_key = None;
+        self.skip_repeated_char = false;
+    }
 
-        self.renderer.draw_on_image(
-            &clipped_meshes,
-            &textures_delta,
-            self.egui_winit.pixels_per_point(),
-            before_future,
-            final_image,
-        )
+    // Injects releases of the pointer buttons egui has down, followed by the pointer leaving
+    fn release_pointer(&mut self) {
+        let (pointer_pos, buttons) = self.egui_ctx.input(|i| {
+            let buttons: Vec<_> = [
+                egui::PointerButton::Primary,
+                egui::PointerButton::Secondary,
+                egui::PointerButton::Middle,
+                egui::PointerButton::Extra1,
+                egui::PointerButton::Extra2,
+            ]
+            .iter()
+            .copied()
+            .filter(|&button| i.pointer.button_down(button))
+            .collect();
+            (i.pointer.interact_pos(), buttons)
+        });
+        let pos = pointer_pos.unwrap_or(egui::Pos2::ZERO);
+        for button in buttons {
+            self.inject_event(egui::Event::PointerButton {
+                pos,
+                button,
+                pressed: false,
+                modifiers: egui::Modifiers::NONE,
+            });
+        }
+        self.inject_event(egui::Event::PointerGone);
     }
 
-    /// Creates commands for rendering ui on subpass' image and returns the command buffer for execution on your side
-    /// - Finishes Egui frame
-    /// - You must execute the secondary command buffer yourself
-    pub fn draw_on_subpass_image(
-        &mut self,
-        image_dimensions: [u32; 2],
-    ) -> SecondaryAutoCommandBuffer {
-        if self.renderer.has_renderpass() {
-            panic!(
-                "Gui integration has been created with its own render pass, use `draw_on_image` \
-                 instead"
-            )
+    /// Passes pointer events (cursor, mouse buttons & wheel, touch and touchpad gestures) only to
+    /// the app while enabled, e.g. while the cursor is grabbed for an FPS-style camera and winit
+    /// keeps reporting it at the window's center. Egui sees the pointer as gone and held buttons
+    /// as released, and `update` returns false for pointer events. Keyboard events still reach
+    /// egui. After disabling, mouse buttons & wheel are ignored until the next `CursorMoved`
+    /// gives egui the pointer's actual position.
+    pub fn set_pointer_passthrough(&mut self, enabled: bool) {
+        if enabled == self.pointer_passthrough {
+            return;
+        }
+        self.pointer_passthrough = enabled;
+        if enabled {
+            self.release_pointer();
+            self.pending_rotation = 0.0;
+        } else {
+            self.awaiting_cursor_moved = true;
         }
+        self.needs_repaint = true;
+    }
 
-        let (clipped_meshes, textures_delta) = self.extract_draw_data_at_frame_end();
+    /// Returns whether pointer events are passed to the app only, see `set_pointer_passthrough`
+    pub fn pointer_passthrough(&self) -> bool {
+        self.pointer_passthrough
+    }
 
-        self.renderer.draw_on_subpass_image(
-            &clipped_meshes,
-            &textures_delta,
-            self.egui_winit.pixels_per_point(),
-            image_dimensions,
-        )
+    /// Enables or disables all window input to egui, enabled by default. While disabled the ui
+    /// is still drawn, e.g. a purely informational HUD, but pointer, keyboard, text and IME
+    /// events only reach the app: `update` returns false for them. Egui sees the pointer as
+    /// gone, held buttons & keys as released and loses keyboard focus, so no hover or focus is
+    /// left behind. Like after pointer passthrough, mouse buttons & wheel are ignored after
+    /// enabling until the next `CursorMoved` gives egui the pointer's actual position.
+    pub fn set_input_enabled(&mut self, enabled: bool) {
+        if enabled == self.input_enabled {
+            return;
+        }
+        self.input_enabled = enabled;
+        if enabled {
+            self.awaiting_cursor_moved = true;
+        } else {
+            self.release_pointer();
+            self.release_keys();
+            self.pending_rotation = 0.0;
+            self.egui_ctx.memory_mut(|memory| {
+                if let Some(focused) = memory.focus() {
+                    memory.surrender_focus(focused);
+                }
+            });
+        }
+        self.needs_repaint = true;
     }
 
-    fn extract_draw_data_at_frame_end(&mut self) -> (Vec<ClippedPrimitive>, TexturesDelta) {
-        self.end_frame();
-        let shapes = std::mem::take(&mut self.shapes);
-        let textures_delta = std::mem::take(&mut self.textures_delta);
-        let clipped_meshes = self.egui_ctx.tessellate(shapes);
-        (clipped_meshes, textures_delta)
+    /// Returns whether window input reaches egui, see `set_input_enabled`
+    pub fn input_enabled(&self) -> bool {
+        self.input_enabled
     }
 
-    fn end_frame(&mut self) {
-        let egui::FullOutput { platform_output, repaint_after: _r, textures_delta, shapes } =
-            self.egui_ctx.end_frame();
+    /// Sets whether egui's cursor icon is set on the window, enabled by default. Disable it when
+    /// you manage the cursor yourself (e.g. a software cursor, or icons for your own states) and
+    /// apply `last_output().cursor_icon` when appropriate. Clipboard, urls and IME keep working.
+    ///
+    /// Egui-winit handles copied text & urls together with the cursor, so on frames with those
+    /// it gets the icon it got last. If the pointer left the window since, that icon is set again.
+    pub fn set_cursor_management(&mut self, enabled: bool) {
+        self.cursor_management = enabled;
+    }
 
-        self.egui_winit.handle_platform_output(
-            surface_window(&self.surface),
-            &self.egui_ctx,
-            platform_output,
-        );
-        self.shapes = shapes;
-        self.textures_delta = textures_delta;
+    /// Moves axis-aligned textured quads, e.g. glyphs, to start on whole physical pixels while
+    /// enabled, for crisper small text at 100% scale. Quads keep their size, and anti-aliased
+    /// shapes are left as is. Only done when pixels per point is an integer, so layouts at
+    /// fractional scale factors aren't distorted. Disabled by default.
+    pub fn set_pixel_snapping(&mut self, enabled: bool) {
+        self.pixel_snapping = enabled;
+        self.needs_repaint = true;
     }
 
-    /// Registers a user image from Vulkano image view to be used by egui
-    pub fn register_user_image_view(
-        &mut self,
-        image: Arc<dyn ImageViewAbstract + Send + Sync>,
-        sampler_create_info: SamplerCreateInfo,
-    ) -> egui::TextureId {
-        self.renderer.register_image(image, sampler_create_info)
+    /// Draws the cursor as part of the ui while enabled, e.g. for recordings or fullscreen modes
+    /// where the hardware cursor lags or disappears. The OS cursor is hidden over the window and
+    /// the icon egui requests is drawn at the pointer above all windows and tooltips, as an
+    /// arrow, I-beam or hand. Nothing is drawn while the pointer is outside the window.
+    pub fn set_software_cursor(&mut self, enabled: bool) {
+        self.software_cursor = enabled;
+        if let Some(surface) = &self.surface {
+            surface_window(surface).set_cursor_visible(!enabled);
+        }
+        self.needs_repaint = true;
     }
 
-    /// Registers a user image to be used by egui
-    /// - `image_file_bytes`: e.g. include_bytes!("./assets/tree.png")
-    /// - `format`: e.g. vulkano::format::Format::R8G8B8A8Unorm
-    pub fn register_user_image(
+    /// Returns whether the integration is suspended, see `suspend`
+    pub fn is_suspended(&self) -> bool {
+        self.suspended
+    }
+
+    fn window(&self) -> Option<&Window> {
+        self.surface.as_deref().map(surface_window)
+    }
+
+    /// Same as `new_with_subpass`, using the first subpass of `render_pass`, e.g. one created with
+    /// `create_gui_render_pass`.
+    pub fn new_from_render_pass<T>(
+        event_loop: &EventLoopWindowTarget<T>,
+        surface: Arc<Surface>,
+        gfx_queue: Arc<Queue>,
+        render_pass: Arc<RenderPass>,
+        config: GuiConfig,
+    ) -> Gui {
+        let subpass =
+            Subpass::from(render_pass, 0).expect("Render pass must have at least one subpass");
+        Self::new_with_subpass(event_loop, surface, gfx_queue, subpass, config)
+    }
+
+    /// Returns the graphics queue the integration draws & uploads with
+    pub fn queue(&self) -> Arc<Queue> {
+        self.renderer().queue()
+    }
+
+    /// Returns the device of the integration's queue
+    pub fn device(&self) -> Arc<Device> {
+        self.renderer().queue().device().clone()
+    }
+
+    /// Returns the subpass egui draws in, e.g. to create pipelines for paint callbacks. This is
+    /// the subpass given in `new_with_subpass`, or the one of the integration's own render pass.
+    pub fn subpass(&self) -> Subpass {
+        self.renderer().subpass()
+    }
+
+    /// Draws in `subpass` from now on, e.g. when switching between a forward and an HDR render
+    /// path with another color format, keeping the egui context, textures & font atlas. See
+    /// `Renderer::set_subpass`: pipelines are created once per compatible subpass, so switching
+    /// back is instant. Execute later command buffers in the new subpass, and recreate pipelines
+    /// of paint callbacks made from `subpass`.
+    ///
+    /// Panics if the integration was created with its own render pass.
+    pub fn set_subpass(&mut self, subpass: Subpass) -> Result<(), GuiCreationError> {
+        self.renderer().set_subpass(subpass)
+    }
+
+    /// Moves the integration to `gfx_queue`, e.g. of a device recreated after a draw failed with
+    /// an error for which `EguiVulkanoError::is_device_lost` holds. The pipeline, buffers and
+    /// font atlas are created again, while the egui context (memory, fonts, window state) is
+    /// kept. `subpass` is the subpass of the new device to draw in for guis created with a
+    /// subpass, `None` for guis with their own render pass.
+    ///
+    /// User images registered from file bytes or pixels while `set_retain_image_data` was
+    /// enabled are uploaded again under their ids, with the bytes they were registered with.
+    /// Other textures can't be restored: registered image views, textures egui loaded itself,
+    /// and images whose upload fails. They are unregistered (meshes using them are drawn as a
+    /// placeholder) and their ids returned, so the app can register them again. Uploads of
+    /// `TextureUploader`s still in flight are dropped, get new uploaders for the new device.
+    /// Shaders of `set_shaders` have to be set again, and a frame of `prepare` prepared again.
+    ///
+    /// Panics if the renderer is shared with other guis, see `new_shared`.
+    pub fn rebuild(
         &mut self,
-        image_file_bytes: &[u8],
-        format: vulkano::format::Format,
-        sampler_create_info: SamplerCreateInfo,
-    ) -> egui::TextureId {
-        let image = immutable_texture_from_file(
-            self.renderer.allocators(),
-            self.renderer.queue(),
-            image_file_bytes,
-            format,
+        gfx_queue: Arc<Queue>,
+        subpass: Option<Subpass>,
+    ) -> Result<Vec<egui::TextureId>, GuiCreationError> {
+        if self.context != 0 || Arc::strong_count(&self.renderer.0) > 1 {
+            panic!("Gui integration shares its renderer with other guis, it can't be rebuilt")
+        }
+        let device = gfx_queue.device().clone();
+        let renderer = {
+            let mut lost_renderer = self.renderer.0.lock().unwrap();
+            // Waiting on uploads of the lost device would panic when dropping them
+            lost_renderer.forget_pending_uploads();
+            lost_renderer.recreate(gfx_queue, subpass)?
+        };
+        let registry = renderer.texture_registry();
+        self.allocators = renderer.shared_allocators();
+        *self.renderer.0.lock().unwrap() = renderer;
+        if let Some(uploader) = self.uploader.take() {
+            uploader.forget_pending();
+        }
+        self.prepared_frame = None;
+        let mut lost = vec![];
+        let mut font_lost = false;
+        let mut textures: Vec<_> = registry.iter().collect();
+        // Variants are restored after the images they borrow
+        let retained_variant = |id: &egui::TextureId| {
+            self.retained_images
+                .as_ref()
+                .and_then(|images| images.get(id))
+                .map_or(false, |retained| matches!(retained.source, RetainedSource::Variant { .. }))
+        };
+        textures.sort_by_key(|(id, _)| retained_variant(id));
+        for (id, image) in textures {
+            // Already restored by a gui with a separate renderer sharing the registry
+            if Arc::ptr_eq(image.device(), &device) {
+                continue;
+            }
+            registry.unregister(id);
+            match id {
+                egui::TextureId::Managed(0) => font_lost = true,
+                egui::TextureId::Managed(_) => lost.push(id),
+                egui::TextureId::User(_) => {
+                    let retained = self.retained_images.as_ref().and_then(|images| images.get(&id));
+                    if retained.map_or(true, |retained| self.restore_image(id, retained).is_err()) {
+                        lost.push(id);
+                    }
+                }
+            }
+        }
+        if let Some(images) = &mut self.retained_images {
+            images.retain(|id, _| registry.contains(*id));
+        }
+        if font_lost {
+            // egui only sends changes of its font atlas, so upload the whole atlas ahead of them
+            let font_image = self.egui_ctx.fonts(|fonts| {
+                let atlas = fonts.texture_atlas();
+                let mut atlas = atlas.lock();
+                let font_image = atlas.allocate((0, 0)).1.clone();
+                // The empty allocation marks the atlas changed, which the full upload covers
+                atlas.take_delta();
+                font_image
+            });
+            self.suspended_textures_delta.set.insert(
+                0,
+                (
+                    egui::TextureId::default(),
+                    egui::epaint::ImageDelta::full(font_image, egui::TextureOptions::LINEAR),
+                ),
+            );
+        }
+        self.needs_repaint = true;
+        Ok(lost)
+    }
+
+    // Uploads a retained image again and registers it as `id`
+    fn restore_image(
+        &self,
+        id: egui::TextureId,
+        retained: &RetainedImage,
+    ) -> Result<(), ImageRegistrationError> {
+        if let RetainedSource::Variant { base } = retained.source {
+            return self.renderer().restore_image_variant(
+                id,
+                base,
+                retained.sampler_create_info.clone(),
+            );
+        }
+        let (image, upload) = self.upload_retained(retained)?;
+        self.renderer().restore_image_deferred(
+            id,
+            image,
+            retained.sampler_create_info.clone(),
+            upload,
         )
-        .expect("Failed to create image");
-        self.renderer.register_image(image, sampler_create_info)
     }
 
-    pub fn register_user_image_from_bytes(
+    fn upload_retained(
+        &self,
+        retained: &RetainedImage,
+    ) -> Result<DeferredTexture, ImageRegistrationError> {
+        let bytes = &retained.bytes;
+        match retained.source {
+            RetainedSource::File { format } => immutable_texture_from_file_deferred(
+                &self.allocators,
+                self.renderer().upload_queue(false),
+                bytes,
+                format,
+            ),
+            RetainedSource::FileWithOptions(options) => {
+                immutable_texture_from_file_with_options_deferred(
+                    &self.allocators,
+                    self.renderer().upload_queue(options.mipmaps),
+                    bytes,
+                    options,
+                )
+            }
+            RetainedSource::Bytes { dimensions, format } => immutable_texture_from_bytes_deferred(
+                &self.allocators,
+                self.renderer().upload_queue(false),
+                bytes,
+                dimensions,
+                format,
+                false,
+            ),
+            RetainedSource::Variant { .. } => unreachable!("variants have no bytes to upload"),
+        }
+    }
+
+    /// Keeps a copy of the bytes of user images registered from then on from file bytes or
+    /// pixels, so `rebuild` can upload them again on a new device. The copies are kept until
+    /// the images are unregistered. Disabling drops the copies. Disabled by default.
+    pub fn set_retain_image_data(&mut self, enabled: bool) {
+        if enabled != self.retained_images.is_some() {
+            self.retained_images = enabled.then(AHashMap::default);
+        }
+    }
+
+    // Keeps the bytes `id` was registered from while `set_retain_image_data` is enabled
+    fn retain_image(
         &mut self,
-        image_byte_data: &[u8],
-        dimensions: [u32; 2],
-        format: vulkano::format::Format,
-        sampler_create_info: SamplerCreateInfo,
-    ) -> egui::TextureId {
-        let image = immutable_texture_from_bytes(
-            self.renderer.allocators(),
-            self.renderer.queue(),
-            image_byte_data,
-            dimensions,
-            format,
-        )
-        .expect("Failed to create image");
-        self.renderer.register_image(image, sampler_create_info)
+        id: egui::TextureId,
+        bytes: &[u8],
+        source: RetainedSource,
+        sampler_create_info: &SamplerCreateInfo,
+    ) {
+        let registry = self.renderer().texture_registry();
+        if let Some(images) = &mut self.retained_images {
+            // Also drops copies of images unregistered through the registry, e.g. dropped
+            // `RegisteredImage`s
+            images.retain(|id, _| registry.contains(*id));
+            images.insert(id, RetainedImage {
+                bytes: bytes.to_vec(),
+                source,
+                sampler_create_info: sampler_create_info.clone(),
+            });
+        }
     }
 
-    /// Unregisters a user image
-    pub fn unregister_user_image(&mut self, texture_id: egui::TextureId) {
-        self.renderer.unregister_image(texture_id);
+    /// Returns a set of resources used to construct the render pipeline. These can be reused
+    /// to create additional pipelines and buffers to be rendered in a `PaintCallback`.
+    pub fn render_resources(&self) -> RenderResources<'_> {
+        RenderResources {
+            memory_allocator: self.allocators.memory.clone(),
+            descriptor_set_allocator: &self.allocators.descriptor_set,
+            command_buffer_allocator: &self.allocators.command_buffer,
+            queue: self.queue(),
+            subpass: self.subpass(),
+        }
     }
 
-    /// Access egui's context (which can be used to e.g. set fonts, visuals etc)
-    pub fn context(&self) -> egui::Context {
-        self.egui_ctx.clone()
+    /// Returns a handle of the integration's renderer for creating more guis drawing with it,
+    /// see `new_shared`
+    pub fn shared_renderer(&self) -> SharedRenderer {
+        self.renderer.clone()
     }
-}
 
-// Helper to retrieve Window from surface object
+    /// Returns the registry of the images of the gui's textures, see `GuiConfig::texture_registry`.
+    /// User images registered in it are drawn from the next frame on.
+    pub fn texture_registry(&self) -> Arc<TextureRegistry> {
+        self.renderer().texture_registry()
+    }
+
+    // Locks the renderer, drawing & registering textures for this gui's context
+    fn renderer(&self) -> MutexGuard<'_, Renderer> {
+        let mut renderer = self.renderer.0.lock().unwrap();
+        renderer.set_context(self.context);
+        renderer.set_ui_viewport(self.ui_viewport);
+        renderer
+    }
+
+    /// Replaces the shaders egui meshes are drawn with, e.g. for a tint or CRT effect over the
+    /// whole ui. Stages without a shader in `shaders` use the built-in one. Guis sharing the
+    /// renderer (see `new_shared`) draw with the same shaders. Returns an error if the pipeline
+    /// can't be created, e.g. when a shader doesn't match the interface described in
+    /// `GuiShaders`.
+    pub fn set_shaders(&mut self, shaders: GuiShaders) -> Result<(), GuiCreationError> {
+        self.renderer().set_shaders(shaders)
+    }
+
+    /// Returns whether vertex & index buffers are device local, see
+    /// `GuiConfig::device_local_buffers`
+    pub fn uses_device_local_buffers(&self) -> bool {
+        self.renderer().uses_device_local_buffers()
+    }
+
+    /// Updates context state by winit window event.
+    /// Returns `true` if egui wants exclusive use of this event
+    /// (e.g. a mouse click on an egui window, or entering text into a text field).
+    /// For instance, if you use egui for a game, you want to first call this
+    /// and only when this returns `false` pass on the events to your game.
+    ///
+    /// Pointer events are consumed while egui wants pointer input (e.g. when hovering an egui
+    /// window), keyboard and character events while egui wants keyboard input. Cursor movement
+    /// is only consumed while egui is actively using the pointer (e.g. dragging), so hovering
+    /// outside of egui areas still reaches your application.
+    ///
+    /// Touch events are passed to egui as touches for its gestures, and the first active touch
+    /// also drives egui's pointer, so further fingers don't move what the first one is dragging.
+    ///
+    /// Scrolling with ctrl (cmd on macOS) held and touchpad pinch gestures zoom egui via
+    /// `InputState::zoom_delta`, at most by a factor of 2 per frame. Touchpad rotate gestures
+    /// (macOS) aren't known to egui and are returned by `touchpad_rotation_delta` instead.
+    ///
+    /// Files dragged over the window are listed in egui's `RawInput::hovered_files` until they are
+    /// dropped or the drag is cancelled. Dropped files are reported in `RawInput::dropped_files`
+    /// of the next frame, all files of one drop together.
+    ///
+    /// Modifier state comes from `ModifiersChanged` events and is attached to every key and
+    /// pointer event. When the window loses focus, the modifiers are cleared and held pointer
+    /// buttons & keys are released with the pointer gone, so e.g. a dragged slider doesn't keep
+    /// dragging after alt-tabbing back. Leaving the window also makes the pointer gone, so hover
+    /// highlights don't stick.
+    /// Keys are mapped to the full set of egui keys, with numpad digits and numpad enter acting
+    /// like their main keyboard counterparts. `Modifiers::command` is `Cmd` on macOS and `Ctrl`
+    /// elsewhere, so standard shortcuts behave natively. Held keys repeat as the OS repeats them,
+    /// unless `set_key_repeat` is used.
+    ///
+    /// In headless mode window events are ignored, use the `inject_*` functions instead.
+    ///
+    /// `ThemeChanged` switches egui's visuals if `set_follow_system_theme` is enabled. When
+    /// `Moved` brings the window to another monitor, its scale factor and refresh rate apply
+    /// right away, without waiting for `ScaleFactorChanged`.
+    ///
+    /// Characters are only passed to egui as text when they are printable: control characters
+    /// (including tab, enter and escape, which reach egui as key events), the private use
+    /// characters macOS sends for function keys and characters typed while a shortcut modifier
+    /// is held (`Ctrl` without `Alt`, so `AltGr` still types, or `Cmd` on macOS) are dropped.
+    ///
+    /// Note that egui uses `tab` to move focus between elements, so this will always return `true` for tabs.
+    pub fn update(&mut self, winit_event: &WindowEvent<'_>) -> bool {
+        let started = Instant::now();
+        let consumed = self.handle_event(winit_event);
+        self.timings.input += started.elapsed();
+        consumed
+    }
+
+    // Handles a window event, see `update`
+    fn handle_event(&mut self, winit_event: &WindowEvent<'_>) -> bool {
+        // Egui-winit's input is only taken from a window, so it would never reach egui
+        if self.surface.is_none() {
+            return false;
+        }
+        // Modifiers are tracked by `WinitInputState`
+        match winit_event {
+            // Releases happening while unfocused aren't reported, so nothing may stay held
+            WindowEvent::Focused(false) => {
+                self.held_mouse_buttons.clear();
+                self.release_pointer();
+                self.release_keys();
+                self.window_focused = false;
+                self.allow_ime(false);
+            }
+            // IME is allowed again once a frame ends
+            WindowEvent::Focused(true) => {
+                self.window_focused = true;
+                self.needs_repaint = true;
+            }
+            // Hides the on-screen keyboard, which comes back if the app keeps the window
+            WindowEvent::CloseRequested => self.allow_ime(false),
+            WindowEvent::CursorMoved { position, .. } => {
+                self.cursor_pos = Some((position.x, position.y))
+            }
+            WindowEvent::MouseInput { state: ElementState::Pressed, button, .. }
+                if !self.held_mouse_buttons.contains(button) =>
+            {
+                self.held_mouse_buttons.push(*button)
+            }
+            WindowEvent::MouseInput { state: ElementState::Released, button, .. } => {
+                self.held_mouse_buttons.retain(|held| held != button)
+            }
+            // While dragging, winit keeps sending `CursorMoved` from outside the window, so
+            // egui keeps the drag and learns the pointer is gone once it ends. When the cursor
+            // enters, egui-winit waits for `CursorMoved` to know where it is.
+            WindowEvent::CursorLeft { .. } if !self.held_mouse_buttons.is_empty() => {
+                self.cursor_left_while_held = true;
+                return false;
+            }
+            WindowEvent::CursorLeft { .. } => self.cursor_pos = None,
+            WindowEvent::CursorEntered { .. } => self.cursor_left_while_held = false,
+            WindowEvent::Moved(_) => self.track_monitor(),
+            WindowEvent::ScaleFactorChanged { .. } => {
+                self.monitor = self.window().and_then(Window::current_monitor);
+                self.monitor_frame_time = self.window().and_then(monitor_frame_time);
+            }
+            WindowEvent::ThemeChanged(theme) => {
+                self.system_theme = Some(*theme);
+                self.apply_system_theme();
+            }
+            // Winit's degrees are counterclockwise, egui's y axis points down
+            WindowEvent::TouchpadRotate { delta, .. }
+                if !self.pointer_passthrough && self.input_enabled =>
+            {
+                self.pending_rotation -= delta.to_radians();
+                self.needs_repaint = true;
+                return self.egui_ctx.wants_pointer_input();
+            }
+            _ => (),
+        }
+        if !self.input_enabled
+            && (is_pointer_event(winit_event)
+                || matches!(
+                    winit_event,
+                    WindowEvent::KeyboardInput { .. }
+                        | WindowEvent::ReceivedCharacter(_)
+                        | WindowEvent::Ime(_)
+                ))
+        {
+            return false;
+        }
+        if is_pointer_event(winit_event) {
+            if self.pointer_passthrough {
+                return false;
+            }
+            if self.awaiting_cursor_moved {
+                // Touches carry their own position
+                if !matches!(winit_event, WindowEvent::CursorMoved { .. } | WindowEvent::Touch(_)) {
+                    return false;
+                }
+                self.awaiting_cursor_moved = false;
+            }
+        }
+        if self.key_repeat.is_some() && self.track_held_key(winit_event) {
+            return self.egui_ctx.wants_keyboard_input();
+        }
+        let response = self.input.on_event(&mut self.egui_winit, &self.egui_ctx, winit_event);
+        self.needs_repaint |= response.repaint;
+        if self.cursor_left_while_held && self.held_mouse_buttons.is_empty() {
+            self.cursor_left_while_held = false;
+            self.cursor_pos = None;
+            self.inject_event(egui::Event::PointerGone);
+        }
+        // Pointer events outside the ui viewport belong to the scene, unless egui is dragging
+        if self.is_outside_ui_viewport(winit_event) && !self.egui_ctx.is_using_pointer() {
+            return false;
+        }
+        // Egui-winit takes the monitor's scale factor as is, keep the user's scale factor & zoom
+        if let WindowEvent::ScaleFactorChanged { scale_factor, .. } = winit_event {
+            self.set_pixels_per_point(*scale_factor as f32);
+        }
+        response.consumed
+    }
+
+    // Updates the scale factor & refresh rate when the window moved to another monitor. Some
+    // platforms send `ScaleFactorChanged` only after further `Moved` events, or not at all, and
+    // pointer positions would be converted with the old monitor's scale factor until then.
+    fn track_monitor(&mut self) {
+        let window = match self.window() {
+            Some(window) => window,
+            None => return,
+        };
+        let monitor = window.current_monitor();
+        if monitor == self.monitor {
+            return;
+        }
+        let window_scale_factor = window.scale_factor() as f32;
+        self.monitor_frame_time = monitor_frame_time(window);
+        self.monitor = monitor;
+        self.set_pixels_per_point(window_scale_factor);
+        self.needs_repaint = true;
+    }
+
+    fn is_outside_ui_viewport(&self, winit_event: &WindowEvent<'_>) -> bool {
+        let ui_viewport = match self.ui_viewport {
+            Some(ui_viewport) => ui_viewport,
+            None => return false,
+        };
+        let pos = match winit_event {
+            WindowEvent::Touch(touch) => Some((touch.location.x, touch.location.y)),
+            WindowEvent::CursorMoved { .. }
+            | WindowEvent::MouseInput { .. }
+            | WindowEvent::MouseWheel { .. } => self.cursor_pos,
+            _ => return false,
+        };
+        !pos.map_or(false, |(x, y)| ui_viewport.contains(x, y))
+    }
+
+    /// Limits the ui to the region of the window (and the images it's drawn on) at `offset` of
+    /// `extent` pixels, e.g. a side panel next to your scene. Egui's screen rect is the region,
+    /// pointer positions are relative to it and the ui is drawn only inside it. Pointer events
+    /// outside the region are never consumed by `update` unless egui is dragging, so they reach
+    /// your scene. Can be changed every frame, e.g. for a resizable panel. The region is clamped
+    /// to the image drawn on.
+    ///
+    /// When the integration clears the image (`GuiConfig::clear_mode`), all of it is cleared, so
+    /// use `ClearMode::Preserve` to keep your scene around the ui.
+    pub fn set_ui_viewport(&mut self, offset: [u32; 2], extent: [u32; 2]) {
+        self.ui_viewport = Some(UiViewport { offset, extent });
+        self.needs_repaint = true;
+    }
+
+    /// Maps `rect` in points (e.g. from `ui.available_rect_before_wrap()`) to the viewport origin
+    /// & extent and the scissor in physical pixels of the image the ui is drawn on, e.g. to draw
+    /// your scene into exactly that part of an egui window. Rounded outwards to whole pixels like
+    /// the renderer's clip rects, so it lines up with what egui paints around it, and clipped to
+    /// the ui. The ui viewport's offset (see `set_ui_viewport`) is included.
+    pub fn rect_to_viewport(&self, rect: egui::Rect) -> ([f32; 2], [f32; 2], Scissor) {
+        let pixels_per_point = self.egui_ctx.pixels_per_point();
+        let screen_size = self.egui_ctx.screen_rect().size() * pixels_per_point;
+        let offset = self.ui_viewport.map_or([0, 0], |ui_viewport| ui_viewport.offset);
+        let scissor = rect_scissor(
+            pixels_per_point,
+            offset,
+            [screen_size.x.round() as u32, screen_size.y.round() as u32],
+            rect,
+        );
+        let origin = [scissor.origin[0] as f32, scissor.origin[1] as f32];
+        let extent = [scissor.dimensions[0] as f32, scissor.dimensions[1] as f32];
+        (origin, extent, scissor)
+    }
+
+    /// Maps a position in physical pixels of the window (e.g. of `WindowEvent::CursorMoved`) to
+    /// egui's points, the inverse of `rect_to_viewport`, e.g. to find where inside an embedded
+    /// scene viewport was clicked
+    pub fn physical_pos_to_egui_pos(&self, pos: [f32; 2]) -> egui::Pos2 {
+        let pixels_per_point = self.egui_ctx.pixels_per_point();
+        let offset = self.ui_viewport.map_or([0, 0], |ui_viewport| ui_viewport.offset);
+        egui::pos2(
+            (pos[0] - offset[0] as f32) / pixels_per_point,
+            (pos[1] - offset[1] as f32) / pixels_per_point,
+        )
+    }
+
+    /// Makes the ui use the whole window again, see `set_ui_viewport`
+    pub fn reset_ui_viewport(&mut self) {
+        self.ui_viewport = None;
+        self.needs_repaint = true;
+    }
+
+    /// Sets when IME is allowed on the window, which also shows and hides the on-screen keyboard
+    /// on platforms where winit ties it to IME. By default it's allowed while a text field has
+    /// focus. IME is set once per transition, and disallowed while the window has no focus or is
+    /// asked to close.
+    pub fn set_virtual_keyboard_policy(&mut self, policy: VirtualKeyboardPolicy) {
+        self.virtual_keyboard_policy = policy;
+        if policy == VirtualKeyboardPolicy::Never {
+            self.allow_ime(false);
+        }
+        self.needs_repaint = true;
+    }
+
+    // Allows or disallows IME on the window if it changed
+    fn allow_ime(&mut self, allowed: bool) {
+        if allowed == self.ime_allowed {
+            return;
+        }
+        if let Some(window) = self.window() {
+            window.set_ime_allowed(allowed);
+            self.ime_allowed = allowed;
+        }
+    }
+
+    /// Repeats held keys (and the text they type) in the integration with `key_repeat`'s timing,
+    /// instead of relying on the OS, which may not repeat keys (e.g. some Wayland compositors) or
+    /// use a rate you don't want. The OS's own repeats are then ignored. While a key is held,
+    /// `needs_repaint` and `repaint_deadline` wake you up for its repeats. `None`, the default,
+    /// uses the OS's key repeat.
+    pub fn set_key_repeat(&mut self, key_repeat: Option<KeyRepeat>) {
+        self.key_repeat = key_repeat;
+        self.held_key = None;
+        self.skip_repeated_char = false;
+    }
+
+    // Tracks the held key for integration key repeat. Returns `true` for OS repeats, which are
+    // swallowed along with the character they type.
+    fn track_held_key(&mut self, winit_event: &WindowEvent<'_>) -> bool {
+        let delay = match self.key_repeat {
+            Some(key_repeat) => key_repeat.delay,
+            None => return false,
+        };
+        match winit_event {
+            WindowEvent::KeyboardInput {
+                device_id,
+                input: input @ KeyboardInput { virtual_keycode: Some(keycode), state, .. },
+                is_synthetic,
+            } => match state {
+                ElementState::Pressed => {
+                    if self.held_key.as_ref().map_or(false, |held| held.keycode == *keycode) {
+                        self.skip_repeated_char = true;
+                        return true;
+                    }
+                    self.skip_repeated_char = false;
+                    self.held_key = Some(HeldKey {
+                        keycode: *keycode,
+                        event: WindowEvent::KeyboardInput {
+                            device_id: *device_id,
+                            input: *input,
+                            is_synthetic: *is_synthetic,
+                        },
+                        text: None,
+                        next_repeat: Instant::now() + delay,
+                    });
+                }
+                ElementState::Released => {
+                    if self.held_key.as_ref().map_or(false, |held| held.keycode == *keycode) {
+                        self.held_key = None;
+                    }
+                }
+            },
+            WindowEvent::ReceivedCharacter(ch) => {
+                if std::mem::take(&mut self.skip_repeated_char) {
+                    return true;
+                }
+                if let Some(held) = &mut self.held_key {
+                    held.text.get_or_insert(*ch);
+                }
+            }
+            WindowEvent::Focused(false) => self.held_key = None,
+            _ => (),
+        }
+        false
+    }
+
+    // Replays the held key's press for each repeat due, so it's translated and gets modifiers
+    // like any other key event
+    fn repeat_held_key(&mut self) {
+        let (key_repeat, held) = match (self.key_repeat, &mut self.held_key) {
+            (Some(key_repeat), Some(held)) => (key_repeat, held),
+            _ => return,
+        };
+        let interval = key_repeat.interval.max(Duration::from_millis(1));
+        let now = Instant::now();
+        let mut repeats = 0;
+        while held.next_repeat <= now {
+            if repeats == MAX_REPEATS_PER_FRAME {
+                held.next_repeat = now + interval;
+                break;
+            }
+            let _ = self.input.on_event(&mut self.egui_winit, &self.egui_ctx, &held.event);
+            if let Some(ch) = held.text {
+                let _ = self.input.on_event(
+                    &mut self.egui_winit,
+                    &self.egui_ctx,
+                    &WindowEvent::ReceivedCharacter(ch),
+                );
+            }
+            held.next_repeat += interval;
+            repeats += 1;
+        }
+    }
+
+    /// Sets the expected duration of a frame in seconds, which egui uses to step animations, e.g.
+    /// for apps with their own frame pacing. `None`, the default, uses the refresh rate of the
+    /// window's monitor, which is queried again when the window moves or its scale factor
+    /// changes, or egui's default of 1/60 if the rate is unknown (or in headless mode).
+    /// Egui's `RawInput::time` is always set from a monotonic clock started with the integration.
+    pub fn set_predicted_frame_time(&mut self, predicted_frame_time: Option<f32>) {
+        self.predicted_frame_time = predicted_frame_time;
+    }
+
+    /// Sets how many points egui scrolls per line of mouse wheel scrolling. Defaults to 50.
+    /// Pixel deltas (e.g. of touchpads) are converted from physical pixels to points and are
+    /// not affected. Holding shift scrolls horizontally.
+    pub fn set_scroll_line_height(&mut self, points_per_line: f32) {
+        self.input.set_scroll_line_height(points_per_line);
+    }
+
+    /// Sets how egui tessellates shapes into meshes, applied when the next drawn frame is
+    /// tessellated. E.g. disabling `feathering` (egui's anti-aliasing) roughly halves the
+    /// vertices, see `last_render_stats`, and `feathering_size_in_pixels` adjusts its width.
+    /// Shorthand for `Context::tessellation_options_mut`.
+    pub fn set_tessellation_options(&mut self, options: egui::epaint::TessellationOptions) {
+        self.egui_ctx.tessellation_options_mut(|tessellation_options| {
+            *tessellation_options = options;
+        });
+    }
+
+    /// Adds a TTF or OTF font named `name` to `family`, as its first font if `prepend` (e.g. to
+    /// replace egui's default font) or else as a fallback for glyphs missing from its fonts.
+    /// Fonts added before are kept, and a new `FontFamily::Name` family falls back to the
+    /// proportional fonts. Adding a font with the name of an added one replaces that font. Can
+    /// be called at any time, the font atlas is rebuilt and uploaded with the next frame.
+    ///
+    /// Fonts start from egui's defaults, so fonts set directly with `Context::set_fonts` are
+    /// replaced.
+    pub fn add_font(
+        &mut self,
+        name: &str,
+        font_bytes: &[u8],
+        family: egui::FontFamily,
+        prepend: bool,
+    ) -> Result<(), FontError> {
+        ab_glyph::FontRef::try_from_slice(font_bytes)?;
+        let definitions = self.font_definitions.get_or_insert_with(Default::default);
+        definitions
+            .font_data
+            .insert(name.to_owned(), egui::FontData::from_owned(font_bytes.to_vec()));
+        let fallback = definitions.families.get(&egui::FontFamily::Proportional).cloned();
+        let fonts =
+            definitions.families.entry(family).or_insert_with(|| fallback.unwrap_or_default());
+        fonts.retain(|font| font != name);
+        if prepend {
+            fonts.insert(0, name.to_owned());
+        } else {
+            fonts.push(name.to_owned());
+        }
+        self.egui_ctx.set_fonts(definitions.clone());
+        Ok(())
+    }
+
+    /// Removes the font named `name`, added with `add_font` or one of egui's defaults, from all
+    /// families. Returns `false` if there is no such font.
+    pub fn remove_font(&mut self, name: &str) -> bool {
+        let definitions = self.font_definitions.get_or_insert_with(Default::default);
+        if definitions.font_data.remove(name).is_none() {
+            return false;
+        }
+        for fonts in definitions.families.values_mut() {
+            fonts.retain(|font| font != name);
+        }
+        self.egui_ctx.set_fonts(definitions.clone());
+        true
+    }
+
+    /// Returns the options egui tessellates shapes with, see `set_tessellation_options`
+    pub fn tessellation_options(&self) -> egui::epaint::TessellationOptions {
+        self.egui_ctx.tessellation_options(|options| *options)
+    }
+
+    /// Returns `true` if egui wants a new frame, either because of an input event passed to
+    /// `update` or because the last frame requested one (e.g. `Context::request_repaint` or an
+    /// ongoing animation) and its deadline has passed. Useful with `ControlFlow::Wait` to only
+    /// redraw when needed.
+    ///
+    /// Animations such as fades or a blinking text cursor request a repaint after a delay rather
+    /// than immediately, so use `ControlFlow::WaitUntil` with `repaint_deadline` instead of plain
+    /// `ControlFlow::Wait`, or they freeze until the next input event. Repaints requested from
+    /// other threads are only seen here after the next frame, use
+    /// `Context::set_request_repaint_callback` to wake up your event loop for them.
+    pub fn needs_repaint(&self) -> bool {
+        self.needs_repaint
+            || self.repaint_deadline.map_or(false, |deadline| deadline <= Instant::now())
+    }
+
+    /// Returns when egui wants to be repainted next, as requested by the last frame. `None` if
+    /// no repaint was requested (or it's needed right away, see `needs_repaint`).
+    pub fn repaint_deadline(&self) -> Option<Instant> {
+        self.repaint_deadline
+    }
+
+    /// Overrides the scale factor (pixels per point) used by egui, independent of the window's
+    /// scale factor. Pass `None` to follow the window's scale factor again (1 in headless mode).
+    pub fn set_scale_factor(&mut self, scale_factor: Option<f64>) {
+        self.scale_factor_override = scale_factor.map(|scale_factor| scale_factor as f32);
+        let window_scale_factor = self.window().map_or(1.0, |window| window.scale_factor());
+        self.set_pixels_per_point(window_scale_factor as f32);
+    }
+
+    /// Zooms the ui by `zoom` on top of the scale factor, e.g. for a "UI scale" setting that is
+    /// independent of the monitor's DPI. Window positions are converted with the zoomed scale
+    /// factor, so the pointer keeps hitting the widgets under it, and the screen rect shrinks
+    /// as the zoom grows. Applies from the next frame. Panics if `zoom` isn't positive.
+    pub fn set_ui_zoom(&mut self, zoom: f32) {
+        assert!(zoom.is_finite() && zoom > 0.0, "Ui zoom must be positive, got {}", zoom);
+        self.ui_zoom = zoom;
+        let window_scale_factor = self.window().map_or(1.0, |window| window.scale_factor());
+        self.set_pixels_per_point(window_scale_factor as f32);
+        self.needs_repaint = true;
+    }
+
+    /// Returns the ui zoom set with `set_ui_zoom`, 1 by default
+    pub fn ui_zoom(&self) -> f32 {
+        self.ui_zoom
+    }
+
+    /// Makes egui's visuals follow the OS dark / light theme. Enabling reads the window's current
+    /// theme, and the visuals switch when `update` receives `WindowEvent::ThemeChanged`. On
+    /// platforms that don't report a theme, the visuals stay as they are. Disabled by default.
+    pub fn set_follow_system_theme(&mut self, enabled: bool) {
+        self.follow_system_theme = enabled;
+        if enabled {
+            if let Some(theme) = self.window().and_then(|window| window.theme()) {
+                self.system_theme = Some(theme);
+            }
+            self.apply_system_theme();
+        }
+    }
+
+    /// Sets the visuals used for the dark & light system themes instead of `Visuals::dark` &
+    /// `Visuals::light`, see `set_follow_system_theme`
+    pub fn set_theme_visuals(&mut self, dark: egui::Visuals, light: egui::Visuals) {
+        self.theme_visuals = Some(Box::new((dark, light)));
+        self.apply_system_theme();
+    }
+
+    // Sets the visuals of the system theme, if followed and known
+    fn apply_system_theme(&mut self) {
+        if !self.follow_system_theme {
+            return;
+        }
+        let Some(theme) = self.system_theme else {
+            return;
+        };
+        let visuals = match (theme, &self.theme_visuals) {
+            (Theme::Dark, Some(visuals)) => visuals.0.clone(),
+            (Theme::Light, Some(visuals)) => visuals.1.clone(),
+            (Theme::Dark, None) => egui::Visuals::dark(),
+            (Theme::Light, None) => egui::Visuals::light(),
+        };
+        self.egui_ctx.set_visuals(visuals);
+        self.needs_repaint = true;
+    }
+
+    // Sets egui-winit's pixels per point from the user's or the window's scale factor & the zoom
+    fn set_pixels_per_point(&mut self, window_scale_factor: f32) {
+        let scale_factor = self.scale_factor_override.unwrap_or(window_scale_factor);
+        self.egui_winit.set_pixels_per_point(scale_factor * self.ui_zoom);
+    }
+
+    /// Sets a handler for urls egui wants to open, e.g. when a `Hyperlink` is clicked. This
+    /// replaces the default behavior of opening the url in the browser (`links` feature), so
+    /// you can e.g. ask for confirmation or handle custom schemes yourself.
+    pub fn set_open_url_handler(
+        &mut self,
+        handler: impl Fn(&egui::output::OpenUrl) + Send + 'static,
+    ) {
+        self.open_url_handler = Some(Box::new(handler));
+    }
+
+    /// Sets a handler for egui's accessibility events, e.g. a widget gaining focus, to drive a
+    /// screen reader or other assistive tooling. Events are delivered in order once at the end
+    /// of each frame, and egui only emits them when something changed. They're also available
+    /// in `last_output`.
+    pub fn set_accessibility_handler(
+        &mut self,
+        handler: impl FnMut(&egui::output::OutputEvent) + Send + 'static,
+    ) {
+        self.accessibility_handler = Some(Box::new(handler));
+    }
+
+    /// Sets a hook called with the input of each frame right before egui begins the frame, after
+    /// window & injected events have been collected, e.g. to add events from a tablet SDK or
+    /// override `pixels_per_point` or `screen_rect`. The input is taken each frame, so changes
+    /// only affect the frame they were made for.
+    pub fn set_raw_input_hook(&mut self, hook: impl FnMut(&mut egui::RawInput) + Send + 'static) {
+        self.raw_input_hook = Some(Box::new(hook));
+    }
+
+    /// Sets a hook called with the shapes of each frame once it ends, before they're
+    /// tessellated, e.g. to slide windows in or shake the screen by transforming the shapes of
+    /// some layers. Shapes may be added, removed or modified freely, tessellation takes whatever
+    /// is left. Each shape is clipped to the rect next to it, so move the clip rect along with
+    /// the shape (e.g. `Shape::translate` and `Rect::translate` by the same offset). This only
+    /// changes what is drawn, egui still hit tests widgets where it laid them out.
+    pub fn set_shape_transform(
+        &mut self,
+        transform: impl FnMut(&mut Vec<egui::epaint::ClippedShape>) + Send + 'static,
+    ) {
+        self.shape_transform = Some(Box::new(transform));
+    }
+
+    /// Returns the scale factor (pixels per point) egui is currently using, including the ui zoom
+    pub fn scale_factor(&self) -> f32 {
+        self.egui_winit.pixels_per_point()
+    }
+
+    /// Injects a pointer move to `pos` (in points, like egui's rects) into the next frame, e.g.
+    /// for automated ui tests. Injected input is appended after the window's events of the frame.
+    pub fn inject_pointer_moved(&mut self, pos: egui::Pos2) {
+        self.inject_event(egui::Event::PointerMoved(pos));
+    }
+
+    /// Injects a pointer button press or release at `pos` (in points) into the next frame. The
+    /// pointer is moved to `pos` first.
+    pub fn inject_pointer_button(
+        &mut self,
+        pos: egui::Pos2,
+        button: egui::PointerButton,
+        pressed: bool,
+    ) {
+        self.inject_event(egui::Event::PointerMoved(pos));
+        self.inject_event(egui::Event::PointerButton {
+            pos,
+            button,
+            pressed,
+            modifiers: self.injected_modifiers,
+        });
+    }
+
+    /// Injects typed text into the next frame
+    pub fn inject_text(&mut self, text: &str) {
+        self.inject_event(egui::Event::Text(text.to_owned()));
+    }
+
+    /// Injects a key press or release into the next frame. `modifiers` are also held for the
+    /// frame, combined with the modifiers of the keyboard.
+    pub fn inject_key(&mut self, key: egui::Key, pressed: bool, modifiers: egui::Modifiers) {
+        self.injected_modifiers = self.injected_modifiers | modifiers;
+        self.inject_event(egui::Event::Key { key, pressed, repeat: false, modifiers });
+    }
+
+    /// Injects scrolling by `delta` points into the next frame
+    pub fn inject_scroll(&mut self, delta: egui::Vec2) {
+        self.inject_event(egui::Event::Scroll(delta));
+    }
+
+    fn inject_event(&mut self, event: egui::Event) {
+        self.injected_events.push(event);
+        self.needs_repaint = true;
+    }
+
+    /// Begins Egui frame & determines what will be drawn later. This must be called before draw, and after `update` (winit event).
+    pub fn immediate_ui(&mut self, layout_function: impl FnOnce(&mut Self)) {
+        self.begin_frame();
+        // Render Egui
+        let started = Instant::now();
+        layout_function(self);
+        self.timings.ui_closure += started.elapsed();
+    }
+
+    /// Ends the egui frame and tessellates its shapes on a worker thread, so your main thread
+    /// can keep working, e.g. record your scene. Opt-in, drawing ends & tessellates frames on the
+    /// calling thread otherwise. The platform output is handled right away as the frame ends,
+    /// e.g. the cursor icon, copied text and `wants_pointer_input`.
+    ///
+    /// The next draw draws this frame instead of ending another one, waiting for the worker if
+    /// it isn't done yet. A begun frame is kept for the draw after. To overlap tessellation
+    /// with the next frame entirely, draw before running the ui
+    /// (draw, `immediate_ui`, `end_frame_async`), which shows the ui one frame late. Ending
+    /// another frame before drawing drops the previous one. `draw_layers_on_image` draws the frame
+    /// as a single background layer.
+    pub fn end_frame_async(&mut self) {
+        // A frame ended before that wasn't drawn is dropped, but its textures still need uploading
+        let mut textures_delta = match self.pending_tessellation.take() {
+            Some(pending) => {
+                let _ = pending.worker.recv();
+                pending.textures_delta
+            }
+            None => TexturesDelta::default(),
+        };
+        self.begin_frame();
+        self.end_frame();
+        let shapes = std::mem::take(&mut self.shapes);
+        textures_delta.append(std::mem::take(&mut self.suspended_textures_delta));
+        let mut frame_delta = std::mem::take(&mut self.textures_delta);
+        if self.context != 0 {
+            self.remap_managed_textures(&mut [], &mut frame_delta);
+        }
+        textures_delta.append(frame_delta);
+        // Snapshot of what `Context::tessellate` reads, the context may change meanwhile
+        let pixels_per_point = self.egui_ctx.pixels_per_point();
+        let options = self.egui_ctx.tessellation_options(|options| *options);
+        let (font_tex_size, prepared_discs) = self.egui_ctx.fonts(|fonts| {
+            let atlas = fonts.texture_atlas();
+            let atlas = atlas.lock();
+            (atlas.size(), atlas.prepared_discs())
+        });
+        let pixel_snapping = self.pixel_snapping;
+        let worker = self
+            .tessellation_worker
+            .get_or_insert_with(|| WorkerThreads::new(1, "gui-tessellation"));
+        let worker = worker.run(move || {
+            let started = Instant::now();
+            let mut clipped_meshes = egui::epaint::tessellator::tessellate_shapes(
+                pixels_per_point,
+                options,
+                font_tex_size,
+                prepared_discs,
+                shapes,
+            );
+            if pixel_snapping {
+                snap_textured_quads(&mut clipped_meshes, pixels_per_point);
+            }
+            (clipped_meshes, started.elapsed())
+        });
+        self.pending_tessellation =
+            Some(PendingTessellation { worker, textures_delta, pixels_per_point });
+    }
+
+    /// Whether a frame ended with `end_frame_async` is waiting to be drawn
+    pub fn has_pending_frame(&self) -> bool {
+        self.pending_tessellation.is_some()
+    }
+
+    /// If you wish to better control when to begin frame, do so by calling this function
+    /// (Finish by drawing). Between this and drawing, `context()` can be passed to any number of
+    /// systems adding ui to the frame.
+    ///
+    /// Calling this again before drawing keeps the current frame. Drawing without beginning a
+    /// frame begins an empty one.
+    pub fn begin_frame(&mut self) {
+        if self.frame_begun {
+            return;
+        }
+        // Layers of the previous frame no draw took are dropped with it
+        self.layered_frame = None;
+        let started = Instant::now();
+        self.repeat_held_key();
+        self.rotation_delta = std::mem::take(&mut self.pending_rotation);
+        let mut raw_input = match (&self.surface, &self.headless) {
+            (Some(surface), _) => self.egui_winit.take_egui_input(surface_window(surface)),
+            (None, Some(headless)) => self.headless_input(headless),
+            (None, None) => unreachable!("Gui has neither a window nor headless state"),
+        };
+        if let Some(predicted_dt) = self.predicted_frame_time.or(self.monitor_frame_time) {
+            raw_input.predicted_dt = predicted_dt;
+        }
+        if let Some(ui_viewport) = self.ui_viewport {
+            self.apply_ui_viewport(ui_viewport, &mut raw_input);
+        }
+        raw_input.events.append(&mut self.injected_events);
+        raw_input.modifiers = raw_input.modifiers | self.injected_modifiers;
+        self.injected_modifiers = egui::Modifiers::NONE;
+        // Combine and clamp zooming per frame, so fast scrolling can't zoom wildly
+        let mut zoom = 1.0;
+        raw_input.events.retain(|event| match event {
+            egui::Event::Zoom(factor) => {
+                zoom *= factor;
+                false
+            }
+            _ => true,
+        });
+        if zoom != 1.0 {
+            raw_input
+                .events
+                .push(egui::Event::Zoom(zoom.clamp(1.0 / MAX_ZOOM_DELTA, MAX_ZOOM_DELTA)));
+        }
+        if let Some(hook) = &mut self.raw_input_hook {
+            hook(&mut raw_input);
+        }
+        self.egui_ctx.begin_frame(raw_input);
+        self.frame_begun = true;
+        self.timings.input += started.elapsed();
+    }
+
+    /// Makes the screen rect the ui viewport and window events' positions relative to it
+    fn apply_ui_viewport(&self, ui_viewport: UiViewport, raw_input: &mut egui::RawInput) {
+        let pixels_per_point = self.egui_winit.pixels_per_point();
+        let extent = egui::vec2(ui_viewport.extent[0] as f32, ui_viewport.extent[1] as f32)
+            / pixels_per_point;
+        raw_input.screen_rect = (extent.x > 0.0 && extent.y > 0.0)
+            .then(|| egui::Rect::from_min_size(egui::Pos2::ZERO, extent));
+        let offset = egui::vec2(ui_viewport.offset[0] as f32, ui_viewport.offset[1] as f32)
+            / pixels_per_point;
+        for event in &mut raw_input.events {
+            match event {
+                egui::Event::PointerMoved(pos)
+                | egui::Event::PointerButton { pos, .. }
+                | egui::Event::Touch { pos, .. } => *pos -= offset,
+                _ => (),
+            }
+        }
+    }
+
+    /// Builds the raw input egui-winit would take from a window
+    fn headless_input(&self, headless: &Headless) -> egui::RawInput {
+        let pixels_per_point = self.egui_winit.pixels_per_point();
+        let screen_size_in_points =
+            egui::vec2(headless.screen_size[0] as f32, headless.screen_size[1] as f32)
+                / pixels_per_point;
+        egui::RawInput {
+            screen_rect: (screen_size_in_points.x > 0.0 && screen_size_in_points.y > 0.0)
+                .then(|| egui::Rect::from_min_size(egui::Pos2::ZERO, screen_size_in_points)),
+            pixels_per_point: Some(pixels_per_point),
+            max_texture_side: Some(headless.max_texture_side),
+            time: Some(headless.start_time.elapsed().as_secs_f64()),
+            focused: true,
+            ..Default::default()
+        }
+    }
+
+    /// Renders ui on `final_image` & Updates cursor icon
+    /// Finishes Egui frame
+    /// - `before_future` = Vulkano's GpuFuture
+    /// - `final_image` = Vulkano's image (render target)
+    /// - Returns the draw's future, executed after `before_future` on the integration's queue
+    ///   (`queue()`) and already flushed, e.g. to present the swapchain image after it. Use
+    ///   `try_draw_on_image` to handle errors instead of panicking.
+    pub fn draw_on_image<F>(
+        &mut self,
+        before_future: F,
+        final_image: Arc<dyn ImageViewAbstract + 'static>,
+    ) -> Box<dyn GpuFuture>
+    where
+        F: GpuFuture + 'static,
+    {
+        self.try_draw_on_image(before_future, final_image)
+            .unwrap_or_else(|err| panic!("Failed to draw gui: {}", err))
+    }
+
+    /// Like `draw_on_image`, but returns an error instead of panicking when drawing fails, e.g.
+    /// when the device is lost or out of memory
+    pub fn try_draw_on_image<F>(
+        &mut self,
+        before_future: F,
+        final_image: Arc<dyn ImageViewAbstract + 'static>,
+    ) -> Result<Box<dyn GpuFuture>, EguiVulkanoError>
+    where
+        F: GpuFuture + 'static,
+    {
+        if !self.renderer().has_renderpass() {
+            panic!(
+                "Gui integration has been created with subpass, use `draw_on_subpass_image` \
+                 instead"
+            )
+        }
+
+        if self.suspended {
+            self.end_frame();
+            self.shapes.clear();
+            let textures_delta = std::mem::take(&mut self.textures_delta);
+            self.suspended_textures_delta.append(textures_delta);
+            return Ok(before_future.boxed());
+        }
+
+        let (clipped_meshes, textures_delta, pixels_per_point) =
+            self.extract_draw_data_at_frame_end();
+
+        let dimensions = final_image.image().dimensions().width_height();
+        let result = self.renderer().draw_on_image(
+            &clipped_meshes,
+            &textures_delta,
+            pixels_per_point,
+            before_future,
+            final_image,
+        );
+        self.finish_draw(clipped_meshes, pixels_per_point, dimensions);
+        result
+    }
+
+    /// Draws the meshes of the last drawn frame again on `final_image`, without running,
+    /// laying out or tessellating the ui, e.g. for frames whose ui didn't change (see
+    /// `needs_repaint`). No egui frame is ended, so a begun frame is kept for the next draw.
+    /// User images unregistered since are skipped.
+    ///
+    /// Returns an error if no frame was drawn yet, or if `final_image`'s dimensions differ from
+    /// those of the image the frame was drawn on.
+    pub fn redraw_last_frame<F>(
+        &mut self,
+        before_future: F,
+        final_image: Arc<dyn ImageViewAbstract + 'static>,
+    ) -> Result<Box<dyn GpuFuture>, EguiVulkanoError>
+    where
+        F: GpuFuture + 'static,
+    {
+        if !self.renderer().has_renderpass() {
+            panic!(
+                "Gui integration has been created with subpass, use \
+                 `redraw_last_frame_on_subpass` instead"
+            )
+        }
+        if self.suspended {
+            return Ok(before_future.boxed());
+        }
+        let last_frame =
+            self.last_frame_of_size(final_image.image().dimensions().width_height())?;
+        let result = self.renderer().draw_on_image(
+            &last_frame.clipped_meshes,
+            &TexturesDelta::default(),
+            last_frame.pixels_per_point,
+            before_future,
+            final_image,
+        );
+        self.notify_evicted_textures();
+        result
+    }
+
+    /// Like `redraw_last_frame`, but records the commands like `draw_on_subpass_image`
+    pub fn redraw_last_frame_on_subpass(
+        &mut self,
+        image_dimensions: [u32; 2],
+    ) -> Result<SecondaryAutoCommandBuffer, EguiVulkanoError> {
+        if self.renderer().has_renderpass() {
+            panic!(
+                "Gui integration has been created with its own render pass, use \
+                 `redraw_last_frame` instead"
+            )
+        }
+        let last_frame = self.last_frame_of_size(image_dimensions)?;
+        let result = self.renderer().draw_on_subpass_image(
+            &last_frame.clipped_meshes,
+            &TexturesDelta::default(),
+            last_frame.pixels_per_point,
+            if self.suspended { [0, 0] } else { image_dimensions },
+        );
+        self.notify_evicted_textures();
+        result
+    }
+
+    /// Draws the layers of the frame for which `filter` returns true on `final_image`, like
+    /// `draw_on_image`, e.g. world space overlays before your post processing and menus after it.
+    ///
+    /// The first call of a frame ends it and splits its output by layers, so call it several
+    /// times per frame with different filters, each drawing in its own render pass. Every layer
+    /// is drawn at most once per frame, by the first call whose filter takes it, so overlapping
+    /// filters don't draw meshes twice. Layers no call takes aren't drawn, so pass `|_| true`
+    /// to the last call to draw the rest. Texture updates are uploaded by the first call.
+    ///
+    /// Layers are those of egui areas & windows, plus the background and debug layers. Shapes
+    /// painted on other layers with `Context::layer_painter` are drawn with the next of those
+    /// layers in paint order. Use `GuiConfig::clear_mode: ClearMode::Preserve` so the calls don't
+    /// clear each other.
+    pub fn draw_layers_on_image<F>(
+        &mut self,
+        before_future: F,
+        final_image: Arc<dyn ImageViewAbstract + 'static>,
+        filter: impl Fn(egui::LayerId) -> bool,
+    ) -> Result<Box<dyn GpuFuture>, EguiVulkanoError>
+    where
+        F: GpuFuture + 'static,
+    {
+        if !self.renderer().has_renderpass() {
+            panic!(
+                "Gui integration has been created with subpass, use \
+                 `draw_layers_on_subpass_image` instead"
+            )
+        }
+        if self.suspended {
+            return self.try_draw_on_image(before_future, final_image);
+        }
+        let (clipped_meshes, textures_delta, pixels_per_point, first) = self.take_layers(filter);
+        let dimensions = final_image.image().dimensions().width_height();
+        let result = self.renderer().draw_on_image(
+            &clipped_meshes,
+            &textures_delta,
+            pixels_per_point,
+            before_future,
+            final_image,
+        );
+        self.finish_layers_draw(clipped_meshes, pixels_per_point, dimensions, first);
+        result
+    }
+
+    /// Like `draw_layers_on_image`, but records the commands like `draw_on_subpass_image`
+    pub fn draw_layers_on_subpass_image(
+        &mut self,
+        image_dimensions: [u32; 2],
+        filter: impl Fn(egui::LayerId) -> bool,
+    ) -> Result<SecondaryAutoCommandBuffer, EguiVulkanoError> {
+        if self.renderer().has_renderpass() {
+            panic!(
+                "Gui integration has been created with its own render pass, use \
+                 `draw_layers_on_image` instead"
+            )
+        }
+        let (clipped_meshes, textures_delta, pixels_per_point, first) = self.take_layers(filter);
+        let result = self.renderer().draw_on_subpass_image(
+            &clipped_meshes,
+            &textures_delta,
+            pixels_per_point,
+            if self.suspended { [0, 0] } else { image_dimensions },
+        );
+        self.finish_layers_draw(clipped_meshes, pixels_per_point, image_dimensions, first);
+        result
+    }
+
+    // Takes the meshes of the layers `filter` returns true for that weren't drawn yet, ending
+    // the frame first if its layers weren't split yet, and whether it did so
+    fn take_layers(
+        &mut self,
+        filter: impl Fn(egui::LayerId) -> bool,
+    ) -> (Vec<ClippedPrimitive>, TexturesDelta, f32, bool) {
+        let first = self.layered_frame.is_none();
+        let frame = match &mut self.layered_frame {
+            Some(frame) => frame,
+            None => {
+                let frame = self.extract_layers_at_frame_end();
+                self.layered_frame.insert(frame)
+            }
+        };
+        let mut clipped_meshes = vec![];
+        for (layer_id, meshes) in &mut frame.layers {
+            if filter(*layer_id) {
+                clipped_meshes.extend(meshes.take().into_iter().flatten());
+            }
+        }
+        let textures_delta = std::mem::take(&mut frame.textures_delta);
+        let pixels_per_point = frame.pixels_per_point;
+        if frame.layers.iter().all(|(_, meshes)| meshes.is_none()) {
+            self.layered_frame = None;
+        }
+        (clipped_meshes, textures_delta, pixels_per_point, first)
+    }
+
+    // Ends the frame like `extract_draw_data_at_frame_end`, splitting its meshes by layers at
+    // markers painted at the end of each layer
+    fn extract_layers_at_frame_end(&mut self) -> LayeredFrame {
+        // Frames ended with `end_frame_async` have no markers, and are drawn as one layer
+        if self.pending_tessellation.is_none() {
+            self.paint_layer_markers();
+        }
+        let (clipped_meshes, textures_delta, pixels_per_point) =
+            self.extract_draw_data_at_frame_end();
+
+        let mut layers = vec![];
+        let mut meshes = vec![];
+        for clipped_mesh in clipped_meshes {
+            if let egui::epaint::Primitive::Callback(callback) = &clipped_mesh.primitive {
+                if let Some(LayerMarker(layer_id)) = callback.callback.downcast_ref() {
+                    layers.push((*layer_id, Some(std::mem::take(&mut meshes))));
+                    continue;
+                }
+            }
+            meshes.push(clipped_mesh);
+        }
+        // Markers of a zero-sized screen are clipped away, so keep what follows the last one
+        if !meshes.is_empty() {
+            match layers.last_mut() {
+                Some((_, Some(last))) => last.append(&mut meshes),
+                _ => layers.push((egui::LayerId::background(), Some(meshes))),
+            }
+        }
+        LayeredFrame { layers, textures_delta, pixels_per_point }
+    }
+
+    // Paints a marker at the end of each layer of the frame
+    fn paint_layer_markers(&mut self) {
+        self.begin_frame();
+        let mut layer_ids: Vec<_> = self.egui_ctx.memory(|memory| memory.layer_ids().collect());
+        for layer_id in [egui::LayerId::background(), egui::LayerId::debug()].iter().copied() {
+            if !layer_ids.contains(&layer_id) {
+                layer_ids.push(layer_id);
+            }
+        }
+        for layer_id in layer_ids {
+            let painter = self.egui_ctx.layer_painter(layer_id);
+            painter.add(egui::Shape::Callback(egui::PaintCallback {
+                rect: painter.clip_rect(),
+                callback: Arc::new(LayerMarker(layer_id)),
+            }));
+        }
+    }
+
+    // Finishes drawing a frame: keeps its meshes for `redraw_last_frame`, completes its timings
+    // with the renderer's and starts the next frame's
+    fn finish_draw(
+        &mut self,
+        clipped_meshes: Vec<ClippedPrimitive>,
+        pixels_per_point: f32,
+        dimensions: [u32; 2],
+    ) {
+        self.notify_evicted_textures();
+        self.last_frame = Some(LastFrame { clipped_meshes, pixels_per_point, dimensions });
+        let stats = self.renderer().last_stats();
+        self.last_timings = FrameTimings {
+            upload: stats.upload_time,
+            record: stats.record_time,
+            ..std::mem::take(&mut self.timings)
+        };
+    }
+
+    // Like `finish_draw` for the layers drawn by a `draw_layers_*` call. The first call of a
+    // frame finishes it, later ones add their meshes & renderer timings to it.
+    fn finish_layers_draw(
+        &mut self,
+        clipped_meshes: Vec<ClippedPrimitive>,
+        pixels_per_point: f32,
+        dimensions: [u32; 2],
+        first: bool,
+    ) {
+        if first {
+            return self.finish_draw(clipped_meshes, pixels_per_point, dimensions);
+        }
+        self.notify_evicted_textures();
+        if let Some(last_frame) = &mut self.last_frame {
+            last_frame.clipped_meshes.extend(clipped_meshes);
+        }
+        let stats = self.renderer().last_stats();
+        self.last_timings.upload += stats.upload_time;
+        self.last_timings.record += stats.record_time;
+    }
+
+    fn last_frame_of_size(&self, dimensions: [u32; 2]) -> Result<&LastFrame, EguiVulkanoError> {
+        let last_frame = self.last_frame.as_ref().ok_or(EguiVulkanoError::NoFrameToRedraw)?;
+        if last_frame.dimensions != dimensions {
+            return Err(EguiVulkanoError::RedrawSizeChanged {
+                cached: last_frame.dimensions,
+                actual: dimensions,
+            });
+        }
+        Ok(last_frame)
+    }
+
+    /// Renders ui on an offscreen texture of `dimensions` owned by the integration, e.g. for ui
+    /// displayed inside your 3D scene. The texture is recreated when `dimensions` change.
+    /// Finishes Egui frame
+    /// - `before_future` = Vulkano's GpuFuture
+    /// - Returns the future to wait on before sampling the texture, and the texture's image view
+    /// - The texture is cleared with the color of `GuiConfig::clear_mode`, or to transparent with
+    ///   `ClearMode::Preserve`, and contains premultiplied alpha. Composite it with
+    ///   `premultiplied_alpha_blend`, not straight alpha blending, or edges get dark fringes.
+    /// - Zero `dimensions` (e.g. of a minimized window) are treated as 1
+    /// - Egui lays out the ui for the window's size, so `dimensions` should usually match the
+    ///   window's inner size
+    pub fn draw_on_texture<F>(
+        &mut self,
+        before_future: F,
+        dimensions: [u32; 2],
+    ) -> (Box<dyn GpuFuture>, Arc<dyn ImageViewAbstract + Send + Sync + 'static>)
+    where
+        F: GpuFuture + 'static,
+    {
+        self.try_draw_on_texture(before_future, dimensions)
+            .unwrap_or_else(|err| panic!("Failed to draw gui: {}", err))
+    }
+
+    /// Like `draw_on_texture`, but returns an error instead of panicking when drawing fails
+    pub fn try_draw_on_texture<F>(
+        &mut self,
+        before_future: F,
+        dimensions: [u32; 2],
+    ) -> Result<TextureDraw, EguiVulkanoError>
+    where
+        F: GpuFuture + 'static,
+    {
+        if !self.renderer().has_renderpass() {
+            panic!(
+                "Gui integration has been created with subpass, use `draw_on_subpass_image` \
+                 instead"
+            )
+        }
+
+        let (clipped_meshes, textures_delta, pixels_per_point) =
+            self.extract_draw_data_at_frame_end();
+
+        let result = self.renderer().draw_on_texture(
+            &clipped_meshes,
+            &textures_delta,
+            pixels_per_point,
+            before_future,
+            dimensions,
+        );
+        self.finish_draw(clipped_meshes, pixels_per_point, dimensions);
+        result
+    }
+
+    /// Reads the image last drawn on with `draw_on_image` or `draw_on_texture` back to the CPU,
+    /// e.g. for screenshots or golden image tests. `before_future` must include the draw, e.g.
+    /// the future `draw_on_texture` returned. Returns the width, height and rgba8 pixels, see
+    /// `read_texture_to_vec`. Images you draw on must have transfer src usage, the texture of
+    /// `draw_on_texture` has it.
+    ///
+    /// Returns `ReadbackError::NoFrameDrawn` if nothing was drawn with the integration's own
+    /// render pass yet.
+    pub fn read_back_last_frame(
+        &self,
+        before_future: impl GpuFuture + 'static,
+    ) -> Result<(u32, u32, Vec<u8>), ReadbackError> {
+        let image = self.renderer().last_target().ok_or(ReadbackError::NoFrameDrawn)?;
+        read_texture_to_vec(&self.allocators, self.queue(), image, before_future)
+    }
+
+    /// Creates commands for rendering ui on subpass' image and returns the command buffer for execution on your side
+    /// - Finishes Egui frame
+    /// - You must execute the secondary command buffer yourself
+    /// - The command buffer inherits the subpass given in `new_with_subpass`, so execute it with
+    ///   `execute_commands` between your own `begin_render_pass` and `end_render_pass`
+    /// - Texture uploads are submitted separately before recording, so the returned command
+    ///   buffer only contains draw commands. This waits for uploads of user images registered
+    ///   since the last draw.
+    /// - With zero `image_dimensions` (e.g. a minimized window) the command buffer is empty
+    pub fn draw_on_subpass_image(
+        &mut self,
+        image_dimensions: [u32; 2],
+    ) -> SecondaryAutoCommandBuffer {
+        self.try_draw_on_subpass_image(image_dimensions)
+            .unwrap_or_else(|err| panic!("Failed to draw gui: {}", err))
+    }
+
+    /// Like `draw_on_subpass_image`, but returns an error instead of panicking when recording
+    /// fails
+    pub fn try_draw_on_subpass_image(
+        &mut self,
+        image_dimensions: [u32; 2],
+    ) -> Result<SecondaryAutoCommandBuffer, EguiVulkanoError> {
+        if self.renderer().has_renderpass() {
+            panic!(
+                "Gui integration has been created with its own render pass, use `draw_on_image` \
+                 instead"
+            )
+        }
+
+        let (clipped_meshes, textures_delta, pixels_per_point) =
+            self.extract_draw_data_at_frame_end();
+
+        let result = self.renderer().draw_on_subpass_image(
+            &clipped_meshes,
+            &textures_delta,
+            pixels_per_point,
+            if self.suspended { [0, 0] } else { image_dimensions },
+        );
+        self.finish_draw(clipped_meshes, pixels_per_point, image_dimensions);
+        result
+    }
+
+    /// Records `meshes` made outside of the egui frame, e.g. a minimap or debug gizmos, with the
+    /// ui's pipeline, textures and clipping, see `Renderer::paint_meshes`. Positions are in
+    /// points at the gui's current pixels per point, and `TextureId`s are those egui and
+    /// `register_user_image*` hand out. Doesn't end the egui frame, so it can be called any
+    /// number of times per frame; execute the command buffer like the one of
+    /// `draw_on_subpass_image`.
+    pub fn paint_raw(
+        &mut self,
+        meshes: &[ClippedPrimitive],
+        image_dimensions: [u32; 2],
+    ) -> Result<SecondaryAutoCommandBuffer, EguiVulkanoError> {
+        if self.renderer().has_renderpass() {
+            panic!(
+                "Gui integration has been created with its own render pass, raw meshes can only \
+                 be painted in a subpass"
+            )
+        }
+        let remapped;
+        let meshes = if self.context != 0 {
+            remapped = {
+                let mut meshes = meshes.to_vec();
+                self.remap_managed_textures(&mut meshes, &mut TexturesDelta::default());
+                meshes
+            };
+            &remapped
+        } else {
+            meshes
+        };
+        let pixels_per_point = self.egui_ctx.pixels_per_point();
+        self.renderer().paint_meshes(
+            meshes,
+            pixels_per_point,
+            if self.suspended { [0, 0] } else { image_dimensions },
+        )
+    }
+
+    /// Records commands for rendering ui directly into your command buffer builder, avoiding
+    /// a separate secondary command buffer
+    /// - Finishes Egui frame
+    /// - `builder` must be inside the subpass given in `new_with_subpass`, begun with
+    ///   `SubpassContents::Inline` if it is a primary command buffer builder
+    /// - Viewport and scissor are left as set by the last egui draw, set your own again if you
+    ///   keep drawing afterwards
+    /// - Paint callbacks are not supported, use `draw_on_subpass_image` for those
+    /// - Waits for uploads of user images registered since the last draw
+    pub fn draw_into<L, A: CommandBufferAllocator>(
+        &mut self,
+        builder: &mut AutoCommandBufferBuilder<L, A>,
+        image_dimensions: [u32; 2],
+    ) {
+        self.try_draw_into(builder, image_dimensions)
+            .unwrap_or_else(|err| panic!("Failed to draw gui: {}", err))
+    }
+
+    /// Like `draw_into`, but returns an error instead of panicking when recording fails
+    pub fn try_draw_into<L, A: CommandBufferAllocator>(
+        &mut self,
+        builder: &mut AutoCommandBufferBuilder<L, A>,
+        image_dimensions: [u32; 2],
+    ) -> Result<(), EguiVulkanoError> {
+        if self.renderer().has_renderpass() {
+            panic!(
+                "Gui integration has been created with its own render pass, use `draw_on_image` \
+                 instead"
+            )
+        }
+
+        let (clipped_meshes, textures_delta, pixels_per_point) =
+            self.extract_draw_data_at_frame_end();
+
+        let result = self.renderer().draw_into(
+            &clipped_meshes,
+            &textures_delta,
+            pixels_per_point,
+            if self.suspended { [0, 0] } else { image_dimensions },
+            builder,
+        );
+        self.finish_draw(clipped_meshes, pixels_per_point, image_dimensions);
+        result
+    }
+
+    /// Finishes the egui frame and submits its texture uploads, the first half of
+    /// `draw_on_subpass_image` split in two. `render` then records the draw commands, which
+    /// contain no transfer commands. Unlike `draw_on_subpass_image` this doesn't wait for the
+    /// uploads: the returned future does, join it with the future your command buffer executing
+    /// the draw commands executes after. `None` if nothing was uploaded.
+    ///
+    /// Calling this again before `render` doesn't end another frame, but returns the uploads
+    /// submitted since, e.g. user images registered after preparing.
+    pub fn prepare(&mut self) -> Result<Option<Box<dyn GpuFuture>>, EguiVulkanoError> {
+        if self.renderer().has_renderpass() {
+            panic!(
+                "Gui integration has been created with its own render pass, use `draw_on_image` \
+                 instead"
+            )
+        }
+        if self.prepared_frame.is_some() {
+            return Ok(self.renderer().take_pending_uploads());
+        }
+
+        let (clipped_meshes, textures_delta, pixels_per_point) =
+            self.extract_draw_data_at_frame_end();
+
+        let uploads = self.renderer().prepare(&textures_delta)?;
+        self.prepared_frame = Some(PreparedFrame {
+            clipped_meshes,
+            freed_textures: textures_delta.free,
+            pixels_per_point,
+        });
+        Ok(uploads)
+    }
+
+    /// Records the draw commands of the frame finished with `prepare`, the second half of
+    /// `draw_on_subpass_image`. Execute the command buffer like that of `draw_on_subpass_image`,
+    /// after the uploads `prepare` returned.
+    ///
+    /// Errors if no frame was prepared, or if uploads were submitted since (e.g. by
+    /// `update_user_image`), which the draw could sample before they finish. Call `prepare`
+    /// again to get their future, the prepared frame is kept until it is rendered.
+    pub fn render(
+        &mut self,
+        image_dimensions: [u32; 2],
+    ) -> Result<SecondaryAutoCommandBuffer, EguiVulkanoError> {
+        let frame = self.prepared_frame.as_ref().ok_or(EguiVulkanoError::FrameNotPrepared)?;
+        let result = self.renderer().render_prepared(
+            &frame.clipped_meshes,
+            &frame.freed_textures,
+            frame.pixels_per_point,
+            if self.suspended { [0, 0] } else { image_dimensions },
+        );
+        if result.is_ok() {
+            let frame = self.prepared_frame.take().unwrap();
+            self.finish_draw(frame.clipped_meshes, frame.pixels_per_point, image_dimensions);
+        }
+        result
+    }
+
+    fn extract_draw_data_at_frame_end(&mut self) -> (Vec<ClippedPrimitive>, TexturesDelta, f32) {
+        if let Some(pending) = self.pending_tessellation.take() {
+            return self.finish_tessellation(pending);
+        }
+        // Begun here, so beginning an empty frame counts as input
+        self.begin_frame();
+        let started = Instant::now();
+        self.end_frame();
+        let shapes = std::mem::take(&mut self.shapes);
+        let mut textures_delta = std::mem::take(&mut self.suspended_textures_delta);
+        textures_delta.append(std::mem::take(&mut self.textures_delta));
+        let mut clipped_meshes = self.egui_ctx.tessellate(shapes);
+        if self.pixel_snapping {
+            snap_textured_quads(&mut clipped_meshes, self.egui_ctx.pixels_per_point());
+        }
+        if self.context != 0 {
+            self.remap_managed_textures(&mut clipped_meshes, &mut textures_delta);
+        }
+        self.timings.tessellation += started.elapsed();
+        (clipped_meshes, textures_delta, self.egui_ctx.pixels_per_point())
+    }
+
+    // Waits for the meshes of a frame ended with `end_frame_async`
+    fn finish_tessellation(
+        &mut self,
+        pending: PendingTessellation,
+    ) -> (Vec<ClippedPrimitive>, TexturesDelta, f32) {
+        let (mut clipped_meshes, tessellation) = pending
+            .worker
+            .recv()
+            .expect("Gui tessellation thread stopped")
+            .unwrap_or_else(|panic| std::panic::resume_unwind(panic));
+        if self.context != 0 {
+            self.remap_managed_textures(&mut clipped_meshes, &mut TexturesDelta::default());
+        }
+        self.timings.tessellation += tessellation;
+        (clipped_meshes, pending.textures_delta, pending.pixels_per_point)
+    }
+
+    // Moves the managed textures (font atlas) of a gui sharing the renderer to its own ids
+    fn remap_managed_textures(
+        &self,
+        clipped_meshes: &mut [ClippedPrimitive],
+        textures_delta: &mut TexturesDelta,
+    ) {
+        for ClippedPrimitive { primitive, .. } in clipped_meshes {
+            if let egui::epaint::Primitive::Mesh(mesh) = primitive {
+                mesh.texture_id = context_texture_id(self.context, mesh.texture_id);
+            }
+        }
+        for (id, _) in &mut textures_delta.set {
+            *id = context_texture_id(self.context, *id);
+        }
+        for id in &mut textures_delta.free {
+            *id = context_texture_id(self.context, *id);
+        }
+    }
+
+    /// Ends egui frame and handles its platform output. Cursor icons are only set on the window
+    /// when they change, and `CursorIcon::None` hides the cursor until egui requests another icon.
+    fn end_frame(&mut self) {
+        // Begins an empty frame if none was begun, so egui's frame state stays consistent
+        self.begin_frame();
+        self.frame_begun = false;
+        if self.software_cursor {
+            self.paint_software_cursor();
+        }
+        let egui::FullOutput { mut platform_output, repaint_after, textures_delta, mut shapes } =
+            self.egui_ctx.end_frame();
+
+        self.wants_pointer_input = self.egui_ctx.wants_pointer_input();
+        self.wants_keyboard_input = self.egui_ctx.wants_keyboard_input();
+        self.pointer_over_ui = self.egui_ctx.is_pointer_over_area();
+        self.last_output = platform_output.clone();
+
+        self.needs_repaint = repaint_after == Duration::ZERO;
+        self.repaint_deadline = if self.needs_repaint {
+            None
+        } else {
+            // Egui uses `Duration::MAX` for no repaint, which overflows
+            Instant::now().checked_add(repaint_after)
+        };
+        // Wake up for the next repeat of a held key
+        if let (Some(_), Some(held)) = (self.key_repeat, &self.held_key) {
+            self.repaint_deadline = Some(
+                self.repaint_deadline
+                    .map_or(held.next_repeat, |deadline| deadline.min(held.next_repeat)),
+            );
+        }
+
+        if let Some(handler) = &mut self.accessibility_handler {
+            platform_output.events.iter().for_each(handler);
+        }
+
+        if let Some(handler) = &self.open_url_handler {
+            if let Some(open_url) = platform_output.open_url.take() {
+                handler(&open_url);
+            }
+        }
+
+        // Winit only sends IME composition events while IME is allowed, so allow it while a
+        // text field has focus
+        let ime_allowed = self.window_focused
+            && match self.virtual_keyboard_policy {
+                VirtualKeyboardPolicy::WhileEditing => platform_output.text_cursor_pos.is_some(),
+                VirtualKeyboardPolicy::Never => false,
+                VirtualKeyboardPolicy::Always => true,
+            };
+        self.allow_ime(ime_allowed);
+        // Egui-winit places the IME window in window coordinates
+        if let (Some(ui_viewport), Some(pos)) =
+            (self.ui_viewport, &mut platform_output.text_cursor_pos)
+        {
+            *pos += egui::vec2(ui_viewport.offset[0] as f32, ui_viewport.offset[1] as f32)
+                / self.egui_winit.pixels_per_point();
+        }
+        if let Some(surface) = &self.surface {
+            if self.cursor_management && !self.software_cursor {
+                self.handed_cursor_icon = Some(platform_output.cursor_icon);
+                self.egui_winit.handle_platform_output(
+                    surface_window(surface),
+                    &self.egui_ctx,
+                    platform_output,
+                );
+            } else {
+                // Done by egui-winit otherwise, in case the ui changed egui's scale
+                self.egui_winit.set_pixels_per_point(self.egui_ctx.pixels_per_point());
+                if let Some(egui::Pos2 { x, y }) = platform_output.text_cursor_pos.take() {
+                    surface_window(surface).set_ime_position(winit::dpi::LogicalPosition { x, y });
+                }
+                if !platform_output.copied_text.is_empty() || platform_output.open_url.is_some() {
+                    platform_output.cursor_icon = self.handed_cursor_icon.unwrap_or_default();
+                    self.egui_winit.handle_platform_output(
+                        surface_window(surface),
+                        &self.egui_ctx,
+                        platform_output,
+                    );
+                    // Egui-winit shows the cursor whenever it sets an icon
+                    if self.software_cursor {
+                        surface_window(surface).set_cursor_visible(false);
+                    }
+                }
+            }
+        }
+        self.run_window_commands();
+        if let Some(transform) = &mut self.shape_transform {
+            transform(&mut shapes);
+        }
+        let software_cursor = self
+            .egui_ctx
+            .input(|input| input.pointer.hover_pos())
+            .filter(|_| self.software_cursor)
+            .map(cursor_rect);
+        self.covered_regions = covered_regions(&shapes, software_cursor);
+        self.shapes = shapes;
+        self.textures_delta = textures_delta;
+    }
+
+    /// Queues a window action, e.g. from the ui closure, run once the frame ends. Commands are
+    /// ignored without a window. Failures, e.g. of `WindowCommand::StartDrag` on platforms
+    /// without support, are passed to the handler of `set_window_command_error_handler`, or
+    /// printed if there is none.
+    pub fn queue_window_command(&mut self, command: WindowCommand) {
+        self.window_commands.push(command);
+    }
+
+    /// Shorthand for queueing `WindowCommand::StartDrag`, e.g. when a drag of your title bar
+    /// starts: `if response.drag_started() { gui.start_window_drag() }`
+    pub fn start_window_drag(&mut self) {
+        self.queue_window_command(WindowCommand::StartDrag);
+    }
+
+    /// Whether the ui queued `WindowCommand::Close`. Stays set, close the window or exit your
+    /// event loop when it is.
+    pub fn close_requested(&self) -> bool {
+        self.close_requested
+    }
+
+    /// Sets a handler called with window commands that failed and their error
+    pub fn set_window_command_error_handler(
+        &mut self,
+        handler: impl FnMut(&WindowCommand, &ExternalError) + Send + 'static,
+    ) {
+        self.window_command_error_handler = Some(Box::new(handler));
+    }
+
+    // Runs the window commands queued during the frame
+    fn run_window_commands(&mut self) {
+        for command in std::mem::take(&mut self.window_commands) {
+            if command == WindowCommand::Close {
+                self.close_requested = true;
+                continue;
+            }
+            let Some(window) = self.window() else {
+                continue;
+            };
+            let result = match &command {
+                WindowCommand::StartDrag => window.drag_window(),
+                WindowCommand::SetTitle(title) => {
+                    window.set_title(title);
+                    Ok(())
+                }
+                WindowCommand::SetMinimized(minimized) => {
+                    window.set_minimized(*minimized);
+                    Ok(())
+                }
+                WindowCommand::SetMaximized(maximized) => {
+                    window.set_maximized(*maximized);
+                    Ok(())
+                }
+                WindowCommand::SetInnerSize([width, height]) => {
+                    window.set_inner_size(PhysicalSize::new(*width, *height));
+                    Ok(())
+                }
+                WindowCommand::Close => Ok(()),
+            };
+            if let Err(err) = result {
+                match &mut self.window_command_error_handler {
+                    Some(handler) => handler(&command, &err),
+                    None => eprintln!("Window command {:?} failed: {}", command, err),
+                }
+            }
+        }
+    }
+
+    // Paints the cursor of `set_software_cursor` on the frame about to end. Painted shapes take
+    // no part in egui's hit testing.
+    fn paint_software_cursor(&self) {
+        let Some(pos) = self.egui_ctx.input(|input| input.pointer.hover_pos()) else {
+            return;
+        };
+        let icon = self.egui_ctx.output(|output| output.cursor_icon);
+        paint_cursor(&self.egui_ctx.layer_painter(software_cursor_layer()), pos, icon);
+    }
+
+    /// Registers a user image from Vulkano image view to be used by egui
+    ///
+    /// Each registered image is sampled with its own sampler created from `sampler_create_info`.
+    /// E.g. use `SamplerCreateInfo::default()` for nearest filtering (pixel art, icon atlases) or
+    /// `SamplerCreateInfo::simple_repeat_linear_no_mipmap()` for smooth filtering.
+    ///
+    /// Single channel images are drawn as grayscale. Panics if the image view can't be sampled,
+    /// see `register_user_image_view_with_options`.
+    pub fn register_user_image_view(
+        &mut self,
+        image: Arc<dyn ImageViewAbstract + Send + Sync>,
+        sampler_create_info: SamplerCreateInfo,
+    ) -> egui::TextureId {
+        self.register_user_image_view_with_options(
+            image,
+            sampler_create_info,
+            UserImageOptions::default(),
+        )
+        .unwrap_or_else(|err| panic!("Failed to register image: {}", err))
+    }
+
+    /// Registers a user image from Vulkano image view, see `register_user_image_view`
+    /// - `options`: e.g. whether single channel images (`R8_UNORM`, depth) are drawn as grayscale
+    ///   or as white with the channel as alpha. Only applied to views with the identity
+    ///   component mapping, so views you swizzled yourself are drawn as is.
+    ///   `srgb` overrides whether the texels are treated as sRGB encoded or linear.
+    ///
+    /// Returns an error if the view can't be sampled (its image lacks sampled usage or its format
+    /// doesn't support sampling) or its format can't be drawn (integer or stencil formats).
+    ///
+    /// Images you render to, e.g. the color attachment of your scene's render pass, need no
+    /// layout transitions of your own: vulkano's command buffer builder transitions them for
+    /// sampling before the egui render pass begins and back to their usual layout afterwards.
+    /// Pass the future of your rendering on as `before_future` of the draw, so egui samples the
+    /// finished image (see the wholesome example).
+    pub fn register_user_image_view_with_options(
+        &mut self,
+        image: Arc<dyn ImageViewAbstract + Send + Sync>,
+        sampler_create_info: SamplerCreateInfo,
+        options: UserImageOptions,
+    ) -> Result<egui::TextureId, ImageRegistrationError> {
+        self.renderer().register_image(image, sampler_create_info, options)
+    }
+
+    /// Registers another `TextureId` for the image of the user texture `base` with its own
+    /// sampler from `sampler_create_info`, e.g. to show an image both crisp with nearest
+    /// filtering and smoothly scaled with linear filtering without uploading it twice. The
+    /// variant shares the image (and region, see `register_user_image_region`) of `base`.
+    ///
+    /// Unregistering `base` unregisters its variants too, so their ids stop being valid with
+    /// it. Unregistering a variant leaves `base` and the other variants registered, and
+    /// `update_user_image_view` with any of them replaces the image for all.
+    ///
+    /// Returns an error if `base` isn't a registered user texture.
+    pub fn register_user_image_variant(
+        &mut self,
+        base: egui::TextureId,
+        sampler_create_info: SamplerCreateInfo,
+    ) -> Result<egui::TextureId, ImageRegistrationError> {
+        let id = self.renderer().register_image_variant(base, sampler_create_info.clone())?;
+        // Restorable by `rebuild` as long as its base is
+        let base = self.renderer().variant_base(id).unwrap_or(base);
+        if self.retained_images.as_ref().map_or(false, |images| images.contains_key(&base)) {
+            self.retain_image(id, &[], RetainedSource::Variant { base }, &sampler_create_info);
+        }
+        Ok(id)
+    }
+
+    /// Registers the `uv_rect` region (in 0-1 uvs) of an image view as its own `TextureId`, e.g. an
+    /// icon in an atlas, so widgets can draw it with full 0-1 uvs. Regions of the same image view
+    /// share a descriptor set and the sampler of the first registered region, and unregistering
+    /// a region leaves the others registered.
+    pub fn register_user_image_region(
+        &mut self,
+        image: Arc<dyn ImageViewAbstract + Send + Sync>,
+        uv_rect: egui::Rect,
+        sampler_create_info: SamplerCreateInfo,
+    ) -> Result<egui::TextureId, ImageRegistrationError> {
+        self.renderer().register_image_region(image, uv_rect, sampler_create_info)
+    }
+
+    /// Registers an image file (e.g. png) as an sRGB user image with linear filtering, returning
+    /// a `RegisteredImage` that shows it at the right size and unregisters it when dropped.
+    /// The upload is submitted with the next draw, see `try_register_user_image`.
+    pub fn load_image(
+        &mut self,
+        image_file_bytes: &[u8],
+    ) -> Result<RegisteredImage, ImageRegistrationError> {
+        let (image, upload) = immutable_texture_from_file_deferred(
+            &self.allocators,
+            self.renderer().upload_queue(false),
+            image_file_bytes,
+            Format::R8G8B8A8_SRGB,
+        )?;
+        let dimensions = image.dimensions().width_height();
+        let sampler_create_info = SamplerCreateInfo::simple_repeat_linear_no_mipmap();
+        let texture_id =
+            self.renderer().register_image_deferred(image, sampler_create_info.clone(), upload)?;
+        let source = RetainedSource::File { format: Format::R8G8B8A8_SRGB };
+        self.retain_image(texture_id, image_file_bytes, source, &sampler_create_info);
+        Ok(RegisteredImage::new(texture_id, dimensions, self.texture_registry()))
+    }
+
+    /// Registers a user image to be used by egui
+    /// - `image_file_bytes`: e.g. include_bytes!("./assets/tree.png")
+    /// - `format`: e.g. vulkano::format::Format::R8G8B8A8Unorm
+    /// - `sampler_create_info`: Sampler used for this image only, see `register_user_image_view`
+    ///
+    /// The upload doesn't block, it is submitted with the next draw ahead of the ui. Returns an
+    /// error if the image can't be decoded or created. Use `register_user_image_with_options`
+    /// for mipmaps.
+    pub fn try_register_user_image(
+        &mut self,
+        image_file_bytes: &[u8],
+        format: vulkano::format::Format,
+        sampler_create_info: SamplerCreateInfo,
+    ) -> Result<egui::TextureId, ImageRegistrationError> {
+        let (image, upload) = immutable_texture_from_file_deferred(
+            &self.allocators,
+            self.renderer().upload_queue(false),
+            image_file_bytes,
+            format,
+        )?;
+        let id =
+            self.renderer().register_image_deferred(image, sampler_create_info.clone(), upload)?;
+        let source = RetainedSource::File { format };
+        self.retain_image(id, image_file_bytes, source, &sampler_create_info);
+        Ok(id)
+    }
+
+    /// Like `try_register_user_image`, but panics if the image can't be decoded or created
+    #[deprecated(note = "use `try_register_user_image` instead")]
+    pub fn register_user_image(
+        &mut self,
+        image_file_bytes: &[u8],
+        format: vulkano::format::Format,
+        sampler_create_info: SamplerCreateInfo,
+    ) -> egui::TextureId {
+        self.try_register_user_image(image_file_bytes, format, sampler_create_info)
+            .unwrap_or_else(|err| panic!("Failed to create image: {}", err))
+    }
+
+    /// Registers a user image decoded from file bytes, with `options` choosing between an sRGB
+    /// and a linear image, flipping it vertically, premultiplying alpha and generating mipmaps.
+    /// With mipmaps, `sampler_create_info` is changed to filter linearly over all mip levels.
+    /// Any color type is converted to rgba, e.g. grayscale pngs or jpegs.
+    ///
+    /// Images larger than the device supports are scaled down to fit unless
+    /// `options.allow_downscale` is false, so the image's final dimensions are returned with its
+    /// `TextureId`.
+    pub fn register_user_image_with_options(
+        &mut self,
+        image_file_bytes: &[u8],
+        sampler_create_info: SamplerCreateInfo,
+        options: TextureLoadOptions,
+    ) -> Result<(egui::TextureId, [u32; 2]), ImageRegistrationError> {
+        let (image, upload) = immutable_texture_from_file_with_options_deferred(
+            &self.allocators,
+            self.renderer().upload_queue(options.mipmaps),
+            image_file_bytes,
+            options,
+        )?;
+        let dimensions = image.dimensions().width_height();
+        let sampler_create_info = mipmap_sampler(sampler_create_info, options.mipmaps);
+        let id =
+            self.renderer().register_image_deferred(image, sampler_create_info.clone(), upload)?;
+        let source = RetainedSource::FileWithOptions(options);
+        self.retain_image(id, image_file_bytes, source, &sampler_create_info);
+        Ok((id, dimensions))
+    }
+
+    /// Like `try_register_user_image`, but returns the unsubmitted upload future instead of
+    /// submitting it with the next draw, e.g. to join it with your own work. The returned
+    /// future must be executed before the image is drawn, e.g. by joining it with the
+    /// `before_future` of `draw_on_image`.
+    pub fn register_user_image_async(
+        &mut self,
+        image_file_bytes: &[u8],
+        format: vulkano::format::Format,
+        sampler_create_info: SamplerCreateInfo,
+    ) -> Result<(egui::TextureId, Box<dyn GpuFuture>), ImageRegistrationError> {
+        let (image, upload) = immutable_texture_from_file_deferred(
+            &self.allocators,
+            self.renderer().upload_queue(false),
+            image_file_bytes,
+            format,
+        )?;
+        let id = self.renderer().register_image(
+            image,
+            sampler_create_info.clone(),
+            UserImageOptions::default(),
+        )?;
+        let upload = self.renderer().submit_upload(upload).map_err(|err| {
+            self.renderer().unregister_image(id);
+            ImageRegistrationError::from(err)
+        })?;
+        let source = RetainedSource::File { format };
+        self.retain_image(id, image_file_bytes, source, &sampler_create_info);
+        Ok((id, upload))
+    }
+
+    /// Registers a user image from raw pixel data (e.g. procedurally generated) to be used by egui
+    /// - `image_byte_data`: Tightly packed rows of `dimensions` pixels in `format`
+    /// - `format`: An uncompressed color format, e.g. `R8G8B8A8_SRGB`. Single channel images are
+    ///   drawn as grayscale, see `SingleChannelMode`.
+    ///
+    /// Returns an error if the format is unsupported, `image_byte_data` doesn't match
+    /// `dimensions` or a dimension is zero.
+    pub fn try_register_user_image_from_bytes(
+        &mut self,
+        image_byte_data: &[u8],
+        dimensions: [u32; 2],
+        format: vulkano::format::Format,
+        sampler_create_info: SamplerCreateInfo,
+    ) -> Result<egui::TextureId, ImageRegistrationError> {
+        let (image, upload) = immutable_texture_from_bytes_deferred(
+            &self.allocators,
+            self.renderer().upload_queue(false),
+            image_byte_data,
+            dimensions,
+            format,
+            false,
+        )?;
+        let id =
+            self.renderer().register_image_deferred(image, sampler_create_info.clone(), upload)?;
+        let source = RetainedSource::Bytes { dimensions, format };
+        self.retain_image(id, image_byte_data, source, &sampler_create_info);
+        Ok(id)
+    }
+
+    /// Like `try_register_user_image_from_bytes`, but panics if the image can't be created
+    #[deprecated(note = "use `try_register_user_image_from_bytes` instead")]
+    pub fn register_user_image_from_bytes(
+        &mut self,
+        image_byte_data: &[u8],
+        dimensions: [u32; 2],
+        format: vulkano::format::Format,
+        sampler_create_info: SamplerCreateInfo,
+    ) -> egui::TextureId {
+        self.try_register_user_image_from_bytes(
+            image_byte_data,
+            dimensions,
+            format,
+            sampler_create_info,
+        )
+        .unwrap_or_else(|err| panic!("Failed to create image: {}", err))
+    }
+
+    /// Registers a user image from tightly packed sRGB rgba8 pixels, e.g. an `image::RgbaImage`
+    /// from your asset pipeline or a regenerated thumbnail, without encoding & decoding a file.
+    ///
+    /// Returns an error if `rgba` doesn't match `dimensions` or a dimension is zero.
+    pub fn register_user_image_from_rgba(
+        &mut self,
+        rgba: &[u8],
+        dimensions: [u32; 2],
+        sampler_create_info: SamplerCreateInfo,
+    ) -> Result<egui::TextureId, ImageRegistrationError> {
+        self.try_register_user_image_from_bytes(
+            rgba,
+            dimensions,
+            Format::R8G8B8A8_SRGB,
+            sampler_create_info,
+        )
+    }
+
+    /// Registers many images of tightly packed sRGB rgba8 pixels with their dimensions, e.g.
+    /// the thumbnails of an asset browser, see `register_user_image_from_rgba`. All uploads are
+    /// recorded into one command buffer, submitted once with the next draw (or returned by
+    /// `prepare`). Each texture gets its own sampler from `sampler_create_info`.
+    ///
+    /// Registration is all or nothing: if an image fails, e.g. because its data doesn't match its
+    /// dimensions, no image is registered and `ImageRegistrationError::InBatch` tells the index
+    /// of the failing image.
+    pub fn register_user_images_from_rgba<'a>(
+        &mut self,
+        images: impl IntoIterator<Item = (&'a [u8], [u32; 2])>,
+        sampler_create_info: SamplerCreateInfo,
+    ) -> Result<Vec<egui::TextureId>, ImageRegistrationError> {
+        let pixels: Vec<_> = images.into_iter().collect();
+        let (images, upload) = immutable_textures_from_bytes_deferred(
+            &self.allocators,
+            self.renderer().upload_queue(false),
+            pixels.iter().copied(),
+            Format::R8G8B8A8_SRGB,
+        )?;
+        let ids = self.renderer().register_images_deferred(
+            images,
+            sampler_create_info.clone(),
+            upload,
+        )?;
+        for (&id, (rgba, dimensions)) in ids.iter().zip(pixels) {
+            let source = RetainedSource::Bytes { dimensions, format: Format::R8G8B8A8_SRGB };
+            self.retain_image(id, rgba, source, &sampler_create_info);
+        }
+        Ok(ids)
+    }
+
+    /// Registers an already decoded image as an sRGB user image, see
+    /// `register_user_image_from_rgba`. Images that aren't rgba8 are converted first.
+    pub fn register_user_image_from_dynamic(
+        &mut self,
+        image: &image::DynamicImage,
+        sampler_create_info: SamplerCreateInfo,
+    ) -> Result<egui::TextureId, ImageRegistrationError> {
+        match image.as_rgba8() {
+            Some(rgba) => self.register_user_image_from_rgba(
+                rgba.as_raw(),
+                [rgba.width(), rgba.height()],
+                sampler_create_info,
+            ),
+            None => {
+                let rgba = image.to_rgba8();
+                self.register_user_image_from_rgba(
+                    rgba.as_raw(),
+                    [rgba.width(), rgba.height()],
+                    sampler_create_info,
+                )
+            }
+        }
+    }
+
+    /// Returns a handle for creating user images on other threads. Call `finalize_uploads` each
+    /// frame to get the `TextureId`s of finished uploads.
+    pub fn texture_uploader(&mut self) -> TextureUploader {
+        let (upload_queue, queue) = {
+            let renderer = self.renderer();
+            (renderer.upload_queue(false), renderer.queue())
+        };
+        let allocators = &self.allocators;
+        self.uploader
+            .get_or_insert_with(|| TextureUploader::new(allocators.clone(), upload_queue, queue))
+            .clone()
+    }
+
+    /// Registers the images of `TextureUploader` uploads the GPU has finished, returning each
+    /// upload's `TextureId` or registration error. Uploads still in flight are returned by a
+    /// later call, so call this each frame before building the ui. Cheap if nothing finished.
+    pub fn finalize_uploads(
+        &mut self,
+    ) -> Vec<(UploadToken, Result<egui::TextureId, ImageRegistrationError>)> {
+        let Some(uploader) = &self.uploader else {
+            return vec![];
+        };
+        uploader
+            .take_finished()
+            .into_iter()
+            .map(|upload| {
+                let id = self.renderer().register_image(
+                    upload.image,
+                    upload.sampler_create_info,
+                    UserImageOptions::default(),
+                );
+                (upload.token, id)
+            })
+            .collect()
+    }
+
+    /// Replaces the image of a registered user image, keeping its `TextureId`, sampler and
+    /// options.
+    /// The previous image is dropped after `GuiConfig::frames_in_flight` more frames are drawn.
+    pub fn update_user_image_view(
+        &mut self,
+        texture_id: egui::TextureId,
+        image: Arc<dyn ImageViewAbstract + Send + Sync>,
+    ) -> Result<(), ImageRegistrationError> {
+        self.renderer().update_image(texture_id, image)?;
+        // Now an image view, which `rebuild` can't restore
+        let owner = self.renderer().variant_base(texture_id).unwrap_or(texture_id);
+        if let Some(images) = &mut self.retained_images {
+            images.remove(&owner);
+        }
+        Ok(())
+    }
+
+    /// Uploads new pixel data to a registered user image, keeping its `TextureId`.
+    /// `image_byte_data` must match the size and format of the registered image, whose view
+    /// needs transfer dst usage if you registered it yourself. The copy is ordered before the
+    /// next draw, like `update_user_image_region`.
+    pub fn update_user_image(
+        &mut self,
+        texture_id: egui::TextureId,
+        image_byte_data: &[u8],
+    ) -> Result<(), ImageRegistrationError> {
+        self.renderer().update_image_bytes(texture_id, image_byte_data)
+    }
+
+    /// Uploads new pixel data to a region of a registered user image, e.g. the strip of a live
+    /// waveform that changed, without re-uploading the whole image. `image_byte_data` must be
+    /// tightly packed rows of `extent` pixels in the image's format.
+    ///
+    /// The copy is ordered before the next draw, so it is visible in that frame. Images
+    /// registered from bytes, files or raw data can be updated, registered image views need
+    /// transfer dst usage. Only the first mip level is updated.
+    pub fn update_user_image_region(
+        &mut self,
+        texture_id: egui::TextureId,
+        offset: [u32; 2],
+        extent: [u32; 2],
+        image_byte_data: &[u8],
+    ) -> Result<(), ImageRegistrationError> {
+        self.renderer().update_image_region(texture_id, offset, extent, image_byte_data)
+    }
+
+    /// Returns whether `texture_id` is registered, e.g. to validate ids you persisted. User
+    /// texture ids are never reused, so ids of unregistered or evicted images stay invalid, and
+    /// meshes still using them are drawn as a magenta placeholder.
+    pub fn is_registered(&self, texture_id: egui::TextureId) -> bool {
+        self.renderer().is_registered(context_texture_id(self.context, texture_id))
+    }
+
+    /// Returns the index of the last drawn frame, e.g. to pass to `frame_completed` once the GPU
+    /// has finished it. Guis sharing a renderer share the indices. `None` before the first draw.
+    pub fn last_frame_index(&self) -> Option<u64> {
+        self.renderer().last_frame_index()
+    }
+
+    /// Tells the renderer that the GPU has finished the frames up to `frame_index` (see
+    /// `last_frame_index`), e.g. once the fence of the frame's future is signaled. From then on
+    /// images of unregistered and replaced textures are dropped as soon as the frames using them
+    /// have finished, rather than after `GuiConfig::frames_in_flight` more frames.
+    ///
+    /// Vertex & index buffers need no reporting: command buffers keep the buffers they draw from
+    /// alive, so they are never reused while in flight. In debug builds, reporting a frame that
+    /// wasn't drawn yet or frames out of order panics.
+    pub fn frame_completed(&mut self, frame_index: u64) {
+        self.renderer().frame_completed(frame_index);
+    }
+
+    /// Unregisters a user image. The image is dropped after `GuiConfig::frames_in_flight` more
+    /// frames are drawn, so frames still rendering can finish using it.
+    pub fn unregister_user_image(&mut self, texture_id: egui::TextureId) {
+        self.renderer().unregister_image(texture_id);
+        if let Some(images) = &mut self.retained_images {
+            images.remove(&texture_id);
+        }
+    }
+
+    /// Returns the approximate GPU memory of registered user images in bytes (size × bytes per
+    /// pixel, including mip levels). Images shared by several textures count once.
+    pub fn texture_memory_used(&self) -> u64 {
+        self.renderer().texture_memory_used()
+    }
+
+    /// Returns the number of registered user textures, including image regions and variants
+    pub fn texture_count(&self) -> usize {
+        self.renderer().texture_count()
+    }
+
+    /// Limits the memory of user images to `budget` bytes, e.g. for thumbnails registered on
+    /// demand. Once exceeded at the end of a draw, the least recently drawn user textures are
+    /// unregistered until the rest fits. Textures drawn in the same frame and egui's own textures
+    /// (e.g. the font atlas) are never evicted. Evicted textures egui still references are drawn
+    /// as a magenta placeholder, see `set_texture_eviction_handler` to register them again.
+    /// `None` (the default) disables the budget.
+    pub fn set_texture_budget(&mut self, budget: Option<u64>) {
+        self.renderer().set_texture_budget(budget);
+    }
+
+    /// Sets a handler called with each user texture evicted by the texture budget, after the
+    /// draw that evicted it
+    pub fn set_texture_eviction_handler(
+        &mut self,
+        handler: impl FnMut(egui::TextureId) + Send + 'static,
+    ) {
+        self.eviction_handler = Some(Box::new(handler));
+    }
+
+    fn notify_evicted_textures(&mut self) {
+        let evicted = self.renderer().take_evicted_textures();
+        if let Some(handler) = &mut self.eviction_handler {
+            for id in evicted {
+                handler(id);
+            }
+        }
+    }
+
+    /// Returns `true` if egui wanted pointer input (e.g. the pointer was over an egui window or
+    /// dragging a widget) at the end of the last drawn frame. Stable until the next frame ends,
+    /// so it can be polled once per frame to suppress your own pointer handling.
+    pub fn wants_pointer_input(&self) -> bool {
+        self.wants_pointer_input
+    }
+
+    /// Returns `true` if egui wanted keyboard input (e.g. a text field had focus) at the end of
+    /// the last drawn frame, see `wants_pointer_input`
+    pub fn wants_keyboard_input(&self) -> bool {
+        self.wants_keyboard_input
+    }
+
+    /// Returns the regions the last ended frame painted in points, e.g. to make the rest of a
+    /// transparent overlay window click through with `Window::set_cursor_hittest`. Regions are
+    /// the bounds of egui's shapes within their clip rects, so menus, popups and tooltips are
+    /// included like windows and panels, and regions within others are left out. Invisible
+    /// frames and the software cursor cover nothing. With a ui viewport, regions are relative
+    /// to it, like egui's rects.
+    pub fn ui_covered_regions(&self) -> &[egui::Rect] {
+        &self.covered_regions
+    }
+
+    /// Whether `pos` (in points, like egui's rects) is in one of the `ui_covered_regions`. Unlike
+    /// `is_pointer_over_ui`, this can test any position, e.g. each cursor move of the event loop,
+    /// without a new frame.
+    pub fn pointer_over_ui_at(&self, pos: egui::Pos2) -> bool {
+        self.covered_regions.iter().any(|region| region.contains(pos))
+    }
+
+    /// Returns `true` if the pointer was over an egui area at the end of the last drawn frame,
+    /// see `wants_pointer_input`
+    pub fn is_pointer_over_ui(&self) -> bool {
+        self.pointer_over_ui
+    }
+
+    /// Returns how far touchpad rotate gestures (macOS) turned since the previous frame, in
+    /// radians with positive values turning clockwise on screen like egui's
+    /// `MultiTouchInfo::rotation_delta`. Stable during a frame, zero on other platforms.
+    pub fn touchpad_rotation_delta(&self) -> f32 {
+        self.rotation_delta
+    }
+
+    /// Returns counters of the renderer's work for the last drawn frame (meshes, draw calls,
+    /// uploads etc.), e.g. to show in a debug overlay
+    pub fn last_render_stats(&self) -> RenderStats {
+        self.renderer().last_stats()
+    }
+
+    /// Returns the CPU time spent in each phase of the last frame drawn with `draw_on_image`,
+    /// `draw_on_texture`, `draw_on_subpass_image` or `draw_into`, e.g. to find where frame time
+    /// regressed. Input and ui times accumulate from the previous draw until this one.
+    pub fn last_frame_timings(&self) -> FrameTimings {
+        self.last_timings
+    }
+
+    /// Enables or disables measuring how long drawing the ui takes on the GPU, see
+    /// `last_gpu_time`. Only drawing with the integration's own render pass (`draw_on_image` or
+    /// `draw_on_texture`) is measured.
+    pub fn enable_gpu_profiling(&mut self, enabled: bool) {
+        self.renderer().set_gpu_profiling(enabled);
+    }
+
+    /// Returns the GPU time of the most recent drawn frame whose results are available, usually
+    /// from a frame or two ago, without waiting for the GPU. `None` if profiling is disabled,
+    /// the queue doesn't support timestamps or no results are available yet.
+    pub fn last_gpu_time(&mut self) -> Option<Duration> {
+        self.renderer().last_gpu_time()
+    }
+
+    /// Returns egui's platform output of the last drawn frame, e.g. `copied_text`, `open_url`,
+    /// `events` or `text_cursor_pos`. The integration handles the cursor icon, clipboard, urls
+    /// and IME itself, this lets you observe and act on the output too.
+    pub fn last_output(&self) -> &egui::PlatformOutput {
+        &self.last_output
+    }
+
+    /// Serializes egui's memory (window positions, collapsed headers, scroll offsets etc.) to
+    /// json, e.g. to restore the ui layout on the next run with `load_memory`. Can be called at
+    /// any time, e.g. on `Event::LoopDestroyed`.
+    #[cfg(feature = "persistence")]
+    pub fn save_memory(&self) -> Result<String, serde_json::Error> {
+        self.egui_ctx.memory(serde_json::to_string)
+    }
+
+    /// Restores egui's memory saved with `save_memory`. Fields missing from `memory` (e.g. saved
+    /// by another egui version) keep their defaults. If `memory` can't be deserialized at all,
+    /// the current memory is kept and the error returned.
+    #[cfg(feature = "persistence")]
+    pub fn load_memory(&mut self, memory: &str) -> Result<(), serde_json::Error> {
+        let memory: egui::Memory = serde_json::from_str(memory)?;
+        self.egui_ctx.memory_mut(|current| *current = memory);
+        Ok(())
+    }
+
+    /// Saves egui's memory to a json file, see `save_memory`
+    #[cfg(feature = "persistence")]
+    pub fn save_memory_to_file(&self, path: impl AsRef<std::path::Path>) -> std::io::Result<()> {
+        std::fs::write(path, self.save_memory()?)
+    }
+
+    /// Loads egui's memory from a json file, see `load_memory`
+    #[cfg(feature = "persistence")]
+    pub fn load_memory_from_file(
+        &mut self,
+        path: impl AsRef<std::path::Path>,
+    ) -> std::io::Result<()> {
+        let memory = std::fs::read_to_string(path)?;
+        Ok(self.load_memory(&memory)?)
+    }
+
+    /// Access egui's context (which can be used to e.g. set fonts, visuals etc)
+    pub fn context(&self) -> egui::Context {
+        self.egui_ctx.clone()
+    }
+}
+
+impl Drop for Gui {
+    fn drop(&mut self) {
+        // Frees the font atlas & targets of this gui in a renderer other guis may still use
+        self.renderer().remove_context(self.context);
+    }
+}
+
+// Helper to retrieve Window from surface object
 fn surface_window(surface: &Surface) -> &Window {
     surface.object().unwrap().downcast_ref::<Window>().unwrap()
 }
+
+/// Frame time of the refresh rate of the window's current monitor, if known
+fn monitor_frame_time(window: &Window) -> Option<f32> {
+    let millihertz = window.current_monitor()?.refresh_rate_millihertz()?;
+    (millihertz > 0).then(|| 1000.0 / millihertz as f32)
+}
+
+/// Moves the axis-aligned textured quads of `clipped_meshes` by less than a pixel, so they start
+/// on a whole pixel, see `Gui::set_pixel_snapping`. Quads are recognized by the indices & vertex
+/// order of `Mesh::add_rect_with_uv`, which glyphs and images are added with. Quads sampling
+/// only egui's white texel are solid rects, whose feathering must stay where it is.
+fn snap_textured_quads(clipped_meshes: &mut [ClippedPrimitive], pixels_per_point: f32) {
+    if pixels_per_point.fract() != 0.0 {
+        return;
+    }
+    for ClippedPrimitive { primitive, .. } in clipped_meshes {
+        let egui::epaint::Primitive::Mesh(mesh) = primitive else {
+            continue;
+        };
+        let mut i = 0;
+        while i + 6 <= mesh.indices.len() {
+            let base = mesh.indices[i];
+            let quad = [base, base + 1, base + 2, base + 2, base + 1, base + 3];
+            if mesh.indices[i..i + 6] != quad {
+                i += 3;
+                continue;
+            }
+            i += 6;
+            let Some(vertices) = mesh.vertices.get_mut(base as usize..base as usize + 4) else {
+                continue;
+            };
+            let [left_top, right_top, left_bottom, right_bottom] =
+                [vertices[0].pos, vertices[1].pos, vertices[2].pos, vertices[3].pos];
+            let axis_aligned = left_top.y == right_top.y
+                && left_bottom.y == right_bottom.y
+                && left_top.x == left_bottom.x
+                && right_top.x == right_bottom.x;
+            if !axis_aligned || vertices.iter().all(|v| v.uv == egui::epaint::WHITE_UV) {
+                continue;
+            }
+            let min = left_top.to_vec2() * pixels_per_point;
+            let offset = (min.round() - min) / pixels_per_point;
+            for vertex in vertices {
+                vertex.pos += offset;
+            }
+        }
+    }
+}
+
+/// Bounds of what `shapes` paint within their clip rects, leaving out bounds within others, see
+/// `Gui::ui_covered_regions`. Shapes within `software_cursor` are the cursor's.
+fn covered_regions(
+    shapes: &[egui::epaint::ClippedShape],
+    software_cursor: Option<egui::Rect>,
+) -> Vec<egui::Rect> {
+    let mut regions: Vec<egui::Rect> = vec![];
+    for egui::epaint::ClippedShape(clip_rect, shape) in shapes {
+        match shape {
+            egui::Shape::Callback(callback) if callback.callback.is::<LayerMarker>() => continue,
+            egui::Shape::Rect(rect)
+                if rect.fill == egui::Color32::TRANSPARENT && rect.stroke.is_empty() =>
+            {
+                continue
+            }
+            _ => (),
+        }
+        let rect = shape.visual_bounding_rect().intersect(*clip_rect);
+        if !rect.is_positive()
+            || software_cursor.map_or(false, |cursor| cursor.contains_rect(rect))
+            || regions.iter().any(|region| region.contains_rect(rect))
+        {
+            continue;
+        }
+        regions.retain(|region| !rect.contains_rect(*region));
+        regions.push(rect);
+    }
+    regions
+}
+
+/// Whether `event` is pointer input, see `Gui::set_pointer_passthrough`
+fn is_pointer_event(event: &WindowEvent<'_>) -> bool {
+    matches!(
+        event,
+        WindowEvent::CursorMoved { .. }
+            | WindowEvent::CursorEntered { .. }
+            | WindowEvent::CursorLeft { .. }
+            | WindowEvent::MouseInput { .. }
+            | WindowEvent::MouseWheel { .. }
+            | WindowEvent::Touch(_)
+            | WindowEvent::TouchpadMagnify { .. }
+            | WindowEvent::TouchpadRotate { .. }
+            | WindowEvent::TouchpadPressure { .. }
+    )
+}
+
+// Helper to sample all mip levels of images created with mipmaps
+pub(crate) fn mipmap_sampler(
+    sampler_create_info: SamplerCreateInfo,
+    mipmaps: bool,
+) -> SamplerCreateInfo {
+    if mipmaps {
+        SamplerCreateInfo {
+            mipmap_mode: SamplerMipmapMode::Linear,
+            lod: 0.0..=LOD_CLAMP_NONE,
+            ..sampler_create_info
+        }
+    } else {
+        sampler_create_info
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use egui::{Color32, LayerId, Stroke};
+    use vulkano::{
+        device::{DeviceCreateInfo, QueueCreateInfo, QueueFlags},
+        instance::{Instance, InstanceCreateInfo},
+        sync, VulkanLibrary,
+    };
+
+    use super::*;
+
+    /// A graphics queue of the first Vulkan device found, `None` on machines without one
+    fn graphics_queue() -> Option<Arc<Queue>> {
+        let library = VulkanLibrary::new().ok()?;
+        let instance = Instance::new(library, InstanceCreateInfo {
+            enumerate_portability: true,
+            ..Default::default()
+        })
+        .ok()?;
+        let (physical_device, queue_family_index) =
+            instance.enumerate_physical_devices().ok()?.find_map(|physical_device| {
+                let index = physical_device
+                    .queue_family_properties()
+                    .iter()
+                    .position(|family| family.queue_flags.intersects(QueueFlags::GRAPHICS))?;
+                Some((physical_device, index as u32))
+            })?;
+        let (_device, mut queues) = Device::new(physical_device, DeviceCreateInfo {
+            queue_create_infos: vec![QueueCreateInfo { queue_family_index, ..Default::default() }],
+            ..Default::default()
+        })
+        .ok()?;
+        queues.next()
+    }
+
+    /// Rows of a `width` wide rgba8 image with any visible pixel
+    fn visible_rows(width: u32, pixels: &[u8]) -> Vec<usize> {
+        pixels
+            .chunks_exact(width as usize * 4)
+            .enumerate()
+            .filter(|(_, row)| row.chunks_exact(4).any(|pixel| pixel[3] > 2))
+            .map(|(y, _)| y)
+            .collect()
+    }
+
+    #[test]
+    #[ignore = "needs a Vulkan device"]
+    fn hairlines_cover_one_pixel_row_at_fractional_scale_factors() {
+        let queue = graphics_queue().expect("No Vulkan device");
+        for &scale_factor in &[1.0, 1.25, 1.5, 1.75] {
+            let dimensions = [120, 90];
+            let mut gui = Gui::new_headless(queue.clone(), dimensions, GuiConfig::default());
+            gui.set_scale_factor(Some(scale_factor as f64));
+            let row = 41;
+            gui.immediate_ui(|gui| {
+                let painter = gui.context().layer_painter(LayerId::background());
+                // Centered on the pixel row, 1 pixel wide
+                let y = (row as f32 + 0.5) / scale_factor;
+                let stroke = Stroke::new(1.0 / scale_factor, Color32::WHITE);
+                painter.hline(0.0..=dimensions[0] as f32 / scale_factor, y, stroke);
+            });
+            let (future, _) = gui.draw_on_texture(sync::now(queue.device().clone()), dimensions);
+            let (width, _, pixels) = gui.read_back_last_frame(future).unwrap();
+            assert_eq!(visible_rows(width, &pixels), vec![row], "at scale factor {}", scale_factor);
+        }
+    }
+
+    #[test]
+    #[ignore = "needs a Vulkan device"]
+    fn mesh_buffers_are_reused_across_frames() {
+        let queue = graphics_queue().expect("No Vulkan device");
+        let dimensions = [400, 300];
+        let min_mesh_buffer_sizes = MeshBufferSizes { vertex_bytes: 1024, index_bytes: 1024 };
+        let config = GuiConfig { min_mesh_buffer_sizes, ..Default::default() };
+        let mut gui = Gui::new_headless(queue.clone(), dimensions, config);
+        let mut allocations = vec![];
+        for _ in 0..10 {
+            gui.immediate_ui(|gui| {
+                egui::CentralPanel::default().show(&gui.context(), |ui| {
+                    egui::Grid::new("table").show(ui, |ui| {
+                        for row in 0..300 {
+                            ui.label(format!("Row {}", row));
+                            let _ = ui.button("Edit");
+                            ui.end_row();
+                        }
+                    });
+                });
+            });
+            let (future, _) = gui.draw_on_texture(sync::now(queue.device().clone()), dimensions);
+            gui.read_back_last_frame(future).unwrap();
+            allocations.push(gui.last_render_stats().mesh_buffer_allocations);
+        }
+        assert!(allocations[0] > 0, "The first frame allocates the buffers");
+        // The first frames may grow as egui's layout settles
+        assert!(allocations[3..].iter().all(|&allocations| allocations == 0), "{:?}", allocations);
+    }
+}
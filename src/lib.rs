@@ -7,11 +7,27 @@
 // notice may not be copied, modified, or distributed except
 // according to those terms.
 
+mod cursor;
+#[cfg(feature = "gamepad")]
+pub mod gamepad;
 mod integration;
 mod renderer;
+mod textures;
+mod uploader;
 mod utils;
+pub mod winit_input;
 
 pub use egui;
 pub use integration::*;
-pub use renderer::{CallbackContext, CallbackFn, RenderResources};
-pub use utils::{immutable_texture_from_bytes, immutable_texture_from_file};
+pub use renderer::{
+    CallbackContext, CallbackFn, GuiShaders, MeshBufferSizes, ParallelRecording, RenderResources,
+    RenderStats, SharedRenderer,
+};
+pub use textures::{RegisteredImage, TextureRegistry};
+pub use uploader::{TextureUploader, UploadToken};
+pub use utils::{
+    create_gui_render_pass, immutable_texture_from_bytes, immutable_texture_from_file,
+    immutable_texture_from_file_with_options, premultiplied_alpha_blend, read_texture_to_vec,
+    ClearMode, EguiVulkanoError, FontError, GuiCreationError, ImageRegistrationError,
+    ReadbackError, RenderPassOptions, SingleChannelMode, TextureLoadOptions, UserImageOptions,
+};
@@ -8,11 +8,13 @@
 // according to those terms.
 
 use std::{
-    convert::{TryFrom, TryInto},
-    sync::Arc,
+    cell::Cell,
+    convert::TryInto,
+    sync::{Arc, Mutex, Weak},
+    time::{Duration, Instant},
 };
 
-use ahash::AHashMap;
+use ahash::{AHashMap, AHashSet};
 use egui::{
     epaint::{Mesh, Primitive},
     ClippedPrimitive, PaintCallbackInfo, Rect, TexturesDelta,
@@ -23,25 +25,29 @@ use vulkano::{
         Buffer, BufferContents, BufferCreateInfo, BufferUsage, Subbuffer,
     },
     command_buffer::{
-        allocator::StandardCommandBufferAllocator, AutoCommandBufferBuilder, BlitImageInfo,
-        CommandBufferInheritanceInfo, CommandBufferUsage, CopyBufferToImageInfo, ImageBlit,
-        PrimaryAutoCommandBuffer, PrimaryCommandBufferAbstract, RenderPassBeginInfo,
+        allocator::{CommandBufferAllocator, StandardCommandBufferAllocator},
+        AutoCommandBufferBuilder, BlitImageInfo, BufferImageCopy, ClearColorImageInfo,
+        CommandBufferInheritanceInfo, CommandBufferUsage, CopyBufferInfo, CopyBufferToImageInfo,
+        ImageBlit, PrimaryAutoCommandBuffer, PrimaryCommandBufferAbstract, RenderPassBeginInfo,
         SecondaryAutoCommandBuffer, SubpassContents,
     },
     descriptor_set::{
         allocator::StandardDescriptorSetAllocator, layout::DescriptorSetLayout,
-        PersistentDescriptorSet, WriteDescriptorSet,
+        DescriptorSetCreationError, PersistentDescriptorSet, WriteDescriptorSet,
     },
     device::Queue,
     format::{Format, NumericType},
     image::{
-        view::ImageView, ImageAccess, ImageLayout, ImageUsage, ImageViewAbstract, ImmutableImage,
-        SampleCount,
+        view::ImageView, AttachmentImage, ImageAccess, ImageLayout, ImageUsage, ImageViewAbstract,
+        ImmutableImage, SampleCount,
+    },
+    memory::allocator::{
+        AllocationCreateInfo, AllocationCreationError, MemoryUsage, StandardMemoryAllocator,
     },
-    memory::allocator::{AllocationCreateInfo, MemoryUsage, StandardMemoryAllocator},
     pipeline::{
         graphics::{
-            color_blend::{AttachmentBlend, BlendFactor, ColorBlendState},
+            color_blend::{ColorBlendState, ColorComponents},
+            depth_stencil::DepthStencilState,
             input_assembly::InputAssemblyState,
             multisample::MultisampleState,
             rasterization::{CullMode as CullModeEnum, RasterizationState},
@@ -50,18 +56,542 @@ use vulkano::{
         },
         GraphicsPipeline, Pipeline, PipelineBindPoint,
     },
+    query::{QueryPool, QueryPoolCreateInfo, QueryResultFlags, QueryType},
     render_pass::{Framebuffer, FramebufferCreateInfo, RenderPass, Subpass},
     sampler::{Filter, Sampler, SamplerAddressMode, SamplerCreateInfo, SamplerMipmapMode},
-    sync::GpuFuture,
+    shader::ShaderModule,
+    sync::{FlushError, GpuFuture, PipelineStage},
     DeviceSize,
 };
 
-use crate::utils::Allocators;
+use crate::{
+    textures::TextureRegistry,
+    utils::{
+        check_device_support, create_gui_render_pass, max_image_dimension,
+        premultiplied_alpha_blend, user_image_view, Allocators, ClearMode, EguiVulkanoError,
+        GuiCreationError, ImageRegistrationError, RenderPassOptions, UserImageOptions,
+        WorkerThreads,
+    },
+};
 
 const VERTICES_PER_QUAD: DeviceSize = 4;
 const VERTEX_BUFFER_SIZE: DeviceSize = 1024 * 1024 * VERTICES_PER_QUAD;
 const INDEX_BUFFER_SIZE: DeviceSize = 1024 * 1024 * 2;
 
+/// Sizes in bytes the vertex & index buffers of frames start at, see
+/// `GuiConfig::min_mesh_buffer_sizes`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MeshBufferSizes {
+    pub vertex_bytes: DeviceSize,
+    pub index_bytes: DeviceSize,
+}
+
+impl Default for MeshBufferSizes {
+    fn default() -> Self {
+        MeshBufferSizes { vertex_bytes: VERTEX_BUFFER_SIZE, index_bytes: INDEX_BUFFER_SIZE }
+    }
+}
+
+/// Pool of vertex or index buffers (arenas) that frames' meshes are suballocated from. Arenas
+/// are reused once the frames using them are dropped, so frames in flight keep theirs. They are
+/// sized to fit the largest frame so far, but at least the floor.
+struct MeshBufferPool {
+    allocator: SubbufferAllocator,
+    floor: Cell<DeviceSize>,
+    largest_frame: Cell<DeviceSize>,
+    /// Arenas allocated so far, alive ones are tracked to tell reused from new ones
+    arenas: Cell<Vec<Weak<Buffer>>>,
+    arenas_created: Cell<usize>,
+}
+
+impl MeshBufferPool {
+    fn new(
+        allocator: &Arc<StandardMemoryAllocator>,
+        floor: DeviceSize,
+        buffer_usage: BufferUsage,
+        memory_usage: MemoryUsage,
+    ) -> MeshBufferPool {
+        MeshBufferPool {
+            allocator: SubbufferAllocator::new(allocator.clone(), SubbufferAllocatorCreateInfo {
+                arena_size: floor,
+                buffer_usage,
+                memory_usage,
+                ..Default::default()
+            }),
+            floor: Cell::new(floor),
+            largest_frame: Cell::new(0),
+            arenas: Cell::new(vec![]),
+            arenas_created: Cell::new(0),
+        }
+    }
+
+    // Shrinks or grows the arenas to the new floor, or the largest frame if it's larger
+    fn set_floor(&self, floor: DeviceSize) {
+        self.floor.set(floor);
+        let size = floor.max(self.largest_frame.get());
+        if size != self.allocator.arena_size() {
+            self.allocator.set_arena_size(size);
+        }
+    }
+
+    // Grows the arenas to fit the `bytes` of a frame, at most to `max_size`
+    fn fit_frame(&self, bytes: DeviceSize, max_size: DeviceSize) {
+        self.largest_frame.set(self.largest_frame.get().max(bytes));
+        let size = self.allocator.arena_size();
+        let grown = grown_arena_size(size, bytes, max_size);
+        if grown != size {
+            self.allocator.set_arena_size(grown);
+        }
+    }
+
+    fn allocate_slice<T: BufferContents>(
+        &self,
+        len: DeviceSize,
+    ) -> Result<Subbuffer<[T]>, AllocationCreationError> {
+        let subbuffer = self.allocator.allocate_slice::<T>(len)?;
+        let mut arenas = self.arenas.take();
+        arenas.retain(|arena| arena.strong_count() > 0);
+        if !arenas.iter().any(|arena| arena.as_ptr() == Arc::as_ptr(subbuffer.buffer())) {
+            arenas.push(Arc::downgrade(subbuffer.buffer()));
+            self.arenas_created.set(self.arenas_created.get() + 1);
+        }
+        self.arenas.set(arenas);
+        Ok(subbuffer)
+    }
+}
+
+/// Arena size fitting `bytes`, doubling `size` until it does but not beyond `max_size`. Arenas
+/// never shrink here.
+fn grown_arena_size(size: DeviceSize, bytes: DeviceSize, max_size: DeviceSize) -> DeviceSize {
+    if bytes <= size {
+        return size;
+    }
+    let mut grown = size.max(1);
+    while grown < bytes {
+        grown = grown.saturating_mul(2);
+    }
+    grown.min(max_size).max(size)
+}
+
+/// Vertex & index buffers of consecutive mesh batches of a frame, all of them unless they'd
+/// exceed `max_mesh_buffer_size`. Indices of meshes that can address their vertices with `u16`
+/// are packed as such, the rest as `u32`.
+struct MeshBuffers {
+    num_batches: usize,
+    vertices: Subbuffer<[EguiVertex]>,
+    indices_u16: Option<Subbuffer<[u16]>>,
+    indices_u32: Option<Subbuffer<[u32]>>,
+}
+
+/// Primary command buffer begun in the render pass, with the framebuffer dimensions & mesh buffers
+type FrameStart = (AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>, [u32; 2], Vec<MeshBuffers>);
+
+/// Future of a draw on an offscreen target & the target
+pub(crate) type TextureDraw =
+    (Box<dyn GpuFuture>, Arc<dyn ImageViewAbstract + Send + Sync + 'static>);
+
+/// Indices of one batch
+enum MeshIndices {
+    U16(Subbuffer<[u16]>),
+    U32(Subbuffer<[u32]>),
+}
+
+/// Rounds `rect` in points outwards to whole pixels of the framebuffer region at `offset` of
+/// `framebuffer_dimensions`, so fractional scale factors don't clip the last pixel. The scissor
+/// is zero-sized if the rect is outside the region.
+pub(crate) fn rect_scissor(
+    scale_factor: f32,
+    offset: [u32; 2],
+    framebuffer_dimensions: [u32; 2],
+    rect: Rect,
+) -> Scissor {
+    let min = rect.min;
+    let min = egui::Pos2 {
+        x: (min.x * scale_factor).floor().clamp(0.0, framebuffer_dimensions[0] as f32),
+        y: (min.y * scale_factor).floor().clamp(0.0, framebuffer_dimensions[1] as f32),
+    };
+    let max = rect.max;
+    let max = egui::Pos2 {
+        x: (max.x * scale_factor).ceil().clamp(min.x, framebuffer_dimensions[0] as f32),
+        y: (max.y * scale_factor).ceil().clamp(min.y, framebuffer_dimensions[1] as f32),
+    };
+    Scissor {
+        origin: [offset[0] + min.x as u32, offset[1] + min.y as u32],
+        dimensions: [(max.x - min.x) as u32, (max.y - min.y) as u32],
+    }
+}
+
+/// Scissor of `rect` like `rect_scissor`, or `None` if nothing of it is visible
+fn get_rect_scissor(
+    scale_factor: f32,
+    offset: [u32; 2],
+    framebuffer_dimensions: [u32; 2],
+    rect: Rect,
+) -> Option<Scissor> {
+    let scissor = rect_scissor(scale_factor, offset, framebuffer_dimensions, rect);
+    // Nothing is visible through a zero-area scissor
+    (scissor.dimensions[0] > 0 && scissor.dimensions[1] > 0).then_some(scissor)
+}
+
+/// Part of the framebuffer a frame's ui is recorded for, with the state bound for it
+struct DrawTarget {
+    scale_factor: f32,
+    offset: [u32; 2],
+    extent: [u32; 2],
+    viewport: Viewport,
+    push_constants: vs::PushConstants,
+}
+
+/// Offsets of the next batch in a frame's mesh buffers
+#[derive(Default)]
+struct BufferCursor {
+    /// Index of the mesh buffers the next batch is in, and of the batch within them
+    segment: usize,
+    batch: usize,
+    vertices: DeviceSize,
+    indices_u16: DeviceSize,
+    indices_u32: DeviceSize,
+}
+
+/// A mesh batch with its buffers, scissor & texture resolved, so it can be recorded without the
+/// renderer (e.g. on a worker thread)
+struct PreparedBatch {
+    texture_id: egui::TextureId,
+    desc_set: Arc<PersistentDescriptorSet>,
+    texture_conv: i32,
+    scissor: Scissor,
+    vertices: Subbuffer<[EguiVertex]>,
+    indices: MeshIndices,
+    num_indices: u32,
+}
+
+/// What is bound in the command buffer being recorded. Pipeline, viewport & push constants are
+/// only bound when needed, which is once per command buffer unless paint callbacks change them,
+/// and descriptor sets when the texture changes.
+struct RecordState {
+    needs_rebind: bool,
+    bound_texture: Option<egui::TextureId>,
+    push_constants: vs::PushConstants,
+}
+
+impl RecordState {
+    fn new(push_constants: vs::PushConstants) -> RecordState {
+        RecordState { needs_rebind: true, bound_texture: None, push_constants }
+    }
+}
+
+// Records the draw of a batch, binding what isn't bound yet
+fn record_batch<L, A: CommandBufferAllocator>(
+    pipeline: &Arc<GraphicsPipeline>,
+    viewport: &Viewport,
+    state: &mut RecordState,
+    batch: &PreparedBatch,
+    builder: &mut AutoCommandBufferBuilder<L, A>,
+) -> Result<(), EguiVulkanoError> {
+    if state.needs_rebind {
+        builder
+            .bind_pipeline_graphics(pipeline.clone())
+            .set_viewport(0, vec![viewport.clone()])
+            .push_constants(pipeline.layout().clone(), 0, state.push_constants);
+        state.needs_rebind = false;
+        state.bound_texture = None;
+    }
+    if state.bound_texture != Some(batch.texture_id) {
+        builder.bind_descriptor_sets(
+            PipelineBindPoint::Graphics,
+            pipeline.layout().clone(),
+            0,
+            batch.desc_set.clone(),
+        );
+        state.bound_texture = Some(batch.texture_id);
+        if batch.texture_conv != state.push_constants.texture_conv {
+            state.push_constants.texture_conv = batch.texture_conv;
+            builder.push_constants(pipeline.layout().clone(), 0, state.push_constants);
+        }
+    }
+    builder.set_scissor(0, vec![batch.scissor]).bind_vertex_buffers(0, batch.vertices.clone());
+    match &batch.indices {
+        MeshIndices::U16(indices) => builder.bind_index_buffer(indices.clone()),
+        MeshIndices::U32(indices) => builder.bind_index_buffer(indices.clone()),
+    };
+    builder.draw_indexed(batch.num_indices, 1, 0, 0, 0)?;
+    Ok(())
+}
+
+// Creates a builder for a secondary command buffer executed inside `subpass`
+fn secondary_builder(
+    allocator: &StandardCommandBufferAllocator,
+    queue_family_index: u32,
+    subpass: &Subpass,
+) -> Result<AutoCommandBufferBuilder<SecondaryAutoCommandBuffer>, EguiVulkanoError> {
+    Ok(AutoCommandBufferBuilder::secondary(
+        allocator,
+        queue_family_index,
+        CommandBufferUsage::MultipleSubmit,
+        CommandBufferInheritanceInfo {
+            render_pass: Some(subpass.clone().into()),
+            ..Default::default()
+        },
+    )?)
+}
+
+/// Records frames with many meshes on worker threads, see `GuiConfig::parallel_recording`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ParallelRecording {
+    /// Threads recording a frame, each into its own secondary command buffer
+    pub threads: usize,
+    /// Meshes a frame needs for being recorded in parallel, smaller frames are recorded on the
+    /// drawing thread, where handing them to the threads would cost more than it saves
+    pub min_meshes: usize,
+}
+
+impl Default for ParallelRecording {
+    fn default() -> Self {
+        ParallelRecording {
+            threads: std::thread::available_parallelism().map_or(1, |threads| threads.get()),
+            min_meshes: 2000,
+        }
+    }
+}
+
+/// Most vertices `u16` indices can address
+const U16_INDEXABLE_VERTICES: usize = u16::MAX as usize + 1;
+
+/// Consecutive drawable meshes with the same texture & clip rect, drawn with one draw call
+struct MeshBatch<'a> {
+    texture_id: egui::TextureId,
+    /// Whether the meshes reference an evicted or unknown user texture and are drawn as a
+    /// placeholder with the white texel of the font texture
+    placeholder: bool,
+    clip_rect: Rect,
+    meshes: Vec<&'a Mesh>,
+    num_vertices: usize,
+    num_indices: usize,
+}
+
+impl<'a> MeshBatch<'a> {
+    fn new(
+        texture_id: egui::TextureId,
+        placeholder: bool,
+        clip_rect: Rect,
+        mesh: &'a Mesh,
+    ) -> MeshBatch<'a> {
+        MeshBatch {
+            texture_id,
+            placeholder,
+            clip_rect,
+            meshes: vec![mesh],
+            num_vertices: mesh.vertices.len(),
+            num_indices: mesh.indices.len(),
+        }
+    }
+
+    /// Appends `mesh` if it's drawn with the same texture & clip rect and the batch stays within
+    /// the vertices `u16` indices can address. Returns whether it was appended.
+    fn try_append(
+        &mut self,
+        texture_id: egui::TextureId,
+        placeholder: bool,
+        clip_rect: Rect,
+        mesh: &'a Mesh,
+    ) -> bool {
+        if self.texture_id != texture_id
+            || self.placeholder != placeholder
+            || self.clip_rect != clip_rect
+            || self.num_vertices + mesh.vertices.len() > U16_INDEXABLE_VERTICES
+        {
+            return false;
+        }
+        self.meshes.push(mesh);
+        self.num_vertices += mesh.vertices.len();
+        self.num_indices += mesh.indices.len();
+        true
+    }
+
+    /// Whether the indices of the batch fit in `u16`, halving their size
+    fn uses_u16_indices(&self) -> bool {
+        self.num_vertices <= U16_INDEXABLE_VERTICES
+    }
+
+    /// Indices of all meshes, rebased onto the vertices of the batch
+    fn indices(&self) -> impl Iterator<Item = u32> + '_ {
+        let mut base = 0;
+        self.meshes.iter().flat_map(move |mesh| {
+            let mesh_base = base;
+            base += mesh.vertices.len() as u32;
+            mesh.indices.iter().map(move |i| i + mesh_base)
+        })
+    }
+}
+
+/// Primitive of a frame in paint order, with consecutive meshes batched
+enum DrawItem<'a> {
+    Meshes(MeshBatch<'a>),
+    Callback { clip_rect: Rect, callback: &'a egui::PaintCallback },
+}
+
+/// Color of meshes whose user texture was evicted to stay within the texture budget, or is
+/// unknown, e.g. unregistered
+const PLACEHOLDER_COLOR: egui::Color32 = egui::Color32::from_rgb(255, 0, 255);
+
+/// A texture registered as a region of an image view
+struct TextureRegion {
+    uv_rect: egui::Rect,
+    source: Arc<dyn ImageViewAbstract + Send + Sync + 'static>,
+}
+
+/// Values of the `texture_conv` push constant
+const TEXTURE_CONV_NONE: i32 = 0;
+const TEXTURE_CONV_LINEAR_FROM_SRGB: i32 = 1;
+const TEXTURE_CONV_SRGB_FROM_LINEAR: i32 = 2;
+
+/// Draws after which resources of an unregistered texture are dropped by default
+pub const DEFAULT_FRAMES_IN_FLIGHT: u64 = 2;
+
+/// Bit offset of the context in the managed texture ids the renderer stores
+const CONTEXT_TEXTURE_ID_SHIFT: u32 = 48;
+
+/// Maps a managed texture id of an egui context to the id the renderer stores it at, so each
+/// context sharing the renderer keeps its own font atlas. User textures are shared by all
+/// contexts and left as is.
+pub(crate) fn context_texture_id(context: u64, texture_id: egui::TextureId) -> egui::TextureId {
+    match texture_id {
+        egui::TextureId::Managed(id) => {
+            egui::TextureId::Managed(context << CONTEXT_TEXTURE_ID_SHIFT | id)
+        }
+        user => user,
+    }
+}
+
+/// Handle of a renderer shared by several `Gui`s, see `Gui::new_shared`. The contexts share the
+/// pipeline, buffer pools and user textures, while each keeps its own font atlas and offscreen
+/// target. The renderer is locked while a gui draws or registers textures, so guis on several
+/// threads take turns.
+#[derive(Clone)]
+pub struct SharedRenderer(pub(crate) Arc<Mutex<Renderer>>);
+
+/// Region of the target image in pixels the ui is drawn in, see `Gui::set_ui_viewport`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UiViewport {
+    pub offset: [u32; 2],
+    pub extent: [u32; 2],
+}
+
+impl UiViewport {
+    /// Clamps the region to an image of `dimensions`
+    fn clamped(self, dimensions: [u32; 2]) -> UiViewport {
+        let offset = [self.offset[0].min(dimensions[0]), self.offset[1].min(dimensions[1])];
+        UiViewport {
+            offset,
+            extent: [
+                self.extent[0].min(dimensions[0] - offset[0]),
+                self.extent[1].min(dimensions[1] - offset[1]),
+            ],
+        }
+    }
+
+    /// Whether the region contains the position in pixels
+    pub fn contains(&self, x: f64, y: f64) -> bool {
+        x >= self.offset[0] as f64
+            && y >= self.offset[1] as f64
+            && x < (self.offset[0] + self.extent[0]) as f64
+            && y < (self.offset[1] + self.extent[1]) as f64
+    }
+}
+
+/// Resources of a texture that was unregistered or replaced at `frame`
+struct RetiredTexture {
+    frame: u64,
+    _desc_set: Option<Arc<PersistentDescriptorSet>>,
+    _image: Option<Arc<dyn ImageViewAbstract + Send + Sync + 'static>>,
+    _sampler: Option<Arc<Sampler>>,
+}
+
+/// Retired textures, kept until the draws that may use them have finished. Draws are known to
+/// be finished once reported with `frame_completed`, or assumed to be after `frames_in_flight`
+/// more draws if no draw was reported finished within the last `frames_in_flight` draws.
+#[derive(Default)]
+struct RetiredTextures {
+    textures: Vec<RetiredTexture>,
+    /// Index of the last draw the GPU is known to have finished, see `Renderer::frame_completed`
+    completed_frame: Option<u64>,
+    /// Number of finished draws when `completed_frame` was reported
+    completed_at: u64,
+}
+
+impl RetiredTextures {
+    fn retire(&mut self, texture: RetiredTexture) {
+        self.textures.push(texture);
+    }
+
+    fn frame_completed(&mut self, frame_index: u64, frame_count: u64) {
+        self.completed_frame =
+            Some(self.completed_frame.map_or(frame_index, |c| c.max(frame_index)));
+        self.completed_at = frame_count;
+    }
+
+    // Drops the textures no draw in flight may still use after `frame_count` draws, with
+    // `frames_in_flight` draws in flight at most
+    fn drop_unused(&mut self, frame_count: u64, frames_in_flight: u64) {
+        match self.completed_frame {
+            // Textures retired before draw `frame` are used by earlier draws only
+            Some(completed) if frame_count - self.completed_at < frames_in_flight => {
+                self.textures.retain(|retired| retired.frame > completed + 1)
+            }
+            _ => self.textures.retain(|retired| frame_count - retired.frame < frames_in_flight),
+        }
+    }
+}
+
+/// Counters of the work done by the renderer for a frame, see `Gui::last_render_stats`. Uploads
+/// and descriptor sets created between frames (e.g. registering user images) count towards the
+/// next drawn frame.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RenderStats {
+    /// Meshes with vertices & an existing texture
+    pub meshes: usize,
+    /// Meshes skipped because their clip rect was empty
+    pub skipped_meshes: usize,
+    /// Meshes drawn with the draw call of the preceding mesh, because they share its texture &
+    /// clip rect. Without batching, there would be this many more draw calls.
+    pub batched_meshes: usize,
+    pub vertices: usize,
+    pub indices: usize,
+    pub draw_calls: usize,
+    /// Distinct textures bound
+    pub textures_bound: usize,
+    pub descriptor_sets_created: usize,
+    pub texture_uploads: usize,
+    /// Bytes of texture data, vertices & indices uploaded
+    pub bytes_uploaded: u64,
+    /// Vertex & index buffers allocated for the frame's meshes. Buffers are reused across
+    /// frames, so this is 0 unless the frame is larger than all before or more frames than
+    /// before are in flight.
+    pub mesh_buffer_allocations: usize,
+    /// CPU time spent uploading egui's textures and writing vertices & indices
+    pub upload_time: Duration,
+    /// CPU time spent recording & submitting the frame's commands, besides uploads
+    pub record_time: Duration,
+}
+
+/// Shaders replacing the built-in ones used for egui meshes, see `Gui::set_shaders`. Both
+/// stages have a `main` entry point and must match egui's pipeline:
+/// - Vertex inputs: `vec2 position` in points at location 0, `vec2 tex_coords` at location 1
+///   and the sRGB vertex color as `vec4 color` (0-1) at location 2
+/// - Push constants, declared alike in both stages: `vec2 screen_size` in points,
+///   `int need_srgb_conv` (1 when the target is UNORM and output must be sRGB encoded)
+///   and `int texture_conv` (see the built-in fragment shader)
+/// - Set 0, binding 0: `sampler2D` of the mesh's texture
+/// - The fragment shader writes premultiplied alpha to location 0, which is blended with
+///   `premultiplied_alpha_blend`
+///
+/// The built-in shaders at the end of `renderer.rs` are a starting point.
+#[derive(Clone, Default)]
+pub struct GuiShaders {
+    pub vertex: Option<Arc<ShaderModule>>,
+    pub fragment: Option<Arc<ShaderModule>>,
+}
+
 /// Should match vertex definition of egui
 #[repr(C)]
 #[derive(BufferContents, Vertex)]
@@ -76,35 +606,108 @@ pub struct EguiVertex {
 
 pub struct Renderer {
     gfx_queue: Arc<Queue>,
+    /// Separate queue for texture uploads, if set
+    transfer_queue: Option<Arc<Queue>>,
     render_pass: Option<Arc<RenderPass>>,
-    is_overlay: bool,
+    clear_mode: ClearMode,
     need_srgb_conv: bool,
 
-    #[allow(unused)]
     format: vulkano::format::Format,
     font_sampler: Arc<Sampler>,
 
-    allocators: Allocators,
-    vertex_buffer_pool: SubbufferAllocator,
-    index_buffer_pool: SubbufferAllocator,
+    allocators: Arc<Allocators>,
+    vertex_buffer_pool: MeshBufferPool,
+    index_buffer_pool: MeshBufferPool,
+    /// Device local vertex & index buffers, which are filled from the above pools as staging
+    /// buffers, if enabled
+    device_buffer_pools: Option<(MeshBufferPool, MeshBufferPool)>,
+    min_mesh_buffer_sizes: MeshBufferSizes,
     pipeline: Arc<GraphicsPipeline>,
+    /// Pipelines created for the subpasses egui was drawn in, reused for compatible subpasses
+    pipelines: Vec<(Subpass, Arc<GraphicsPipeline>)>,
+    /// User shaders the pipeline was created with
+    shaders: GuiShaders,
     subpass: Subpass,
 
+    /// Descriptor sets built once per texture, rebuilt when the texture gets a new image
     texture_desc_sets: AHashMap<egui::TextureId, Arc<PersistentDescriptorSet>>,
+    /// Images the descriptor sets sample, mirrored into `textures`
     texture_images: AHashMap<egui::TextureId, Arc<dyn ImageViewAbstract + Send + Sync + 'static>>,
-    next_native_tex_id: u64,
+    texture_samplers: AHashMap<egui::TextureId, Arc<Sampler>>,
+    texture_options: AHashMap<egui::TextureId, UserImageOptions>,
+    /// Uv rects of textures registered as a region of an image, and the image view they were
+    /// registered with
+    texture_regions: AHashMap<egui::TextureId, TextureRegion>,
+    /// Variants sampling the image of another user texture with their own sampler, by variant
+    /// id to the id of the texture owning the image, see `register_image_variant`
+    texture_variants: AHashMap<egui::TextureId, egui::TextureId>,
+    textures: Arc<TextureRegistry>,
+    /// Generation of `textures` whose user textures were last mirrored, see `sync_textures`
+    synced_generation: u64,
+    /// Resources of unregistered or replaced textures, kept until the frames that may use them
+    /// have finished
+    retired_textures: RetiredTextures,
+    frames_in_flight: u64,
+    /// Number of finished draws
+    frame_count: u64,
+
+    /// Framebuffers keyed by the identity of the final image view they were created for
+    framebuffers: AHashMap<usize, Arc<Framebuffer>>,
+    /// Render targets used when drawing to a texture, per context
+    offscreen_targets: AHashMap<u64, Arc<dyn ImageViewAbstract + Send + Sync + 'static>>,
+    /// Images last drawn on with the renderer's own render pass, per context
+    last_targets: AHashMap<u64, Arc<dyn ImageViewAbstract + 'static>>,
+    /// Context drawing or registering textures, see `set_context`
+    context: u64,
+    next_context: u64,
+    /// Number of contexts sharing the renderer, each drawing once per frame
+    context_count: u64,
+
+    /// Stats of the frame being drawn, in a cell so they can be counted while drawing
+    stats: Cell<RenderStats>,
+    last_stats: RenderStats,
+    gpu_timer: Option<GpuTimer>,
+    /// Uploads of registered user images that haven't been submitted yet, joined into the next
+    /// draw submission
+    pending_uploads: Option<Box<dyn GpuFuture + Send + Sync>>,
+    /// Region of the target the ui is drawn in, the whole target if `None`
+    ui_viewport: Option<UiViewport>,
+    parallel_recording: Option<ParallelRecording>,
+    recording_workers: Option<WorkerThreads>,
+    /// Frame each user texture was last drawn (or registered) in
+    texture_last_drawn: AHashMap<egui::TextureId, u64>,
+    /// Bytes of user textures above which the least recently drawn ones are evicted
+    texture_budget: Option<u64>,
+    /// Evicted user textures, drawn as placeholders while egui still references them
+    evicted_textures: AHashSet<egui::TextureId>,
+    /// Textures evicted since the last `take_evicted_textures`
+    newly_evicted: Vec<egui::TextureId>,
 }
 
+// Renderers are moved to render threads, so what they hold must stay `Send`
+const _: fn() = || {
+    fn assert_send<T: Send>() {}
+    assert_send::<Renderer>();
+};
+
 impl Renderer {
     pub fn new_with_subpass(
         gfx_queue: Arc<Queue>,
         final_output_format: Format,
         subpass: Subpass,
+        device_local_buffers: bool,
     ) -> Renderer {
+        // Output color conversion must match the attachment egui actually draws to
+        let final_output_format = subpass_color_format(&subpass).unwrap_or(final_output_format);
         let need_srgb_conv = final_output_format.type_color().unwrap() == NumericType::UNORM;
-        let allocators = Allocators::new_default(gfx_queue.device());
-        let (vertex_buffer_pool, index_buffer_pool) = Self::create_buffers(&allocators.memory);
-        let pipeline = Self::create_pipeline(gfx_queue.clone(), subpass.clone());
+        let allocators = Arc::new(Allocators::new_default(gfx_queue.device()));
+        let (vertex_buffer_pool, index_buffer_pool) =
+            Self::create_buffers(&allocators.memory, device_local_buffers);
+        let device_buffer_pools =
+            device_local_buffers.then(|| Self::create_device_buffers(&allocators.memory));
+        let pipeline =
+            Self::create_pipeline(gfx_queue.clone(), subpass.clone(), &GuiShaders::default())
+                .unwrap();
         let font_sampler = Sampler::new(gfx_queue.device().clone(), SamplerCreateInfo {
             mag_filter: Filter::Linear,
             min_filter: Filter::Linear,
@@ -115,16 +718,46 @@ impl Renderer {
         .unwrap();
         Renderer {
             gfx_queue,
+            transfer_queue: None,
             format: final_output_format,
             render_pass: None,
             vertex_buffer_pool,
             index_buffer_pool,
+            device_buffer_pools,
+            min_mesh_buffer_sizes: MeshBufferSizes::default(),
+            pipelines: vec![(subpass.clone(), pipeline.clone())],
             pipeline,
+            shaders: GuiShaders::default(),
             subpass,
             texture_desc_sets: AHashMap::default(),
             texture_images: AHashMap::default(),
-            next_native_tex_id: 0,
-            is_overlay: false,
+            texture_samplers: AHashMap::default(),
+            texture_options: AHashMap::default(),
+            texture_regions: AHashMap::default(),
+            texture_variants: AHashMap::default(),
+            textures: Arc::new(TextureRegistry::new()),
+            synced_generation: 0,
+            retired_textures: RetiredTextures::default(),
+            frames_in_flight: DEFAULT_FRAMES_IN_FLIGHT,
+            frame_count: 0,
+            framebuffers: AHashMap::default(),
+            offscreen_targets: AHashMap::default(),
+            last_targets: AHashMap::default(),
+            context: 0,
+            next_context: 1,
+            context_count: 1,
+            stats: Cell::default(),
+            last_stats: RenderStats::default(),
+            gpu_timer: None,
+            pending_uploads: None,
+            ui_viewport: None,
+            parallel_recording: None,
+            recording_workers: None,
+            texture_last_drawn: AHashMap::default(),
+            texture_budget: None,
+            evicted_textures: AHashSet::default(),
+            newly_evicted: vec![],
+            clear_mode: ClearMode::Preserve,
             need_srgb_conv,
             font_sampler,
             allocators,
@@ -136,50 +769,39 @@ impl Renderer {
     pub fn new_with_render_pass(
         gfx_queue: Arc<Queue>,
         final_output_format: Format,
-        is_overlay: bool,
+        clear_mode: ClearMode,
         samples: SampleCount,
+        device_local_buffers: bool,
     ) -> Renderer {
-        // Create Gui render pass with just depth and final color
-        let render_pass = if is_overlay {
-            vulkano::single_pass_renderpass!(gfx_queue.device().clone(),
-                attachments: {
-                    final_color: {
-                        load: Load,
-                        store: Store,
-                        format: final_output_format,
-                        samples: samples,
-                    }
-                },
-                pass: {
-                        color: [final_color],
-                        depth_stencil: {}
-                }
-            )
-            .unwrap()
-        } else {
-            vulkano::single_pass_renderpass!(gfx_queue.device().clone(),
-                attachments: {
-                    final_color: {
-                        load: Clear,
-                        store: Store,
-                        format: final_output_format,
-                        samples: samples,
-                    }
-                },
-                pass: {
-                        color: [final_color],
-                        depth_stencil: {}
-                }
-            )
-            .unwrap()
-        };
+        assert!(
+            gfx_queue
+                .device()
+                .physical_device()
+                .properties()
+                .framebuffer_color_sample_counts
+                .contains_enum(samples),
+            "Sample count {:?} is not supported by the device",
+            samples
+        );
+        // Create Gui render pass with just final color
+        let render_pass = create_gui_render_pass(
+            gfx_queue.device().clone(),
+            final_output_format,
+            RenderPassOptions { clear_mode, depth_format: None, samples },
+        )
+        .unwrap();
 
         let need_srgb_conv = final_output_format.type_color().unwrap() == NumericType::UNORM;
-        let allocators = Allocators::new_default(gfx_queue.device());
-        let (vertex_buffer_pool, index_buffer_pool) = Self::create_buffers(&allocators.memory);
+        let allocators = Arc::new(Allocators::new_default(gfx_queue.device()));
+        let (vertex_buffer_pool, index_buffer_pool) =
+            Self::create_buffers(&allocators.memory, device_local_buffers);
+        let device_buffer_pools =
+            device_local_buffers.then(|| Self::create_device_buffers(&allocators.memory));
 
         let subpass = Subpass::from(render_pass.clone(), 0).unwrap();
-        let pipeline = Self::create_pipeline(gfx_queue.clone(), subpass.clone());
+        let pipeline =
+            Self::create_pipeline(gfx_queue.clone(), subpass.clone(), &GuiShaders::default())
+                .unwrap();
         let font_sampler = Sampler::new(gfx_queue.device().clone(), SamplerCreateInfo {
             mag_filter: Filter::Linear,
             min_filter: Filter::Linear,
@@ -190,16 +812,46 @@ impl Renderer {
         .unwrap();
         Renderer {
             gfx_queue,
+            transfer_queue: None,
             format: final_output_format,
             render_pass: Some(render_pass),
             vertex_buffer_pool,
             index_buffer_pool,
+            device_buffer_pools,
+            min_mesh_buffer_sizes: MeshBufferSizes::default(),
+            pipelines: vec![(subpass.clone(), pipeline.clone())],
             pipeline,
+            shaders: GuiShaders::default(),
             subpass,
             texture_desc_sets: AHashMap::default(),
             texture_images: AHashMap::default(),
-            next_native_tex_id: 0,
-            is_overlay,
+            texture_samplers: AHashMap::default(),
+            texture_options: AHashMap::default(),
+            texture_regions: AHashMap::default(),
+            texture_variants: AHashMap::default(),
+            textures: Arc::new(TextureRegistry::new()),
+            synced_generation: 0,
+            retired_textures: RetiredTextures::default(),
+            frames_in_flight: DEFAULT_FRAMES_IN_FLIGHT,
+            frame_count: 0,
+            framebuffers: AHashMap::default(),
+            offscreen_targets: AHashMap::default(),
+            last_targets: AHashMap::default(),
+            context: 0,
+            next_context: 1,
+            context_count: 1,
+            stats: Cell::default(),
+            last_stats: RenderStats::default(),
+            gpu_timer: None,
+            pending_uploads: None,
+            ui_viewport: None,
+            parallel_recording: None,
+            recording_workers: None,
+            texture_last_drawn: AHashMap::default(),
+            texture_budget: None,
+            evicted_textures: AHashSet::default(),
+            newly_evicted: vec![],
+            clear_mode,
             need_srgb_conv,
             font_sampler,
             allocators,
@@ -210,46 +862,89 @@ impl Renderer {
         self.render_pass.is_some()
     }
 
+    pub fn uses_device_local_buffers(&self) -> bool {
+        self.device_buffer_pools.is_some()
+    }
+
     fn create_buffers(
         allocator: &Arc<StandardMemoryAllocator>,
-    ) -> (SubbufferAllocator, SubbufferAllocator) {
+        staging: bool,
+    ) -> (MeshBufferPool, MeshBufferPool) {
+        // Staging buffers are only copied from
+        let (vertex_usage, index_usage) = if staging {
+            (BufferUsage::TRANSFER_SRC, BufferUsage::TRANSFER_SRC)
+        } else {
+            (BufferUsage::VERTEX_BUFFER, BufferUsage::INDEX_BUFFER)
+        };
         // Create vertex and index buffers
         let vertex_buffer_pool =
-            SubbufferAllocator::new(allocator.clone(), SubbufferAllocatorCreateInfo {
-                arena_size: VERTEX_BUFFER_SIZE,
-                buffer_usage: BufferUsage::VERTEX_BUFFER,
-                memory_usage: MemoryUsage::Upload,
-                ..Default::default()
-            });
-
+            MeshBufferPool::new(allocator, VERTEX_BUFFER_SIZE, vertex_usage, MemoryUsage::Upload);
         let index_buffer_pool =
-            SubbufferAllocator::new(allocator.clone(), SubbufferAllocatorCreateInfo {
-                arena_size: INDEX_BUFFER_SIZE,
-                buffer_usage: BufferUsage::INDEX_BUFFER,
-                memory_usage: MemoryUsage::Upload,
-                ..Default::default()
-            });
+            MeshBufferPool::new(allocator, INDEX_BUFFER_SIZE, index_usage, MemoryUsage::Upload);
+        (vertex_buffer_pool, index_buffer_pool)
+    }
 
+    fn create_device_buffers(
+        allocator: &Arc<StandardMemoryAllocator>,
+    ) -> (MeshBufferPool, MeshBufferPool) {
+        let vertex_buffer_pool = MeshBufferPool::new(
+            allocator,
+            VERTEX_BUFFER_SIZE,
+            BufferUsage::VERTEX_BUFFER | BufferUsage::TRANSFER_DST,
+            MemoryUsage::DeviceOnly,
+        );
+        let index_buffer_pool = MeshBufferPool::new(
+            allocator,
+            INDEX_BUFFER_SIZE,
+            BufferUsage::INDEX_BUFFER | BufferUsage::TRANSFER_DST,
+            MemoryUsage::DeviceOnly,
+        );
         (vertex_buffer_pool, index_buffer_pool)
     }
 
-    fn create_pipeline(gfx_queue: Arc<Queue>, subpass: Subpass) -> Arc<GraphicsPipeline> {
-        let vs = vs::load(gfx_queue.device().clone()).expect("failed to create shader module");
-        let fs = fs::load(gfx_queue.device().clone()).expect("failed to create shader module");
+    // All mesh buffer pools, the staging ones first with device local buffers
+    fn mesh_buffer_pools(&self) -> impl Iterator<Item = (&MeshBufferPool, &MeshBufferPool)> {
+        std::iter::once((&self.vertex_buffer_pool, &self.index_buffer_pool))
+            .chain(self.device_buffer_pools.as_ref().map(|(vertices, indices)| (vertices, indices)))
+    }
+
+    fn create_pipeline(
+        gfx_queue: Arc<Queue>,
+        subpass: Subpass,
+        shaders: &GuiShaders,
+    ) -> Result<Arc<GraphicsPipeline>, GuiCreationError> {
+        let vs = match &shaders.vertex {
+            Some(vs) => vs.clone(),
+            None => vs::load(gfx_queue.device().clone()).expect("failed to create shader module"),
+        };
+        let fs = match &shaders.fragment {
+            Some(fs) => fs.clone(),
+            None => fs::load(gfx_queue.device().clone()).expect("failed to create shader module"),
+        };
+        let vs_main = vs.entry_point("main").ok_or(GuiCreationError::MissingEntryPoint)?;
+        let fs_main = fs.entry_point("main").ok_or(GuiCreationError::MissingEntryPoint)?;
+
+        let mut blend_state = ColorBlendState::new(subpass.num_color_attachments())
+            .blend(premultiplied_alpha_blend());
+        // Egui only draws to the first color attachment, other attachments of the subpass (e.g.
+        // an id buffer of a deferred renderer) are left untouched
+        for attachment in blend_state.attachments.iter_mut().skip(1) {
+            attachment.blend = None;
+            attachment.color_write_mask = ColorComponents::empty();
+        }
 
-        let mut blend = AttachmentBlend::alpha();
-        blend.color_source = BlendFactor::One;
-        blend.alpha_source = BlendFactor::OneMinusDstAlpha;
-        blend.alpha_destination = BlendFactor::One;
-        let blend_state = ColorBlendState::new(1).blend(blend);
+        // Egui is always drawn on top, so depth is neither tested nor written. This keeps the
+        // pipeline valid for subpasses with and without a depth attachment.
+        let depth_stencil_state = DepthStencilState::disabled();
 
         GraphicsPipeline::start()
             .vertex_input_state(EguiVertex::per_vertex())
-            .vertex_shader(vs.entry_point("main").unwrap(), ())
+            .vertex_shader(vs_main, ())
             .input_assembly_state(InputAssemblyState::new())
-            .fragment_shader(fs.entry_point("main").unwrap(), ())
+            .fragment_shader(fs_main, ())
             .viewport_state(ViewportState::viewport_dynamic_scissor_dynamic(1))
             .color_blend_state(blend_state)
+            .depth_stencil_state(depth_stencil_state)
             .rasterization_state(RasterizationState::new().cull_mode(CullModeEnum::None))
             .multisample_state(MultisampleState {
                 rasterization_samples: subpass.num_samples().unwrap_or(SampleCount::Sample1),
@@ -257,7 +952,61 @@ impl Renderer {
             })
             .render_pass(subpass)
             .build(gfx_queue.device().clone())
-            .unwrap()
+            .map_err(GuiCreationError::from)
+    }
+
+    /// Replaces the shaders of egui meshes, the built-in ones where `shaders` has none
+    pub fn set_shaders(&mut self, shaders: GuiShaders) -> Result<(), GuiCreationError> {
+        self.pipeline =
+            Self::create_pipeline(self.gfx_queue.clone(), self.subpass.clone(), &shaders)?;
+        self.pipelines = vec![(self.subpass.clone(), self.pipeline.clone())];
+        self.shaders = shaders;
+        Ok(())
+    }
+
+    // Returns the pipeline for `subpass`, created once per compatible subpass
+    fn pipeline_for(
+        &mut self,
+        subpass: &Subpass,
+    ) -> Result<Arc<GraphicsPipeline>, GuiCreationError> {
+        let cached = self.pipelines.iter().find(|(cached, _)| {
+            cached.index() == subpass.index()
+                && cached.render_pass().is_compatible_with(subpass.render_pass())
+        });
+        if let Some((_, pipeline)) = cached {
+            return Ok(pipeline.clone());
+        }
+        let pipeline =
+            Self::create_pipeline(self.gfx_queue.clone(), subpass.clone(), &self.shaders)?;
+        self.pipelines.push((subpass.clone(), pipeline.clone()));
+        Ok(pipeline)
+    }
+
+    /// Draws in `subpass` from now on, e.g. when switching to a render path with another color
+    /// format. Textures, buffers & contexts are kept, only the pipeline is created for the new
+    /// subpass, and reused when switching back to a compatible one. Returns an error if the
+    /// device can't draw to the subpass' color attachment or the pipeline can't be created.
+    ///
+    /// Panics if the renderer was created with its own render pass.
+    pub fn set_subpass(&mut self, subpass: Subpass) -> Result<(), GuiCreationError> {
+        if self.render_pass.is_some() {
+            panic!(
+                "Gui integration has been created with its own render pass, it follows the \
+                 swapchain format instead"
+            )
+        }
+        let format = subpass_color_format(&subpass).unwrap_or(self.format);
+        check_device_support(
+            &self.gfx_queue,
+            format,
+            subpass.num_samples().unwrap_or(SampleCount::Sample1),
+            subpass.num_color_attachments() as usize,
+        )?;
+        self.pipeline = self.pipeline_for(&subpass)?;
+        self.subpass = subpass;
+        self.need_srgb_conv = format.type_color().unwrap() == NumericType::UNORM;
+        self.format = format;
+        Ok(())
     }
 
     /// Creates a descriptor set for images
@@ -266,37 +1015,795 @@ impl Renderer {
         layout: &Arc<DescriptorSetLayout>,
         image: Arc<dyn ImageViewAbstract + 'static>,
         sampler: Arc<Sampler>,
-    ) -> Arc<PersistentDescriptorSet> {
+    ) -> Result<Arc<PersistentDescriptorSet>, DescriptorSetCreationError> {
+        self.count(|stats| stats.descriptor_sets_created += 1);
         PersistentDescriptorSet::new(&self.allocators.descriptor_set, layout.clone(), [
             WriteDescriptorSet::image_view_sampler(0, image, sampler),
         ])
-        .unwrap()
     }
 
-    /// Registers a user texture. User texture needs to be unregistered when it is no longer needed
+    /// Registers a user texture. User texture needs to be unregistered when it is no longer needed.
+    /// A sampler is created per texture from `sampler_create_info`.
     pub fn register_image(
         &mut self,
         image: Arc<dyn ImageViewAbstract + Send + Sync>,
         sampler_create_info: SamplerCreateInfo,
-    ) -> egui::TextureId {
-        let layout = self.pipeline.layout().set_layouts().get(0).unwrap();
-        let sampler = Sampler::new(self.gfx_queue.device().clone(), sampler_create_info).unwrap();
-        let desc_set = self.sampled_image_desc_set(layout, image.clone(), sampler);
-        let id = egui::TextureId::User(self.next_native_tex_id);
-        self.next_native_tex_id += 1;
+        options: UserImageOptions,
+    ) -> Result<egui::TextureId, ImageRegistrationError> {
+        let id = self.textures.allocate_user_id();
+        self.insert_image(id, image, sampler_create_info, options)?;
+        Ok(id)
+    }
+
+    // Creates the descriptor set & sampler of a user texture and registers it as `id`
+    fn insert_image(
+        &mut self,
+        id: egui::TextureId,
+        image: Arc<dyn ImageViewAbstract + Send + Sync>,
+        sampler_create_info: SamplerCreateInfo,
+        options: UserImageOptions,
+    ) -> Result<(), ImageRegistrationError> {
+        let image = user_image_view(image, options)?;
+        let layout = self.pipeline.layout().set_layouts().first().unwrap();
+        let sampler = Sampler::new(self.gfx_queue.device().clone(), sampler_create_info)?;
+        let desc_set = self.sampled_image_desc_set(layout, image.clone(), sampler.clone())?;
+        self.textures.insert(id, image.clone());
+        self.texture_desc_sets.insert(id, desc_set);
+        self.texture_images.insert(id, image);
+        self.texture_samplers.insert(id, sampler);
+        self.texture_options.insert(id, options);
+        self.texture_last_drawn.insert(id, self.frame_count);
+        Ok(())
+    }
+
+    /// Registers the `uv_rect` region of an image as its own texture, whose uvs span the region.
+    /// Regions of the same image view share its descriptor set and the sampler of the first
+    /// registered region, so `sampler_create_info` is only used if there is none yet.
+    pub fn register_image_region(
+        &mut self,
+        image: Arc<dyn ImageViewAbstract + Send + Sync>,
+        uv_rect: egui::Rect,
+        sampler_create_info: SamplerCreateInfo,
+    ) -> Result<egui::TextureId, ImageRegistrationError> {
+        let sibling = self.texture_regions.iter().find_map(|(id, region)| {
+            (Arc::as_ptr(&region.source) as *const () == Arc::as_ptr(&image) as *const ()
+                && !self.texture_variants.contains_key(id))
+            .then_some(*id)
+        });
+        let id = match sibling {
+            Some(sibling) => {
+                let id = self.textures.allocate_user_id();
+                self.textures.insert(id, self.texture_images[&sibling].clone());
+                self.texture_desc_sets.insert(id, self.texture_desc_sets[&sibling].clone());
+                self.texture_images.insert(id, self.texture_images[&sibling].clone());
+                self.texture_samplers.insert(id, self.texture_samplers[&sibling].clone());
+                self.texture_options.insert(id, self.texture_options[&sibling]);
+                self.texture_last_drawn.insert(id, self.frame_count);
+                id
+            }
+            None => self.register_image(
+                image.clone(),
+                sampler_create_info,
+                UserImageOptions::default(),
+            )?,
+        };
+        self.texture_regions.insert(id, TextureRegion { uv_rect, source: image });
+        Ok(id)
+    }
+
+    /// Uploads textures on `transfer_queue` instead of the graphics queue, so they can overlap
+    /// with graphics work. Ignored if it is the graphics queue.
+    pub fn set_transfer_queue(&mut self, transfer_queue: Arc<Queue>) {
+        assert_eq!(
+            transfer_queue.device(),
+            self.gfx_queue.device(),
+            "Transfer queue must belong to the same device as the graphics queue"
+        );
+        self.transfer_queue =
+            (!Arc::ptr_eq(&transfer_queue, &self.gfx_queue)).then_some(transfer_queue);
+    }
+
+    /// Returns the queue to record texture uploads on. Generating mipmaps needs blits, which
+    /// are only supported on graphics queues.
+    pub fn upload_queue(&self, mipmaps: bool) -> Arc<Queue> {
+        match &self.transfer_queue {
+            Some(transfer_queue) if !mipmaps => transfer_queue.clone(),
+            _ => self.gfx_queue.clone(),
+        }
+    }
+
+    /// Registers another texture sampling the image of the user texture `base` with its own
+    /// sampler, e.g. nearest filtering for a pixel-perfect thumbnail and linear filtering for a
+    /// scaled up view of the same image. The variant shares the image (and the uv rect if `base`
+    /// is a region), only its sampler & descriptor set are its own. Variants of a variant are
+    /// variants of its base.
+    ///
+    /// Unregistering `base` (or its eviction) unregisters its variants too, while unregistering a
+    /// variant leaves the base registered. Replacing the image of either replaces it for all.
+    pub fn register_image_variant(
+        &mut self,
+        base: egui::TextureId,
+        sampler_create_info: SamplerCreateInfo,
+    ) -> Result<egui::TextureId, ImageRegistrationError> {
+        let id = self.textures.allocate_user_id();
+        self.insert_variant(id, base, sampler_create_info)?;
+        Ok(id)
+    }
+
+    /// Registers a variant restored by `Gui::rebuild` under the id it had on the lost device
+    pub(crate) fn restore_image_variant(
+        &mut self,
+        id: egui::TextureId,
+        base: egui::TextureId,
+        sampler_create_info: SamplerCreateInfo,
+    ) -> Result<(), ImageRegistrationError> {
+        self.insert_variant(id, base, sampler_create_info)
+    }
+
+    fn insert_variant(
+        &mut self,
+        id: egui::TextureId,
+        base: egui::TextureId,
+        sampler_create_info: SamplerCreateInfo,
+    ) -> Result<(), ImageRegistrationError> {
+        let owner = self.variant_owner(base);
+        let image = match (owner, self.texture_images.get(&owner)) {
+            (egui::TextureId::User(_), Some(image)) => image.clone(),
+            _ => return Err(ImageRegistrationError::UnknownTexture(base)),
+        };
+        let layout = self.pipeline.layout().set_layouts().first().unwrap();
+        let sampler = Sampler::new(self.gfx_queue.device().clone(), sampler_create_info)?;
+        let desc_set = self.sampled_image_desc_set(layout, image.clone(), sampler.clone())?;
+        if let Some(region) = self.texture_regions.get(&owner) {
+            let region = TextureRegion { uv_rect: region.uv_rect, source: region.source.clone() };
+            self.texture_regions.insert(id, region);
+        }
+        self.textures.insert(id, image.clone());
         self.texture_desc_sets.insert(id, desc_set);
         self.texture_images.insert(id, image);
-        id
+        self.texture_samplers.insert(id, sampler);
+        self.texture_options.insert(id, self.texture_options[&owner]);
+        self.texture_last_drawn.insert(id, self.frame_count);
+        self.texture_variants.insert(id, owner);
+        Ok(())
+    }
+
+    // Returns the texture owning the image of `texture_id`, itself unless it is a variant
+    fn variant_owner(&self, texture_id: egui::TextureId) -> egui::TextureId {
+        self.texture_variants.get(&texture_id).copied().unwrap_or(texture_id)
+    }
+
+    // Returns the variants borrowing the image of `owner`
+    fn variants_of(&self, owner: egui::TextureId) -> Vec<egui::TextureId> {
+        self.texture_variants.iter().filter(|(_, &o)| o == owner).map(|(&id, _)| id).collect()
+    }
+
+    /// Returns the texture whose image the variant `texture_id` samples, `None` if it isn't a
+    /// variant
+    pub fn variant_base(&self, texture_id: egui::TextureId) -> Option<egui::TextureId> {
+        self.texture_variants.get(&texture_id).copied()
+    }
+
+    /// Submits an upload recorded on the transfer queue right away with a semaphore for the
+    /// graphics queue to wait on. Uploads on the graphics queue are returned as is.
+    pub fn submit_upload(
+        &self,
+        upload: Box<dyn GpuFuture + Send + Sync>,
+    ) -> Result<Box<dyn GpuFuture + Send + Sync>, FlushError> {
+        match upload.queue() {
+            Some(queue) if !Arc::ptr_eq(&queue, &self.gfx_queue) => {
+                Ok(Box::new(upload.then_signal_semaphore_and_flush()?))
+            }
+            _ => Ok(upload),
+        }
+    }
+
+    /// Registers a user texture whose upload hasn't been submitted yet. The upload is submitted
+    /// with the next draw, ahead of the commands sampling the texture.
+    pub fn register_image_deferred(
+        &mut self,
+        image: Arc<dyn ImageViewAbstract + Send + Sync>,
+        sampler_create_info: SamplerCreateInfo,
+        upload: Box<dyn GpuFuture + Send + Sync>,
+    ) -> Result<egui::TextureId, ImageRegistrationError> {
+        let id = self.register_image(image, sampler_create_info, UserImageOptions::default())?;
+        if let Err(err) = self.add_pending_upload(upload) {
+            self.unregister_image(id);
+            return Err(err.into());
+        }
+        Ok(id)
+    }
+
+    /// Registers an image re-uploaded by `Gui::rebuild` under the id it had on the lost device
+    pub(crate) fn restore_image_deferred(
+        &mut self,
+        id: egui::TextureId,
+        image: Arc<dyn ImageViewAbstract + Send + Sync>,
+        sampler_create_info: SamplerCreateInfo,
+        upload: Box<dyn GpuFuture + Send + Sync>,
+    ) -> Result<(), ImageRegistrationError> {
+        self.insert_image(id, image, sampler_create_info, UserImageOptions::default())?;
+        if let Err(err) = self.add_pending_upload(upload) {
+            self.unregister_image(id);
+            return Err(err.into());
+        }
+        Ok(())
+    }
+
+    /// Registers user textures sharing one upload that hasn't been submitted yet, see
+    /// `register_image_deferred`. If a texture can't be registered, none is.
+    pub fn register_images_deferred(
+        &mut self,
+        images: Vec<Arc<dyn ImageViewAbstract + Send + Sync>>,
+        sampler_create_info: SamplerCreateInfo,
+        upload: Box<dyn GpuFuture + Send + Sync>,
+    ) -> Result<Vec<egui::TextureId>, ImageRegistrationError> {
+        let mut ids = vec![];
+        for (index, image) in images.into_iter().enumerate() {
+            match self.register_image(
+                image,
+                sampler_create_info.clone(),
+                UserImageOptions::default(),
+            ) {
+                Ok(id) => ids.push(id),
+                Err(error) => {
+                    for id in ids {
+                        self.unregister_image(id);
+                    }
+                    return Err(ImageRegistrationError::InBatch { index, error: Box::new(error) });
+                }
+            }
+        }
+        if let Err(err) = self.add_pending_upload(upload) {
+            for id in ids {
+                self.unregister_image(id);
+            }
+            return Err(err.into());
+        }
+        Ok(ids)
+    }
+
+    fn add_pending_upload(
+        &mut self,
+        upload: Box<dyn GpuFuture + Send + Sync>,
+    ) -> Result<(), FlushError> {
+        let upload = self.submit_upload(upload)?;
+        self.pending_uploads = Some(match self.pending_uploads.take() {
+            Some(pending) => Box::new(pending.join(upload)),
+            None => upload,
+        });
+        Ok(())
+    }
+
+    /// Drops pending uploads without waiting for them, as waiting on a lost device panics
+    pub(crate) fn forget_pending_uploads(&mut self) {
+        if let Some(pending) = self.pending_uploads.take() {
+            std::mem::forget(pending);
+        }
+    }
+
+    /// Waits for pending uploads, used when the draw commands are submitted by the caller
+    fn wait_pending_uploads(&mut self) -> Result<(), EguiVulkanoError> {
+        if let Some(pending) = self.pending_uploads.take() {
+            pending.then_signal_fence_and_flush()?.wait(None)?;
+        }
+        Ok(())
+    }
+
+    /// Replaces the image of a registered user texture, keeping its id and sampler. The old
+    /// image is retired like an unregistered one.
+    pub fn update_image(
+        &mut self,
+        texture_id: egui::TextureId,
+        image: Arc<dyn ImageViewAbstract + Send + Sync>,
+    ) -> Result<(), ImageRegistrationError> {
+        if !self.texture_samplers.contains_key(&texture_id) {
+            return Err(ImageRegistrationError::UnknownTexture(texture_id));
+        }
+        // Variants borrow the image of their owner, so all of them get the new image
+        let owner = self.variant_owner(texture_id);
+        let image = user_image_view(image, self.texture_options[&owner])?;
+        let layout = self.pipeline.layout().set_layouts().first().unwrap().clone();
+        let ids: Vec<_> = std::iter::once(owner).chain(self.variants_of(owner)).collect();
+        let desc_sets = ids
+            .iter()
+            .map(|id| {
+                let sampler = self.texture_samplers[id].clone();
+                self.sampled_image_desc_set(&layout, image.clone(), sampler)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        for (id, desc_set) in ids.into_iter().zip(desc_sets) {
+            self.textures.insert(id, image.clone());
+            let old_desc_set = self.texture_desc_sets.insert(id, desc_set);
+            let old_image = self.texture_images.insert(id, image.clone());
+            self.retire(old_desc_set, old_image, None);
+        }
+        Ok(())
+    }
+
+    /// Uploads `byte_data` into the existing image of a registered user texture. The data size
+    /// must match the image's size and the image must have been created with transfer dst usage.
+    /// The copy is submitted with the next draw, ahead of the commands sampling the texture.
+    pub fn update_image_bytes(
+        &mut self,
+        texture_id: egui::TextureId,
+        byte_data: &[u8],
+    ) -> Result<(), ImageRegistrationError> {
+        if !self.texture_samplers.contains_key(&texture_id) {
+            return Err(ImageRegistrationError::UnknownTexture(texture_id));
+        }
+        let image = self.texture_images[&texture_id].image().clone();
+        if !image.usage().intersects(ImageUsage::TRANSFER_DST) {
+            return Err(ImageRegistrationError::NotUpdatable(texture_id));
+        }
+        let expected = image.dimensions().num_texels() as u64
+            * image.format().block_size().unwrap_or_default();
+        if expected != byte_data.len() as u64 {
+            return Err(ImageRegistrationError::SizeMismatch {
+                expected,
+                actual: byte_data.len() as u64,
+            });
+        }
+        self.count(|stats| {
+            stats.texture_uploads += 1;
+            stats.bytes_uploaded += byte_data.len() as u64;
+        });
+        let buffer = Buffer::from_iter(
+            &self.allocators.memory,
+            BufferCreateInfo { usage: BufferUsage::TRANSFER_SRC, ..Default::default() },
+            AllocationCreateInfo { usage: MemoryUsage::Upload, ..Default::default() },
+            byte_data.iter().copied(),
+        )?;
+        let mut cbb = AutoCommandBufferBuilder::primary(
+            &self.allocators.command_buffer,
+            self.gfx_queue.queue_family_index(),
+            CommandBufferUsage::OneTimeSubmit,
+        )?;
+        cbb.copy_buffer_to_image(CopyBufferToImageInfo::buffer_image(buffer, image))?;
+        let upload = cbb.build()?.execute(self.gfx_queue.clone())?;
+        self.add_pending_upload(Box::new(upload))?;
+        Ok(())
+    }
+
+    /// Uploads `byte_data` into a region of the existing image of a registered user texture.
+    /// The copy is submitted with the next draw, ahead of the commands sampling the texture.
+    pub fn update_image_region(
+        &mut self,
+        texture_id: egui::TextureId,
+        offset: [u32; 2],
+        extent: [u32; 2],
+        byte_data: &[u8],
+    ) -> Result<(), ImageRegistrationError> {
+        if !self.texture_samplers.contains_key(&texture_id) {
+            return Err(ImageRegistrationError::UnknownTexture(texture_id));
+        }
+        let image = self.texture_images[&texture_id].image().clone();
+        if !image.usage().intersects(ImageUsage::TRANSFER_DST) {
+            return Err(ImageRegistrationError::NotUpdatable(texture_id));
+        }
+        if extent.contains(&0) {
+            return Err(ImageRegistrationError::ZeroSize(extent));
+        }
+        let dimensions = image.dimensions().width_height();
+        if (0..2).any(|i| offset[i] as u64 + extent[i] as u64 > dimensions[i] as u64) {
+            return Err(ImageRegistrationError::RegionOutOfBounds { offset, extent, dimensions });
+        }
+        let expected =
+            extent[0] as u64 * extent[1] as u64 * image.format().block_size().unwrap_or_default();
+        if expected != byte_data.len() as u64 {
+            return Err(ImageRegistrationError::SizeMismatch {
+                expected,
+                actual: byte_data.len() as u64,
+            });
+        }
+        self.count(|stats| {
+            stats.texture_uploads += 1;
+            stats.bytes_uploaded += byte_data.len() as u64;
+        });
+        let buffer = Buffer::from_iter(
+            &self.allocators.memory,
+            BufferCreateInfo { usage: BufferUsage::TRANSFER_SRC, ..Default::default() },
+            AllocationCreateInfo { usage: MemoryUsage::Upload, ..Default::default() },
+            byte_data.iter().copied(),
+        )?;
+        let mut cbb = AutoCommandBufferBuilder::primary(
+            &self.allocators.command_buffer,
+            self.gfx_queue.queue_family_index(),
+            CommandBufferUsage::OneTimeSubmit,
+        )?;
+        cbb.copy_buffer_to_image(CopyBufferToImageInfo {
+            regions: [BufferImageCopy {
+                image_subresource: image.subresource_layers(),
+                image_offset: [offset[0], offset[1], 0],
+                image_extent: [extent[0], extent[1], 1],
+                ..Default::default()
+            }]
+            .into(),
+            ..CopyBufferToImageInfo::buffer_image(buffer, image)
+        })?;
+        let upload = cbb.build()?.execute(self.gfx_queue.clone())?;
+        self.add_pending_upload(Box::new(upload))?;
+        Ok(())
     }
 
-    /// Unregister user texture.
+    /// Returns how the shader converts texels of a texture, see `UserImageOptions::srgb`
+    fn texture_conversion(&self, texture_id: egui::TextureId) -> i32 {
+        let srgb = match self.texture_options.get(&texture_id).and_then(|options| options.srgb) {
+            Some(srgb) => srgb,
+            None => return TEXTURE_CONV_NONE,
+        };
+        let srgb_format = self.texture_images[&texture_id]
+            .format()
+            .map_or(false, |format| format.type_color() == Some(NumericType::SRGB));
+        match (srgb, srgb_format) {
+            (true, false) => TEXTURE_CONV_LINEAR_FROM_SRGB,
+            (false, true) => TEXTURE_CONV_SRGB_FROM_LINEAR,
+            _ => TEXTURE_CONV_NONE,
+        }
+    }
+
+    /// Unregister user texture. Its resources are dropped once `frames_in_flight` more frames
+    /// have been drawn, so frames still being rendered can keep using them. Texture ids are never
+    /// reused, so registering again creates a new texture. Variants of the texture (see
+    /// `register_image_variant`) are unregistered with it.
     pub fn unregister_image(&mut self, texture_id: egui::TextureId) {
-        self.texture_desc_sets.remove(&texture_id);
-        self.texture_images.remove(&texture_id);
+        self.textures.unregister(texture_id);
+        self.forget_texture(texture_id);
+    }
+
+    // Drops the resources of a texture, retiring them for the frames that may use them. The
+    // variants borrowing its image are dropped with it, a variant only drops its own sampler &
+    // descriptor set as the image stays with its owner.
+    fn forget_texture(&mut self, texture_id: egui::TextureId) {
+        if self.texture_variants.remove(&texture_id).is_none() {
+            for variant in self.variants_of(texture_id) {
+                self.textures.unregister(variant);
+                self.forget_texture(variant);
+            }
+        }
+        let desc_set = self.texture_desc_sets.remove(&texture_id);
+        let image = self.texture_images.remove(&texture_id);
+        let sampler = self.texture_samplers.remove(&texture_id);
+        self.texture_options.remove(&texture_id);
+        self.texture_regions.remove(&texture_id);
+        self.texture_last_drawn.remove(&texture_id);
+        self.evicted_textures.remove(&texture_id);
+        self.retire(desc_set, image, sampler);
+    }
+
+    /// Uses `textures` for the images of textures instead of the private registry. Must be
+    /// called before any texture is registered or drawn.
+    pub fn set_texture_registry(&mut self, textures: Arc<TextureRegistry>) {
+        assert!(
+            self.texture_images.is_empty(),
+            "The texture registry must be set before registering textures"
+        );
+        self.textures = textures;
+        self.synced_generation = 0;
+    }
+
+    /// Returns the registry holding the images of the renderer's textures
+    pub fn texture_registry(&self) -> Arc<TextureRegistry> {
+        self.textures.clone()
+    }
+
+    // Mirrors the user textures registered, replaced or unregistered in the registry but not by
+    // the renderer, e.g. by another renderer sharing it
+    fn sync_textures(&mut self) -> Result<(), EguiVulkanoError> {
+        let local = &self.texture_images;
+        let (generation, changes) = self.textures.with_textures(|textures| {
+            if textures.generation == self.synced_generation {
+                return (textures.generation, vec![]);
+            }
+            let user = |id: &egui::TextureId| matches!(id, egui::TextureId::User(_));
+            let removed = local
+                .keys()
+                .filter(|id| user(id) && !textures.images.contains_key(id))
+                .map(|id| (*id, None));
+            let changed = textures
+                .images
+                .iter()
+                .filter(|(id, image)| {
+                    user(id) && !local.get(id).map_or(false, |local| Arc::ptr_eq(local, image))
+                })
+                .map(|(id, image)| (*id, Some(image.clone())));
+            (textures.generation, removed.chain(changed).collect::<Vec<_>>())
+        });
+        self.synced_generation = generation;
+        for (id, image) in changes {
+            match image {
+                Some(image) => self.adopt_image(id, image)?,
+                None => self.forget_texture(id),
+            }
+        }
+        Ok(())
+    }
+
+    // Creates the descriptor set of a user image registered in the registry, keeping the
+    // sampler & options if the texture had another image
+    fn adopt_image(
+        &mut self,
+        texture_id: egui::TextureId,
+        image: Arc<dyn ImageViewAbstract + Send + Sync + 'static>,
+    ) -> Result<(), EguiVulkanoError> {
+        let sampler = self.texture_samplers.get(&texture_id).unwrap_or(&self.font_sampler).clone();
+        let layout = self.pipeline.layout().set_layouts().first().unwrap();
+        let desc_set = self.sampled_image_desc_set(layout, image.clone(), sampler.clone())?;
+        let old_desc_set = self.texture_desc_sets.insert(texture_id, desc_set);
+        let old_image = self.texture_images.insert(texture_id, image);
+        self.texture_samplers.insert(texture_id, sampler);
+        self.texture_options.entry(texture_id).or_default();
+        self.texture_last_drawn.entry(texture_id).or_insert(self.frame_count);
+        self.retire(old_desc_set, old_image, None);
+        Ok(())
+    }
+
+    /// Approximate GPU memory of all registered user images, counting images shared by several
+    /// textures (e.g. regions) once
+    pub fn texture_memory_used(&self) -> u64 {
+        let mut images = AHashSet::new();
+        self.texture_images
+            .iter()
+            .filter(|(id, _)| matches!(id, egui::TextureId::User(_)))
+            .filter(|(_, view)| images.insert(Arc::as_ptr(&view.image()) as *const () as usize))
+            .map(|(_, view)| image_memory(view.image().as_ref()))
+            .sum()
+    }
+
+    /// Whether `texture_id` is a registered texture
+    pub fn is_registered(&self, texture_id: egui::TextureId) -> bool {
+        self.texture_desc_sets.contains_key(&texture_id)
+    }
+
+    /// Number of registered user textures
+    pub fn texture_count(&self) -> usize {
+        self.texture_images.keys().filter(|id| matches!(id, egui::TextureId::User(_))).count()
+    }
+
+    pub fn set_texture_budget(&mut self, budget: Option<u64>) {
+        self.texture_budget = budget;
+    }
+
+    /// Returns the textures evicted since the last call
+    pub fn take_evicted_textures(&mut self) -> Vec<egui::TextureId> {
+        std::mem::take(&mut self.newly_evicted)
+    }
+
+    // Marks the user textures drawn this frame as recently used
+    fn touch_textures(&mut self, items: &[DrawItem]) {
+        for item in items {
+            if let DrawItem::Meshes(batch) = item {
+                // Drawing a variant keeps the image it borrows from being evicted
+                let owner = self.variant_owner(batch.texture_id);
+                for id in [batch.texture_id, owner] {
+                    if let Some(frame) = self.texture_last_drawn.get_mut(&id) {
+                        *frame = self.frame_count;
+                    }
+                }
+            }
+        }
+    }
+
+    // Unregisters the least recently drawn user textures until they fit the budget. Textures
+    // drawn this frame (by any context) are kept, even if that exceeds the budget.
+    fn evict_over_budget(&mut self) {
+        let Some(budget) = self.texture_budget else {
+            return;
+        };
+        let mut used = self.texture_memory_used();
+        if used <= budget {
+            return;
+        }
+        let mut candidates: Vec<(u64, egui::TextureId)> = self
+            .texture_last_drawn
+            .iter()
+            .filter(|(_, &frame)| frame + self.context_count <= self.frame_count)
+            // Evicting a variant frees no image, its owner is evicted with its variants instead
+            .filter(|(id, _)| !self.texture_variants.contains_key(id))
+            .map(|(&id, &frame)| (frame, id))
+            .collect();
+        candidates.sort_unstable_by_key(|&(frame, _)| frame);
+        for (_, id) in candidates {
+            if used <= budget {
+                break;
+            }
+            let evicted: Vec<_> = std::iter::once(id).chain(self.variants_of(id)).collect();
+            self.unregister_image(id);
+            for id in evicted {
+                self.evicted_textures.insert(id);
+                self.newly_evicted.push(id);
+            }
+            used = self.texture_memory_used();
+        }
+    }
+
+    /// Creates a renderer with the settings of this one on `gfx_queue`, e.g. of a device
+    /// recreated after this one's was lost, drawing in `subpass` if this one was created with a
+    /// subpass. Shares the texture registry, but no textures or user shaders are carried over.
+    pub(crate) fn recreate(
+        &self,
+        gfx_queue: Arc<Queue>,
+        subpass: Option<Subpass>,
+    ) -> Result<Renderer, GuiCreationError> {
+        let device_local_buffers = self.device_buffer_pools.is_some();
+        let mut renderer = match (&self.render_pass, subpass) {
+            (Some(_), None) => {
+                let samples = self.subpass.num_samples().unwrap_or(SampleCount::Sample1);
+                check_device_support(&gfx_queue, self.format, samples, 1)?;
+                Renderer::new_with_render_pass(
+                    gfx_queue,
+                    self.format,
+                    self.clear_mode,
+                    samples,
+                    device_local_buffers,
+                )
+            }
+            (None, Some(subpass)) => {
+                let format = subpass_color_format(&subpass).unwrap_or(self.format);
+                check_device_support(
+                    &gfx_queue,
+                    format,
+                    subpass.num_samples().unwrap_or(SampleCount::Sample1),
+                    subpass.num_color_attachments() as usize,
+                )?;
+                Renderer::new_with_subpass(gfx_queue, format, subpass, device_local_buffers)
+            }
+            (Some(_), Some(_)) => panic!(
+                "Gui integration has been created with its own render pass, rebuild it without a \
+                 subpass"
+            ),
+            (None, None) => panic!(
+                "Gui integration has been created with subpass, rebuild it with a subpass of the \
+                 new device"
+            ),
+        };
+        renderer.textures = self.textures.clone();
+        renderer.frames_in_flight = self.frames_in_flight;
+        renderer.set_parallel_recording(self.parallel_recording);
+        renderer.set_min_mesh_buffer_sizes(self.min_mesh_buffer_sizes);
+        renderer.texture_budget = self.texture_budget;
+        renderer.ui_viewport = self.ui_viewport;
+        renderer.set_gpu_profiling(self.gpu_timer.is_some());
+        Ok(renderer)
+    }
+
+    /// Sets the region of the target the ui is drawn in, the whole target if `None`
+    pub fn set_ui_viewport(&mut self, ui_viewport: Option<UiViewport>) {
+        self.ui_viewport = ui_viewport;
+    }
+
+    /// Enables recording large frames on worker threads, see `GuiConfig::parallel_recording`
+    pub fn set_parallel_recording(&mut self, parallel_recording: Option<ParallelRecording>) {
+        if parallel_recording.map(|parallel| parallel.threads)
+            != self.parallel_recording.map(|parallel| parallel.threads)
+        {
+            self.recording_workers = parallel_recording
+                .filter(|parallel| parallel.threads > 1)
+                .map(|parallel| WorkerThreads::new(parallel.threads, "gui-recording"));
+        }
+        self.parallel_recording = parallel_recording;
+    }
+
+    /// Sets the sizes the vertex & index buffers of frames start at, see
+    /// `GuiConfig::min_mesh_buffer_sizes`. Panics if a size is 0.
+    pub fn set_min_mesh_buffer_sizes(&mut self, sizes: MeshBufferSizes) {
+        assert!(
+            sizes.vertex_bytes > 0 && sizes.index_bytes > 0,
+            "Mesh buffer sizes must not be 0, got {:?}",
+            sizes
+        );
+        self.min_mesh_buffer_sizes = sizes;
+        for (vertices, indices) in self.mesh_buffer_pools() {
+            vertices.set_floor(sizes.vertex_bytes);
+            indices.set_floor(sizes.index_bytes);
+        }
+    }
+
+    /// Sets the number of frames drawn after which resources of unregistered textures are dropped
+    pub fn set_frames_in_flight(&mut self, frames_in_flight: u64) {
+        self.frames_in_flight = frames_in_flight;
+    }
+
+    /// Returns the index of the last finished draw, `None` before the first one
+    pub fn last_frame_index(&self) -> Option<u64> {
+        self.frame_count.checked_sub(1)
+    }
+
+    /// Marks the draws up to `frame_index` as finished on the GPU. Retired resources are dropped
+    /// once the draws that may use them are finished from then on, instead of after
+    /// `frames_in_flight` more draws. If no draw is reported finished within `frames_in_flight`
+    /// draws, e.g. because the app stopped calling this, they are dropped after
+    /// `frames_in_flight` draws again.
+    pub fn frame_completed(&mut self, frame_index: u64) {
+        debug_assert!(
+            frame_index < self.frame_count,
+            "Frame {} completed, but only {} frames were drawn",
+            frame_index,
+            self.frame_count
+        );
+        let completed_frame = self.retired_textures.completed_frame;
+        debug_assert!(
+            !matches!(completed_frame, Some(completed) if completed > frame_index),
+            "Frame {} completed after the later frame {}",
+            frame_index,
+            completed_frame.unwrap_or_default()
+        );
+        self.retired_textures.frame_completed(frame_index, self.frame_count);
+        self.drop_retired_textures();
+    }
+
+    // Drops retired resources no draw in flight may still use
+    fn drop_retired_textures(&mut self) {
+        // Each context sharing the renderer draws once per frame
+        let frames_in_flight = self.frames_in_flight * self.context_count;
+        self.retired_textures.drop_unused(self.frame_count, frames_in_flight);
+    }
+
+    /// Adds a context sharing the renderer and returns its id
+    pub fn add_context(&mut self) -> u64 {
+        self.context_count += 1;
+        self.next_context += 1;
+        self.next_context - 1
+    }
+
+    /// Removes a context, retiring its managed textures (e.g. font atlas) and targets
+    pub fn remove_context(&mut self, context: u64) {
+        let managed: Vec<egui::TextureId> = self
+            .texture_desc_sets
+            .keys()
+            .filter(|id| match id {
+                egui::TextureId::Managed(id) => id >> CONTEXT_TEXTURE_ID_SHIFT == context,
+                egui::TextureId::User(_) => false,
+            })
+            .copied()
+            .collect();
+        for id in managed {
+            self.unregister_image(id);
+        }
+        self.offscreen_targets.remove(&context);
+        self.last_targets.remove(&context);
+        self.context_count = self.context_count.saturating_sub(1).max(1);
+    }
+
+    /// Sets the context whose offscreen target, last target & font atlas placeholders are used
+    pub fn set_context(&mut self, context: u64) {
+        self.context = context;
+    }
+
+    fn retire(
+        &mut self,
+        desc_set: Option<Arc<PersistentDescriptorSet>>,
+        image: Option<Arc<dyn ImageViewAbstract + Send + Sync + 'static>>,
+        sampler: Option<Arc<Sampler>>,
+    ) {
+        if desc_set.is_some() || image.is_some() || sampler.is_some() {
+            self.retired_textures.retire(RetiredTexture {
+                frame: self.frame_count,
+                _desc_set: desc_set,
+                _image: image,
+                _sampler: sampler,
+            });
+        }
     }
 
-    fn update_texture(&mut self, texture_id: egui::TextureId, delta: &egui::epaint::ImageDelta) {
-        // Extract pixel data from egui
+    /// Uploads an egui texture delta. Whole textures (e.g. a rebuilt or grown font atlas after
+    /// font changes) replace the stored image, retiring the previous one
+    /// for frames in flight. Partial deltas are blitted into the existing image.
+    fn update_texture(
+        &mut self,
+        texture_id: egui::TextureId,
+        delta: &egui::epaint::ImageDelta,
+    ) -> Result<(), EguiVulkanoError> {
+        // Egui keeps its font atlas within the max texture side it gets from the integration,
+        // but a huge one would otherwise fail in vulkano with an opaque error
+        let max = max_image_dimension(&self.gfx_queue);
+        let size = [delta.image.width() as u32, delta.image.height() as u32];
+        if size.iter().any(|&side| side > max) {
+            return Err(EguiVulkanoError::TextureTooLarge { max, actual: size });
+        }
+        // Extract pixel data from egui. Both layouts are uploaded as premultiplied srgba, so the
+        // font texture (or any other) may be a color image, e.g. a color emoji atlas, and is
+        // sampled the same way as single channel coverage expanded to white.
         let data: Vec<u8> = match &delta.image {
             egui::ImageData::Color(image) => {
                 assert_eq!(
@@ -310,14 +1817,28 @@ impl Renderer {
                 image.srgba_pixels(None).flat_map(|color| color.to_array()).collect()
             }
         };
+        self.count(|stats| {
+            stats.texture_uploads += 1;
+            stats.bytes_uploaded += data.len() as u64;
+        });
         // Create buffer to be copied to the image
         let texture_data_buffer = Buffer::from_iter(
             &self.allocators.memory,
             BufferCreateInfo { usage: BufferUsage::TRANSFER_SRC, ..Default::default() },
             AllocationCreateInfo { usage: MemoryUsage::Upload, ..Default::default() },
             data,
-        )
-        .unwrap();
+        )?;
+        // Whole textures are uploaded on the transfer queue if there is one, while partial updates
+        // are blitted on the graphics queue after any pending upload of the texture
+        let upload_queue =
+            if delta.pos.is_none() { self.upload_queue(false) } else { self.gfx_queue.clone() };
+        if delta.pos.is_some() && self.transfer_queue.is_some() {
+            self.wait_pending_uploads()?;
+        }
+        let mut queue_family_indices = vec![self.gfx_queue.queue_family_index()];
+        if upload_queue.queue_family_index() != self.gfx_queue.queue_family_index() {
+            queue_family_indices.push(upload_queue.queue_family_index());
+        }
         // Create image
         let (img, init) = ImmutableImage::uninitialized(
             &self.allocators.memory,
@@ -331,22 +1852,19 @@ impl Renderer {
             ImageUsage::TRANSFER_DST | ImageUsage::TRANSFER_SRC | ImageUsage::SAMPLED,
             Default::default(),
             ImageLayout::ShaderReadOnlyOptimal,
-            Some(self.gfx_queue.queue_family_index()),
-        )
-        .unwrap();
-        let font_image = ImageView::new_default(img).unwrap();
+            queue_family_indices,
+        )?;
+        let font_image = ImageView::new_default(img)?;
 
         // Create command buffer builder
         let mut cbb = AutoCommandBufferBuilder::primary(
             &self.allocators.command_buffer,
-            self.gfx_queue.queue_family_index(),
+            upload_queue.queue_family_index(),
             CommandBufferUsage::OneTimeSubmit,
-        )
-        .unwrap();
+        )?;
 
         // Copy buffer to image
-        cbb.copy_buffer_to_image(CopyBufferToImageInfo::buffer_image(texture_data_buffer, init))
-            .unwrap();
+        cbb.copy_buffer_to_image(CopyBufferToImageInfo::buffer_image(texture_data_buffer, init))?;
 
         // Blit texture data to existing image if delta pos exists (e.g. font changed)
         if let Some(pos) = delta.pos {
@@ -369,100 +1887,331 @@ impl Renderer {
                     .into(),
                     filter: Filter::Nearest,
                     ..BlitImageInfo::images(font_image.image().clone(), existing_image.image())
-                })
-                .unwrap();
+                })?;
+            } else {
+                eprintln!("Partial update of a texture that doesn't exist {:?}", texture_id);
             }
             // Otherwise save the newly created image
         } else {
-            let layout = self.pipeline.layout().set_layouts().get(0).unwrap();
+            let layout = self.pipeline.layout().set_layouts().first().unwrap();
             let font_desc_set =
-                self.sampled_image_desc_set(layout, font_image.clone(), self.font_sampler.clone());
-            self.texture_desc_sets.insert(texture_id, font_desc_set);
-            self.texture_images.insert(texture_id, font_image);
+                self.sampled_image_desc_set(layout, font_image.clone(), self.font_sampler.clone())?;
+            self.textures.insert(texture_id, font_image.clone());
+            let old_desc_set = self.texture_desc_sets.insert(texture_id, font_desc_set);
+            let old_image = self.texture_images.insert(texture_id, font_image);
+            self.retire(old_desc_set, old_image, None);
         }
         // Execute command buffer
-        let command_buffer = cbb.build().unwrap();
-        let finished = command_buffer.execute(self.gfx_queue.clone()).unwrap();
-        let _fut = finished.then_signal_fence_and_flush().unwrap();
+        let command_buffer = cbb.build()?;
+        let finished = command_buffer.execute(upload_queue.clone())?;
+        if Arc::ptr_eq(&upload_queue, &self.gfx_queue) {
+            // Waited on right away, so a lost device is reported instead of panicking on drop
+            finished.then_signal_fence_and_flush()?.wait(None)?;
+        } else {
+            self.add_pending_upload(Box::new(finished))?;
+        }
+        Ok(())
+    }
+
+    /// Uploads all texture deltas of a frame, errors are tagged with the failing texture
+    fn update_textures(&mut self, textures_delta: &TexturesDelta) -> Result<(), EguiVulkanoError> {
+        let started = Instant::now();
+        let result = textures_delta.set.iter().try_for_each(|(id, image_delta)| {
+            self.update_texture(*id, image_delta).map_err(|error| EguiVulkanoError::Texture {
+                texture_id: *id,
+                error: Box::new(error),
+            })
+        });
+        self.count(|stats| stats.upload_time += started.elapsed());
+        result
+    }
+
+    /// Batches consecutive drawable meshes that share a texture & clip rect, keeping paint order.
+    /// Batches are limited to the vertices `u16` indices can address, unless a single mesh has
+    /// more.
+    fn draw_items<'a>(&self, clipped_meshes: &'a [ClippedPrimitive]) -> Vec<DrawItem<'a>> {
+        let mut items: Vec<DrawItem<'a>> = vec![];
+        for ClippedPrimitive { clip_rect, primitive } in clipped_meshes {
+            match primitive {
+                Primitive::Mesh(mesh) => {
+                    // Nothing to draw if we don't have vertices & indices
+                    if mesh.vertices.is_empty() || mesh.indices.is_empty() {
+                        continue;
+                    }
+                    // User textures may be unregistered while egui still references them
+                    let placeholder = self.evicted_textures.contains(&mesh.texture_id)
+                        || matches!(mesh.texture_id, egui::TextureId::User(_))
+                            && !self.texture_desc_sets.contains_key(&mesh.texture_id);
+                    let texture_id = if placeholder {
+                        context_texture_id(self.context, egui::TextureId::default())
+                    } else {
+                        mesh.texture_id
+                    };
+                    if !self.texture_desc_sets.contains_key(&texture_id) {
+                        eprintln!("This texture no longer exists {:?}", mesh.texture_id);
+                        continue;
+                    }
+                    if let Some(DrawItem::Meshes(batch)) = items.last_mut() {
+                        if batch.try_append(texture_id, placeholder, *clip_rect, mesh) {
+                            continue;
+                        }
+                    }
+                    items.push(DrawItem::Meshes(MeshBatch::new(
+                        texture_id,
+                        placeholder,
+                        *clip_rect,
+                        mesh,
+                    )));
+                }
+                Primitive::Callback(callback) => {
+                    items.push(DrawItem::Callback { clip_rect: *clip_rect, callback })
+                }
+            }
+        }
+        items
     }
 
-    fn get_rect_scissor(
+    /// Creates the vertex & index buffers of the mesh batches of the frame. With device local
+    /// buffers, the copies from the staging buffers are recorded into `transfer_builder`, which
+    /// must not be inside a render pass, or submitted (and waited on) separately if it's `None`.
+    fn mesh_buffers(
         &self,
-        scale_factor: f32,
-        framebuffer_dimensions: [u32; 2],
-        rect: Rect,
-    ) -> Scissor {
-        let min = rect.min;
-        let min = egui::Pos2 { x: min.x * scale_factor, y: min.y * scale_factor };
-        let min = egui::Pos2 {
-            x: min.x.clamp(0.0, framebuffer_dimensions[0] as f32),
-            y: min.y.clamp(0.0, framebuffer_dimensions[1] as f32),
+        items: &[DrawItem],
+        transfer_builder: Option<&mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>>,
+    ) -> Result<Vec<MeshBuffers>, EguiVulkanoError> {
+        let started = Instant::now();
+        let arenas_created = self.mesh_buffer_arenas_created();
+        let result = self.write_mesh_buffers(items, transfer_builder);
+        let allocations = self.mesh_buffer_arenas_created() - arenas_created;
+        self.count(|stats| {
+            stats.upload_time += started.elapsed();
+            stats.mesh_buffer_allocations += allocations;
+        });
+        result
+    }
+
+    fn mesh_buffer_arenas_created(&self) -> usize {
+        self.mesh_buffer_pools()
+            .map(|(vertices, indices)| vertices.arenas_created.get() + indices.arenas_created.get())
+            .sum()
+    }
+
+    fn write_mesh_buffers(
+        &self,
+        items: &[DrawItem],
+        mut transfer_builder: Option<&mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>>,
+    ) -> Result<Vec<MeshBuffers>, EguiVulkanoError> {
+        // All meshes of the frame are packed into the same vertex & index buffers, unless they
+        // are too large for one buffer
+        let batches = items
+            .iter()
+            .filter_map(|item| match item {
+                DrawItem::Meshes(batch) => Some(batch),
+                DrawItem::Callback { .. } => None,
+            })
+            .collect::<Vec<_>>();
+        // Grow the pools before allocating, so the whole frame fits in one arena and doesn't
+        // spill into more of them
+        let (vertex_bytes, index_bytes) =
+            batches.iter().fold((0, 0), |(vertices, indices), batch| {
+                let index_size = if batch.uses_u16_indices() { 2 } else { 4 };
+                (
+                    vertices
+                        + (batch.num_vertices * std::mem::size_of::<EguiVertex>()) as DeviceSize,
+                    indices + (batch.num_indices * index_size) as DeviceSize,
+                )
+            });
+        let max_arena_size = 2 * self.max_mesh_buffer_size();
+        for (vertices, indices) in self.mesh_buffer_pools() {
+            vertices.fit_frame(vertex_bytes, max_arena_size);
+            indices.fit_frame(index_bytes, max_arena_size);
+        }
+        mesh_segments(&batches, self.max_mesh_buffer_size())?
+            .into_iter()
+            .map(|segment| self.write_mesh_segment(segment, transfer_builder.as_deref_mut()))
+            .collect()
+    }
+
+    /// Largest vertex or index buffer allocated for a frame's meshes. Buffer pools double their
+    /// arenas to fit allocations, so this is half of the largest allocation the device allows,
+    /// or of the 1 GiB every Vulkan 1.1 device allows.
+    fn max_mesh_buffer_size(&self) -> DeviceSize {
+        let properties = self.gfx_queue.device().physical_device().properties();
+        properties.max_memory_allocation_size.unwrap_or(1 << 30) / 2
+    }
+
+    // Writes the vertices & indices of `batches` into mesh buffers
+    fn write_mesh_segment(
+        &self,
+        batches: &[&MeshBatch],
+        transfer_builder: Option<&mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>>,
+    ) -> Result<MeshBuffers, EguiVulkanoError> {
+        let staging = self.create_subbuffers(batches)?;
+        let Some((device_vertex_pool, device_index_pool)) = &self.device_buffer_pools else {
+            return Ok(staging);
         };
-        let max = rect.max;
-        let max = egui::Pos2 { x: max.x * scale_factor, y: max.y * scale_factor };
-        let max = egui::Pos2 {
-            x: max.x.clamp(min.x, framebuffer_dimensions[0] as f32),
-            y: max.y.clamp(min.y, framebuffer_dimensions[1] as f32),
+
+        let buffers = MeshBuffers {
+            num_batches: staging.num_batches,
+            vertices: device_vertex_pool.allocate_slice::<EguiVertex>(staging.vertices.len())?,
+            indices_u16: staging
+                .indices_u16
+                .as_ref()
+                .map(|indices| device_index_pool.allocate_slice::<u16>(indices.len()))
+                .transpose()?,
+            indices_u32: staging
+                .indices_u32
+                .as_ref()
+                .map(|indices| device_index_pool.allocate_slice::<u32>(indices.len()))
+                .transpose()?,
+        };
+        let record_copies = |builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>| {
+            builder
+                .copy_buffer(CopyBufferInfo::buffers(staging.vertices, buffers.vertices.clone()))?;
+            if let (Some(src), Some(dst)) = (staging.indices_u16, &buffers.indices_u16) {
+                builder.copy_buffer(CopyBufferInfo::buffers(src, dst.clone()))?;
+            }
+            if let (Some(src), Some(dst)) = (staging.indices_u32, &buffers.indices_u32) {
+                builder.copy_buffer(CopyBufferInfo::buffers(src, dst.clone()))?;
+            }
+            Ok::<_, EguiVulkanoError>(())
         };
-        Scissor {
-            origin: [min.x.round() as u32, min.y.round() as u32],
-            dimensions: [(max.x.round() - min.x) as u32, (max.y.round() - min.y) as u32],
+        match transfer_builder {
+            Some(builder) => record_copies(builder)?,
+            None => {
+                let mut builder = AutoCommandBufferBuilder::primary(
+                    &self.allocators.command_buffer,
+                    self.gfx_queue.queue_family_index(),
+                    CommandBufferUsage::OneTimeSubmit,
+                )?;
+                record_copies(&mut builder)?;
+                let command_buffer = builder.build()?;
+                let finished = command_buffer.execute(self.gfx_queue.clone())?;
+                let _fut = finished.then_signal_fence_and_flush()?;
+            }
         }
+        Ok(buffers)
     }
 
-    fn create_subbuffers(&self, mesh: &Mesh) -> (Subbuffer<[EguiVertex]>, Subbuffer<[u32]>) {
-        // Copy vertices to buffer
-        let v_slice = &mesh.vertices;
+    /// Copies vertices and indices of all `batches` into one vertex and one index buffer per
+    /// index type. `batches` must contain at least one vertex and index.
+    fn create_subbuffers(&self, batches: &[&MeshBatch]) -> Result<MeshBuffers, EguiVulkanoError> {
+        let num_vertices: usize = batches.iter().map(|batch| batch.num_vertices).sum();
+        let num_meshes: usize = batches.iter().map(|batch| batch.meshes.len()).sum();
+        let (num_indices_u16, num_indices_u32) =
+            batches.iter().fold((0, 0), |(u16s, u32s), batch| {
+                if batch.uses_u16_indices() {
+                    (u16s + batch.num_indices, u32s)
+                } else {
+                    (u16s, u32s + batch.num_indices)
+                }
+            });
+        self.count(|stats| {
+            stats.meshes += num_meshes;
+            stats.batched_meshes += num_meshes - batches.len();
+            stats.vertices += num_vertices;
+            stats.indices += num_indices_u16 + num_indices_u32;
+            stats.bytes_uploaded += (num_vertices * std::mem::size_of::<EguiVertex>()
+                + num_indices_u16 * std::mem::size_of::<u16>()
+                + num_indices_u32 * std::mem::size_of::<u32>())
+                as u64;
+        });
 
+        // Copy vertices to buffer
         let vertex_chunk =
-            self.vertex_buffer_pool.allocate_slice::<EguiVertex>(v_slice.len() as u64).unwrap();
+            self.vertex_buffer_pool.allocate_slice::<EguiVertex>(num_vertices as u64)?;
         {
-            let mut vertex_write = vertex_chunk.write().unwrap();
-            for (i, v) in v_slice.iter().enumerate() {
-                vertex_write[i] = EguiVertex {
+            let mut vertex_write = vertex_chunk.write()?;
+            // Uvs of image regions are remapped from the region to the whole image
+            let vertices = batches.iter().flat_map(|batch| {
+                let region =
+                    self.texture_regions.get(&batch.texture_id).map(|region| region.uv_rect);
+                let placeholder = batch.placeholder;
+                batch.meshes.iter().flat_map(move |mesh| {
+                    mesh.vertices.iter().map(move |v| (v, region, placeholder))
+                })
+            });
+            for (dst, (v, region, placeholder)) in vertex_write.iter_mut().zip(vertices) {
+                let (uv, color) = match region {
+                    _ if placeholder => (
+                        egui::epaint::WHITE_UV,
+                        PLACEHOLDER_COLOR.linear_multiply(v.color.a() as f32 / 255.0),
+                    ),
+                    Some(region) => (region.min + v.uv.to_vec2() * region.size(), v.color),
+                    None => (v.uv, v.color),
+                };
+                *dst = EguiVertex {
                     position: [v.pos.x, v.pos.y],
-                    tex_coords: [v.uv.x, v.uv.y],
-                    color: v.color.to_array(),
+                    tex_coords: [uv.x, uv.y],
+                    color: color.to_array(),
                 };
             }
         }
 
-        // Copy indices to buffer
-        let i_slice = &mesh.indices;
-        let index_chunk =
-            self.index_buffer_pool.allocate_slice::<u32>(i_slice.len() as u64).unwrap();
-        {
-            let mut index_write = index_chunk.write().unwrap();
-            for (i, v) in i_slice.iter().enumerate() {
-                index_write[i] = *v;
+        // Copy indices to buffers, written straight into the mapped memory as they are rebased
+        // & narrowed
+        let indices_u16 = (num_indices_u16 > 0).then(|| {
+            let index_chunk =
+                self.index_buffer_pool.allocate_slice::<u16>(num_indices_u16 as u64)?;
+            {
+                let mut index_write = index_chunk.write()?;
+                let indices = batches
+                    .iter()
+                    .filter(|batch| batch.uses_u16_indices())
+                    .flat_map(|batch| batch.indices());
+                for (dst, i) in index_write.iter_mut().zip(indices) {
+                    *dst = i as u16;
+                }
             }
-        }
-
-        (vertex_chunk, index_chunk)
+            Ok::<_, EguiVulkanoError>(index_chunk)
+        });
+        let indices_u32 = (num_indices_u32 > 0).then(|| {
+            let index_chunk =
+                self.index_buffer_pool.allocate_slice::<u32>(num_indices_u32 as u64)?;
+            {
+                let mut index_write = index_chunk.write()?;
+                let indices = batches
+                    .iter()
+                    .filter(|batch| !batch.uses_u16_indices())
+                    .flat_map(|batch| batch.indices());
+                for (dst, i) in index_write.iter_mut().zip(indices) {
+                    *dst = i;
+                }
+            }
+            Ok::<_, EguiVulkanoError>(index_chunk)
+        });
+
+        Ok(MeshBuffers {
+            num_batches: batches.len(),
+            vertices: vertex_chunk,
+            indices_u16: indices_u16.transpose()?,
+            indices_u32: indices_u32.transpose()?,
+        })
     }
 
     fn create_secondary_command_buffer_builder(
         &self,
-    ) -> AutoCommandBufferBuilder<SecondaryAutoCommandBuffer> {
-        AutoCommandBufferBuilder::secondary(
+    ) -> Result<AutoCommandBufferBuilder<SecondaryAutoCommandBuffer>, EguiVulkanoError> {
+        secondary_builder(
             &self.allocators.command_buffer,
             self.gfx_queue.queue_family_index(),
-            CommandBufferUsage::MultipleSubmit,
-            CommandBufferInheritanceInfo {
-                render_pass: Some(self.subpass.clone().into()),
-                ..Default::default()
-            },
+            &self.subpass,
         )
-        .unwrap()
     }
 
-    // Starts the rendering pipeline and returns [`AutoCommandBufferBuilder`] for drawing
-    fn start(
+    /// Returns a cached framebuffer for `final_image`, creating it if it doesn't exist yet.
+    /// Framebuffers whose image view is no longer referenced outside of the cache are dropped.
+    fn framebuffer(
         &mut self,
         final_image: Arc<dyn ImageViewAbstract + 'static>,
-    ) -> (AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>, [u32; 2]) {
-        // Get dimensions
-        let img_dims = final_image.image().dimensions().width_height();
+    ) -> Result<Arc<Framebuffer>, EguiVulkanoError> {
+        self.framebuffers.retain(|_, fb| Arc::strong_count(&fb.attachments()[0]) > 1);
+        let key = Arc::as_ptr(&final_image) as *const () as usize;
+        if let Some(framebuffer) = self.framebuffers.get(&key) {
+            if framebuffer.extent() == final_image.dimensions().width_height() {
+                return Ok(framebuffer.clone());
+            }
+        }
         // Create framebuffer (must be in same order as render pass description in `new`
         let framebuffer = Framebuffer::new(
             self.render_pass
@@ -473,25 +2222,48 @@ impl Renderer {
                 )
                 .clone(),
             FramebufferCreateInfo { attachments: vec![final_image], ..Default::default() },
-        )
-        .unwrap();
+        )?;
+        self.framebuffers.insert(key, framebuffer.clone());
+        Ok(framebuffer)
+    }
+
+    // Starts the rendering pipeline and returns [`AutoCommandBufferBuilder`] for drawing along
+    // with the mesh buffers, which are uploaded before the render pass begins
+    fn start(
+        &mut self,
+        final_image: Arc<dyn ImageViewAbstract + 'static>,
+        items: &[DrawItem],
+    ) -> Result<FrameStart, EguiVulkanoError> {
+        // Get dimensions
+        let img_dims = final_image.image().dimensions().width_height();
+        // Offscreen targets are cleared to transparent even when preserving the target contents
+        let is_offscreen_target =
+            self.offscreen_targets.get(&self.context).map_or(false, |target| {
+                Arc::as_ptr(target) as *const () == Arc::as_ptr(&final_image) as *const ()
+            });
         let mut command_buffer_builder = AutoCommandBufferBuilder::primary(
             &self.allocators.command_buffer,
             self.gfx_queue.queue_family_index(),
             CommandBufferUsage::OneTimeSubmit,
-        )
-        .unwrap();
+        )?;
+        if is_offscreen_target && self.clear_mode == ClearMode::Preserve {
+            command_buffer_builder
+                .clear_color_image(ClearColorImageInfo::image(final_image.image()))?;
+        }
+        if let Some(gpu_timer) = &mut self.gpu_timer {
+            gpu_timer.begin(&mut command_buffer_builder);
+        }
+        let buffers = self.mesh_buffers(items, Some(&mut command_buffer_builder))?;
+        let framebuffer = self.framebuffer(final_image)?;
         // Add clear values here for attachments and begin render pass
-        command_buffer_builder
-            .begin_render_pass(
-                RenderPassBeginInfo {
-                    clear_values: vec![if !self.is_overlay { Some([0.0; 4].into()) } else { None }],
-                    ..RenderPassBeginInfo::framebuffer(framebuffer)
-                },
-                SubpassContents::SecondaryCommandBuffers,
-            )
-            .unwrap();
-        (command_buffer_builder, img_dims)
+        command_buffer_builder.begin_render_pass(
+            RenderPassBeginInfo {
+                clear_values: vec![self.clear_mode.clear_value()],
+                ..RenderPassBeginInfo::framebuffer(framebuffer)
+            },
+            SubpassContents::SecondaryCommandBuffers,
+        )?;
+        Ok((command_buffer_builder, img_dims, buffers))
     }
 
     /// Executes our draw commands on the final image and returns a `GpuFuture` to wait on
@@ -502,45 +2274,118 @@ impl Renderer {
         scale_factor: f32,
         before_future: F,
         final_image: Arc<dyn ImageViewAbstract + 'static>,
-    ) -> Box<dyn GpuFuture>
+    ) -> Result<Box<dyn GpuFuture>, EguiVulkanoError>
     where
         F: GpuFuture + 'static,
     {
-        for (id, image_delta) in &textures_delta.set {
-            self.update_texture(*id, image_delta);
-        }
-
-        let (mut command_buffer_builder, framebuffer_dimensions) = self.start(final_image);
-        let mut builder = self.create_secondary_command_buffer_builder();
-        self.draw_egui(scale_factor, clipped_meshes, framebuffer_dimensions, &mut builder);
+        let started = Instant::now();
+        self.update_textures(textures_delta)?;
+        self.sync_textures()?;
+
+        self.last_targets.insert(self.context, final_image.clone());
+        let items = self.draw_items(clipped_meshes);
+        self.touch_textures(&items);
+        let (mut command_buffer_builder, framebuffer_dimensions, buffers) =
+            self.start(final_image, &items)?;
+        let command_buffers = if self.records_in_parallel(&items) {
+            self.draw_egui_parallel(scale_factor, &items, buffers, framebuffer_dimensions)?
+        } else {
+            let mut builder = self.create_secondary_command_buffer_builder()?;
+            self.draw_egui(
+                scale_factor,
+                &items,
+                buffers,
+                framebuffer_dimensions,
+                &mut builder,
+                Self::run_paint_callback,
+            )?;
+            vec![builder.build()?]
+        };
         // Execute draw commands
-        let command_buffer = builder.build().unwrap();
-        command_buffer_builder.execute_commands(command_buffer).unwrap();
-        let done_future = self.finish(command_buffer_builder, Box::new(before_future));
+        for command_buffer in command_buffers {
+            command_buffer_builder.execute_commands(command_buffer)?;
+        }
+        let before_future: Box<dyn GpuFuture> = match self.pending_uploads.take() {
+            Some(pending) => Box::new(before_future.join(pending)),
+            None => Box::new(before_future),
+        };
+        let done_future = self.finish(command_buffer_builder, before_future)?;
 
         for &id in &textures_delta.free {
             self.unregister_image(id);
         }
+        self.finish_frame(started);
+
+        Ok(done_future)
+    }
 
-        done_future
+    /// Executes our draw commands on an offscreen render target of `dimensions` owned by the
+    /// renderer. Returns a `GpuFuture` to wait on and the target image view, which can be sampled
+    /// once the future has completed. The image contains premultiplied alpha.
+    pub fn draw_on_texture<F>(
+        &mut self,
+        clipped_meshes: &[ClippedPrimitive],
+        textures_delta: &TexturesDelta,
+        scale_factor: f32,
+        before_future: F,
+        dimensions: [u32; 2],
+    ) -> Result<TextureDraw, EguiVulkanoError>
+    where
+        F: GpuFuture + 'static,
+    {
+        assert_eq!(
+            self.subpass.num_samples(),
+            Some(SampleCount::Sample1),
+            "Drawing on a texture is not supported with multisampling"
+        );
+        // Images can't be empty, e.g. when sizing the texture by a minimized window
+        let dimensions = dimensions.map(|d| d.max(1));
+        let target = match self.offscreen_targets.get(&self.context) {
+            Some(target) if target.dimensions().width_height() == dimensions => target.clone(),
+            _ => {
+                let image = AttachmentImage::with_usage(
+                    &self.allocators.memory,
+                    dimensions,
+                    self.format,
+                    ImageUsage::COLOR_ATTACHMENT
+                        | ImageUsage::SAMPLED
+                        | ImageUsage::TRANSFER_SRC
+                        | ImageUsage::TRANSFER_DST,
+                )?;
+                let target: Arc<dyn ImageViewAbstract + Send + Sync> =
+                    ImageView::new_default(image)?;
+                self.offscreen_targets.insert(self.context, target.clone());
+                target
+            }
+        };
+        let future = self.draw_on_image(
+            clipped_meshes,
+            textures_delta,
+            scale_factor,
+            before_future,
+            target.clone(),
+        )?;
+        Ok((future, target))
     }
 
     // Finishes the rendering pipeline
     fn finish(
-        &self,
+        &mut self,
         mut command_buffer_builder: AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
         before_main_cb_future: Box<dyn GpuFuture>,
-    ) -> Box<dyn GpuFuture> {
+    ) -> Result<Box<dyn GpuFuture>, EguiVulkanoError> {
         // We end render pass
-        command_buffer_builder.end_render_pass().unwrap();
+        command_buffer_builder.end_render_pass()?;
+        if let Some(gpu_timer) = &mut self.gpu_timer {
+            gpu_timer.end(&mut command_buffer_builder);
+        }
         // Then execute our whole command buffer
-        let command_buffer = command_buffer_builder.build().unwrap();
+        let command_buffer = command_buffer_builder.build()?;
         let after_main_cb =
-            before_main_cb_future.then_execute(self.gfx_queue.clone(), command_buffer).unwrap();
-        let future =
-            after_main_cb.then_signal_fence_and_flush().expect("Failed to signal fence and flush");
+            before_main_cb_future.then_execute(self.gfx_queue.clone(), command_buffer)?;
+        let future = after_main_cb.then_signal_fence_and_flush()?;
         // Return our future
-        Box::new(future)
+        Ok(Box::new(future))
     }
 
     pub fn draw_on_subpass_image(
@@ -549,95 +2394,198 @@ impl Renderer {
         textures_delta: &TexturesDelta,
         scale_factor: f32,
         framebuffer_dimensions: [u32; 2],
-    ) -> SecondaryAutoCommandBuffer {
-        for (id, image_delta) in &textures_delta.set {
-            self.update_texture(*id, image_delta);
-        }
-        let mut builder = self.create_secondary_command_buffer_builder();
-        self.draw_egui(scale_factor, clipped_meshes, framebuffer_dimensions, &mut builder);
-        let buffer = builder.build().unwrap();
+    ) -> Result<SecondaryAutoCommandBuffer, EguiVulkanoError> {
+        let started = Instant::now();
+        self.update_textures(textures_delta)?;
+        let buffer = self.paint_meshes(clipped_meshes, scale_factor, framebuffer_dimensions)?;
         for &id in &textures_delta.free {
             self.unregister_image(id);
         }
-        buffer
+        self.finish_frame(started);
+        Ok(buffer)
+    }
+
+    /// Records meshes made outside of an egui frame, e.g. a procedurally generated minimap, into
+    /// a secondary command buffer of the subpass. Positions and clip rects are in points,
+    /// textures are the font atlas and registered user textures, drawn like in a frame.
+    ///
+    /// Unlike the draws of a frame, this uploads no textures and doesn't end the frame, so it
+    /// can be called any number of times per frame. Waits for uploads of user images registered
+    /// since the last draw.
+    pub fn paint_meshes(
+        &mut self,
+        meshes: &[ClippedPrimitive],
+        pixels_per_point: f32,
+        framebuffer_dimensions: [u32; 2],
+    ) -> Result<SecondaryAutoCommandBuffer, EguiVulkanoError> {
+        self.sync_textures()?;
+        self.wait_pending_uploads()?;
+        self.record_subpass_image(meshes, pixels_per_point, framebuffer_dimensions)
+    }
+
+    /// Submits the texture uploads of a frame drawn later with `render_prepared`. Returns the
+    /// future of the uploads, which the command buffer executing the draw commands must wait on.
+    pub fn prepare(
+        &mut self,
+        textures_delta: &TexturesDelta,
+    ) -> Result<Option<Box<dyn GpuFuture>>, EguiVulkanoError> {
+        self.update_textures(textures_delta)?;
+        Ok(self.take_pending_uploads())
+    }
+
+    /// Takes the future of the uploads submitted since the last draw or `prepare`
+    pub fn take_pending_uploads(&mut self) -> Option<Box<dyn GpuFuture>> {
+        self.pending_uploads.take().map(|upload| upload as Box<dyn GpuFuture>)
+    }
+
+    /// Records the draw commands of a frame whose textures were uploaded with `prepare`, then
+    /// frees the textures in `freed`. Errors if uploads were submitted since, because the draw
+    /// could sample them before they finish.
+    pub fn render_prepared(
+        &mut self,
+        clipped_meshes: &[ClippedPrimitive],
+        freed: &[egui::TextureId],
+        scale_factor: f32,
+        framebuffer_dimensions: [u32; 2],
+    ) -> Result<SecondaryAutoCommandBuffer, EguiVulkanoError> {
+        if self.pending_uploads.is_some() {
+            return Err(EguiVulkanoError::UnpreparedUploads);
+        }
+        let started = Instant::now();
+        self.sync_textures()?;
+        let buffer =
+            self.record_subpass_image(clipped_meshes, scale_factor, framebuffer_dimensions)?;
+        for &id in freed {
+            self.unregister_image(id);
+        }
+        self.finish_frame_recorded(started.elapsed());
+        Ok(buffer)
     }
 
-    fn draw_egui(
+    // Records the draw commands of a frame into a secondary command buffer of the subpass
+    fn record_subpass_image(
         &mut self,
+        clipped_meshes: &[ClippedPrimitive],
         scale_factor: f32,
+        framebuffer_dimensions: [u32; 2],
+    ) -> Result<SecondaryAutoCommandBuffer, EguiVulkanoError> {
+        let items = self.draw_items(clipped_meshes);
+        self.touch_textures(&items);
+        let buffers = self.mesh_buffers(&items, None)?;
+        let mut builder = self.create_secondary_command_buffer_builder()?;
+        self.draw_egui(
+            scale_factor,
+            &items,
+            buffers,
+            framebuffer_dimensions,
+            &mut builder,
+            Self::run_paint_callback,
+        )?;
+        Ok(builder.build()?)
+    }
+
+    /// Records draw commands directly into `builder`, which must be inside the egui subpass.
+    /// Paint callbacks are skipped, because they require a secondary command buffer.
+    pub fn draw_into<L, A: CommandBufferAllocator>(
+        &mut self,
         clipped_meshes: &[ClippedPrimitive],
+        textures_delta: &TexturesDelta,
+        scale_factor: f32,
         framebuffer_dimensions: [u32; 2],
+        builder: &mut AutoCommandBufferBuilder<L, A>,
+    ) -> Result<(), EguiVulkanoError> {
+        let started = Instant::now();
+        self.update_textures(textures_delta)?;
+        self.sync_textures()?;
+        self.wait_pending_uploads()?;
+        let items = self.draw_items(clipped_meshes);
+        self.touch_textures(&items);
+        let buffers = self.mesh_buffers(&items, None)?;
+        self.draw_egui(
+            scale_factor,
+            &items,
+            buffers,
+            framebuffer_dimensions,
+            builder,
+            |_, _, _, _| {
+                println!(
+                    "Warning: Paint callbacks are not supported when drawing into a command \
+                     buffer builder"
+                );
+            },
+        )?;
+        for &id in &textures_delta.free {
+            self.unregister_image(id);
+        }
+        self.finish_frame(started);
+        Ok(())
+    }
+
+    fn run_paint_callback(
+        &self,
+        info: PaintCallbackInfo,
+        callback: &CallbackFn,
         builder: &mut AutoCommandBufferBuilder<SecondaryAutoCommandBuffer>,
     ) {
-        let push_constants = vs::PushConstants {
-            screen_size: [
-                framebuffer_dimensions[0] as f32 / scale_factor,
-                framebuffer_dimensions[1] as f32 / scale_factor,
-            ],
-            need_srgb_conv: self.need_srgb_conv.into(),
-        };
+        (callback.f)(info, &mut CallbackContext { builder, resources: self.render_resources() });
+    }
 
-        for ClippedPrimitive { clip_rect, primitive } in clipped_meshes {
-            match primitive {
-                Primitive::Mesh(mesh) => {
-                    // Nothing to draw if we don't have vertices & indices
-                    if mesh.vertices.is_empty() || mesh.indices.is_empty() {
-                        continue;
-                    }
-                    if self.texture_desc_sets.get(&mesh.texture_id).is_none() {
-                        eprintln!("This texture no longer exists {:?}", mesh.texture_id);
+    /// Records egui draw commands into a builder that is inside the egui subpass.
+    /// `paint_callback` is responsible for running paint callbacks on the builder.
+    fn draw_egui<L, A: CommandBufferAllocator>(
+        &self,
+        scale_factor: f32,
+        items: &[DrawItem],
+        buffers: Vec<MeshBuffers>,
+        framebuffer_dimensions: [u32; 2],
+        builder: &mut AutoCommandBufferBuilder<L, A>,
+        mut paint_callback: impl FnMut(
+            &Self,
+            PaintCallbackInfo,
+            &CallbackFn,
+            &mut AutoCommandBufferBuilder<L, A>,
+        ),
+    ) -> Result<(), EguiVulkanoError> {
+        let Some(target) = self.draw_target(scale_factor, framebuffer_dimensions) else {
+            return Ok(());
+        };
+        // From here on the dimensions are those of the region the ui is drawn in
+        let (offset, framebuffer_dimensions) = (target.offset, target.extent);
+        let mut state = RecordState::new(target.push_constants);
+        let mut cursor = BufferCursor::default();
+        let mut textures_bound = AHashSet::new();
+
+        for item in items {
+            match item {
+                DrawItem::Meshes(batch) => {
+                    let Some(batch) = self.prepare_batch(&target, batch, &buffers, &mut cursor)
+                    else {
                         continue;
-                    }
-
-                    let scissors = vec![self.get_rect_scissor(
-                        scale_factor,
-                        framebuffer_dimensions,
-                        *clip_rect,
-                    )];
-
-                    let (vertices, indices) = self.create_subbuffers(mesh);
-
-                    let desc_set = self.texture_desc_sets.get(&mesh.texture_id).unwrap();
-                    builder
-                        .bind_pipeline_graphics(self.pipeline.clone())
-                        .set_viewport(0, vec![Viewport {
-                            origin: [0.0, 0.0],
-                            dimensions: [
-                                framebuffer_dimensions[0] as f32,
-                                framebuffer_dimensions[1] as f32,
-                            ],
-                            depth_range: 0.0..1.0,
-                        }])
-                        .set_scissor(0, scissors)
-                        .bind_descriptor_sets(
-                            PipelineBindPoint::Graphics,
-                            self.pipeline.layout().clone(),
-                            0,
-                            desc_set.clone(),
-                        )
-                        .push_constants(self.pipeline.layout().clone(), 0, push_constants)
-                        .bind_vertex_buffers(0, vertices.clone())
-                        .bind_index_buffer(indices.clone())
-                        .draw_indexed(indices.len() as u32, 1, 0, 0, 0)
-                        .unwrap();
+                    };
+                    textures_bound.insert(batch.texture_id);
+                    record_batch(&self.pipeline, &target.viewport, &mut state, &batch, builder)?;
+                    self.count(|stats| stats.draw_calls += 1);
                 }
-                Primitive::Callback(callback) => {
+                DrawItem::Callback { clip_rect, callback } => {
                     if callback.rect.is_positive() {
                         let rect_min_x = scale_factor * callback.rect.min.x;
                         let rect_min_y = scale_factor * callback.rect.min.y;
                         let rect_max_x = scale_factor * callback.rect.max.x;
                         let rect_max_y = scale_factor * callback.rect.max.y;
 
-                        let rect_min_x = rect_min_x.round();
-                        let rect_min_y = rect_min_y.round();
-                        let rect_max_x = rect_max_x.round();
-                        let rect_max_y = rect_max_y.round();
+                        let rect_min_x = rect_min_x.round() + offset[0] as f32;
+                        let rect_min_y = rect_min_y.round() + offset[1] as f32;
+                        let rect_max_x = rect_max_x.round() + offset[0] as f32;
+                        let rect_max_y = rect_max_y.round() + offset[1] as f32;
 
-                        let scissors = vec![self.get_rect_scissor(
+                        let Some(scissor) = get_rect_scissor(
                             scale_factor,
+                            offset,
                             framebuffer_dimensions,
                             *clip_rect,
-                        )];
+                        ) else {
+                            continue;
+                        };
 
                         builder
                             .set_viewport(0, vec![Viewport {
@@ -645,7 +2593,8 @@ impl Renderer {
                                 dimensions: [rect_max_x - rect_min_x, rect_max_y - rect_min_y],
                                 depth_range: 0.0..1.0,
                             }])
-                            .set_scissor(0, scissors);
+                            .set_scissor(0, vec![scissor]);
+                        state.needs_rebind = true;
 
                         let info = egui::PaintCallbackInfo {
                             viewport: callback.rect,
@@ -655,10 +2604,7 @@ impl Renderer {
                         };
 
                         if let Some(callback) = callback.callback.downcast_ref::<CallbackFn>() {
-                            (callback.f)(info, &mut CallbackContext {
-                                builder,
-                                resources: self.render_resources(),
-                            });
+                            paint_callback(self, info, callback, builder);
                         } else {
                             println!(
                                 "Warning: Unsupported render callback. Expected \
@@ -669,9 +2615,167 @@ impl Renderer {
                 }
             }
         }
+        self.count(|stats| stats.textures_bound += textures_bound.len());
+        Ok(())
+    }
+
+    /// Whether the frame's meshes are recorded on worker threads with `draw_egui_parallel`
+    fn records_in_parallel(&self, items: &[DrawItem]) -> bool {
+        let Some(parallel) = self.parallel_recording else {
+            return false;
+        };
+        let mut meshes = 0;
+        for item in items {
+            match item {
+                DrawItem::Meshes(batch) => meshes += batch.meshes.len(),
+                // Paint callbacks need the renderer, which stays on this thread
+                DrawItem::Callback { .. } => return false,
+            }
+        }
+        self.recording_workers.is_some() && meshes >= parallel.min_meshes
+    }
+
+    /// Records the mesh batches of a frame without paint callbacks into one secondary command
+    /// buffer per chunk of consecutive batches, each on a recording thread. The mesh buffers are
+    /// written before, and the command buffers are returned in paint order.
+    fn draw_egui_parallel(
+        &self,
+        scale_factor: f32,
+        items: &[DrawItem],
+        buffers: Vec<MeshBuffers>,
+        framebuffer_dimensions: [u32; 2],
+    ) -> Result<Vec<SecondaryAutoCommandBuffer>, EguiVulkanoError> {
+        let Some(target) = self.draw_target(scale_factor, framebuffer_dimensions) else {
+            return Ok(vec![]);
+        };
+        let mut cursor = BufferCursor::default();
+        let batches: Vec<PreparedBatch> = items
+            .iter()
+            .filter_map(|item| match item {
+                DrawItem::Meshes(batch) => {
+                    self.prepare_batch(&target, batch, &buffers, &mut cursor)
+                }
+                DrawItem::Callback { .. } => unreachable!("Paint callbacks are recorded serially"),
+            })
+            .collect();
+        let textures_bound: AHashSet<_> = batches.iter().map(|batch| batch.texture_id).collect();
+        self.count(|stats| {
+            stats.draw_calls += batches.len();
+            stats.textures_bound += textures_bound.len();
+        });
+
+        let workers =
+            self.recording_workers.as_ref().expect("Parallel recording has no recording threads");
+        let threads = workers.threads();
+        let chunk_size = ((batches.len() + threads - 1) / threads).max(1);
+        let queue_family_index = self.gfx_queue.queue_family_index();
+        let mut batches = batches.into_iter().peekable();
+        let mut results = vec![];
+        while batches.peek().is_some() {
+            let chunk: Vec<PreparedBatch> = batches.by_ref().take(chunk_size).collect();
+            let allocators = self.allocators.clone();
+            let (subpass, pipeline) = (self.subpass.clone(), self.pipeline.clone());
+            let (viewport, push_constants) = (target.viewport.clone(), target.push_constants);
+            results.push(workers.run(move || -> Result<_, EguiVulkanoError> {
+                // Builders can't move between threads, so each job creates its own
+                let mut builder =
+                    secondary_builder(&allocators.command_buffer, queue_family_index, &subpass)?;
+                let mut state = RecordState::new(push_constants);
+                for batch in &chunk {
+                    record_batch(&pipeline, &viewport, &mut state, batch, &mut builder)?;
+                }
+                Ok(builder.build()?)
+            }));
+        }
+        results
+            .into_iter()
+            .map(|result| {
+                let result = result.recv().expect("Gui recording threads stopped");
+                result.unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+            })
+            .collect()
+    }
+
+    /// Returns the region of the framebuffer the ui is drawn in, or `None` if nothing is visible
+    fn draw_target(
+        &self,
+        scale_factor: f32,
+        framebuffer_dimensions: [u32; 2],
+    ) -> Option<DrawTarget> {
+        let UiViewport { offset, extent } = match self.ui_viewport {
+            Some(ui_viewport) => ui_viewport.clamped(framebuffer_dimensions),
+            None => UiViewport { offset: [0, 0], extent: framebuffer_dimensions },
+        };
+        // Nothing is visible on a zero-sized framebuffer (e.g. of a minimized window), and
+        // recording a zero-sized viewport would be invalid
+        if extent.contains(&0) {
+            return None;
+        }
+        Some(DrawTarget {
+            scale_factor,
+            offset,
+            extent,
+            viewport: Viewport {
+                origin: [offset[0] as f32, offset[1] as f32],
+                dimensions: [extent[0] as f32, extent[1] as f32],
+                depth_range: 0.0..1.0,
+            },
+            push_constants: vs::PushConstants {
+                screen_size: [extent[0] as f32 / scale_factor, extent[1] as f32 / scale_factor],
+                need_srgb_conv: self.need_srgb_conv.into(),
+                texture_conv: TEXTURE_CONV_NONE,
+            },
+        })
+    }
+
+    /// Slices a batch's vertices & indices from the frame's buffers and resolves its scissor &
+    /// texture. Returns `None` (counting the meshes as skipped) if its clip rect is empty.
+    fn prepare_batch(
+        &self,
+        target: &DrawTarget,
+        batch: &MeshBatch,
+        buffers: &[MeshBuffers],
+        cursor: &mut BufferCursor,
+    ) -> Option<PreparedBatch> {
+        if cursor.batch == buffers[cursor.segment].num_batches {
+            *cursor = BufferCursor { segment: cursor.segment + 1, ..BufferCursor::default() };
+        }
+        let buffers = &buffers[cursor.segment];
+        cursor.batch += 1;
+        let num_vertices = batch.num_vertices as DeviceSize;
+        let num_indices = batch.num_indices as DeviceSize;
+        let vertices =
+            buffers.vertices.clone().slice(cursor.vertices..cursor.vertices + num_vertices);
+        cursor.vertices += num_vertices;
+        // Sliced before skipping, so the offsets stay in step with the buffers
+        let indices = if batch.uses_u16_indices() {
+            let range = cursor.indices_u16..cursor.indices_u16 + num_indices;
+            cursor.indices_u16 += num_indices;
+            MeshIndices::U16(buffers.indices_u16.clone().unwrap().slice(range))
+        } else {
+            let range = cursor.indices_u32..cursor.indices_u32 + num_indices;
+            cursor.indices_u32 += num_indices;
+            MeshIndices::U32(buffers.indices_u32.clone().unwrap().slice(range))
+        };
+
+        let Some(scissor) =
+            get_rect_scissor(target.scale_factor, target.offset, target.extent, batch.clip_rect)
+        else {
+            self.count(|stats| stats.skipped_meshes += batch.meshes.len());
+            return None;
+        };
+        Some(PreparedBatch {
+            texture_id: batch.texture_id,
+            desc_set: self.texture_desc_sets[&batch.texture_id].clone(),
+            texture_conv: self.texture_conversion(batch.texture_id),
+            scissor,
+            vertices,
+            indices,
+            num_indices: num_indices as u32,
+        })
     }
 
-    pub fn render_resources(&self) -> RenderResources {
+    pub fn render_resources(&self) -> RenderResources<'_> {
         RenderResources {
             queue: self.queue(),
             subpass: self.subpass.clone(),
@@ -681,22 +2785,297 @@ impl Renderer {
         }
     }
 
+    pub fn subpass(&self) -> Subpass {
+        self.subpass.clone()
+    }
+
     pub fn queue(&self) -> Arc<Queue> {
         self.gfx_queue.clone()
     }
 
-    pub fn allocators(&self) -> &Allocators {
-        &self.allocators
+    /// Drops cached framebuffers of another size than the recreated swapchain's images, and
+    /// recreates the render pass & pipeline for `format` if it differs from the current one.
+    /// Returns an error if the device can't draw to `format`, keeping the previous render pass.
+    ///
+    /// With a subpass, the format is your render pass', so `format` is ignored, see
+    /// `set_subpass`.
+    pub fn swapchain_recreated(
+        &mut self,
+        size: [u32; 2],
+        format: Option<Format>,
+    ) -> Result<(), GuiCreationError> {
+        self.framebuffers.retain(|_, fb| fb.extent() == size);
+        let Some(format) = format.filter(|format| *format != self.format) else {
+            return Ok(());
+        };
+        if self.render_pass.is_none() {
+            return Ok(());
+        }
+        let samples = self.subpass.num_samples().unwrap_or(SampleCount::Sample1);
+        check_device_support(&self.gfx_queue, format, samples, 1)?;
+        let render_pass =
+            create_gui_render_pass(self.gfx_queue.device().clone(), format, RenderPassOptions {
+                clear_mode: self.clear_mode,
+                depth_format: None,
+                samples,
+            })?;
+        let subpass = render_pass.clone().first_subpass();
+        self.pipeline = self.pipeline_for(&subpass)?;
+        self.subpass = subpass;
+        self.render_pass = Some(render_pass);
+        self.need_srgb_conv = format.type_color().unwrap() == NumericType::UNORM;
+        self.format = format;
+        // Framebuffers & the offscreen targets were created for the previous format
+        self.framebuffers.clear();
+        self.offscreen_targets.clear();
+        self.last_targets.clear();
+        Ok(())
+    }
+
+    /// Drops cached framebuffers and the last drawn image, e.g. swapchain images of a surface
+    /// that is destroyed
+    pub fn release_targets(&mut self) {
+        self.framebuffers.clear();
+        self.last_targets.remove(&self.context);
+    }
+
+    /// Returns the image the current context last drew on with `draw_on_image` or
+    /// `draw_on_texture`
+    pub fn last_target(&self) -> Option<Arc<dyn ImageViewAbstract + 'static>> {
+        self.last_targets.get(&self.context).cloned()
+    }
+
+    /// Returns the allocators for sharing them with other threads
+    pub fn shared_allocators(&self) -> Arc<Allocators> {
+        self.allocators.clone()
+    }
+
+    /// Enables or disables measuring the GPU time of drawing with the renderer's own render pass.
+    /// Stays disabled if the queue doesn't support timestamps.
+    pub fn set_gpu_profiling(&mut self, enabled: bool) {
+        if !enabled {
+            self.gpu_timer = None;
+        } else if self.gpu_timer.is_none() {
+            self.gpu_timer = GpuTimer::new(&self.gfx_queue);
+        }
+    }
+
+    /// Returns the most recently resolved GPU time of drawing, if profiling is enabled
+    pub fn last_gpu_time(&mut self) -> Option<Duration> {
+        let gpu_timer = self.gpu_timer.as_mut()?;
+        gpu_timer.resolve();
+        gpu_timer.last_time
+    }
+
+    /// Returns the stats of the last drawn frame
+    pub fn last_stats(&self) -> RenderStats {
+        self.last_stats
+    }
+
+    fn count(&self, f: impl FnOnce(&mut RenderStats)) {
+        let mut stats = self.stats.get();
+        f(&mut stats);
+        self.stats.set(stats);
+    }
+
+    // Ends counting the stats of the drawn frame, whose draw began at `started`
+    fn finish_frame(&mut self, started: Instant) {
+        let upload_time = self.stats.get().upload_time;
+        self.finish_frame_recorded(started.elapsed().saturating_sub(upload_time));
+    }
+
+    // Ends counting the stats of the drawn frame, whose commands took `record_time` to record
+    fn finish_frame_recorded(&mut self, record_time: Duration) {
+        self.evict_over_budget();
+        let mut stats = self.stats.take();
+        stats.record_time = record_time;
+        self.last_stats = stats;
+        self.frame_count += 1;
+        self.drop_retired_textures();
+    }
+}
+
+/// Splits `batches` into runs whose vertices & indices of each index type fit in buffers of
+/// `max_size` bytes. Errors if a batch alone doesn't fit.
+fn mesh_segments<'b, 'a>(
+    batches: &'b [&'b MeshBatch<'a>],
+    max_size: DeviceSize,
+) -> Result<Vec<&'b [&'b MeshBatch<'a>]>, EguiVulkanoError> {
+    let sizes = |batch: &MeshBatch| {
+        let vertices = (batch.num_vertices * std::mem::size_of::<EguiVertex>()) as DeviceSize;
+        let indices = batch.num_indices as DeviceSize;
+        if batch.uses_u16_indices() {
+            [vertices, indices * 2, 0]
+        } else {
+            [vertices, 0, indices * 4]
+        }
+    };
+    let mut segments = vec![];
+    let (mut start, mut used) = (0, [0; 3]);
+    for (i, batch) in batches.iter().enumerate() {
+        let sizes = sizes(batch);
+        if sizes.iter().any(|&size| size > max_size) {
+            return Err(EguiVulkanoError::FrameTooLarge {
+                vertices: batch.num_vertices,
+                indices: batch.num_indices,
+                max_buffer_size: max_size,
+            });
+        }
+        if used.iter().zip(&sizes).any(|(used, size)| used + size > max_size) {
+            segments.push(&batches[start..i]);
+            (start, used) = (i, [0; 3]);
+        }
+        for (used, size) in used.iter_mut().zip(&sizes) {
+            *used += size;
+        }
+    }
+    if start < batches.len() {
+        segments.push(&batches[start..]);
+    }
+    Ok(segments)
+}
+
+/// Number of frames whose timestamps can be in flight at once
+const GPU_TIMER_FRAMES: u32 = 4;
+
+/// Measures GPU time between timestamps written around the renderer's commands. Results are
+/// read without waiting once the GPU has finished a frame, and a frame's queries are only
+/// reused after they have been read.
+struct GpuTimer {
+    query_pool: Arc<QueryPool>,
+    /// Nanoseconds per timestamp tick
+    timestamp_period: f32,
+    timestamp_mask: u64,
+    in_flight: [bool; GPU_TIMER_FRAMES as usize],
+    next_frame: u32,
+    current_frame: Option<u32>,
+    last_time: Option<Duration>,
+}
+
+impl GpuTimer {
+    fn new(queue: &Arc<Queue>) -> Option<GpuTimer> {
+        let physical_device = queue.device().physical_device();
+        let valid_bits = physical_device.queue_family_properties()
+            [queue.queue_family_index() as usize]
+            .timestamp_valid_bits?;
+        let query_pool = QueryPool::new(queue.device().clone(), QueryPoolCreateInfo {
+            query_count: GPU_TIMER_FRAMES * 2,
+            ..QueryPoolCreateInfo::query_type(QueryType::Timestamp)
+        })
+        .ok()?;
+        Some(GpuTimer {
+            query_pool,
+            timestamp_period: physical_device.properties().timestamp_period,
+            timestamp_mask: u64::MAX >> (64 - valid_bits.min(64)),
+            in_flight: [false; GPU_TIMER_FRAMES as usize],
+            next_frame: 0,
+            current_frame: None,
+            last_time: None,
+        })
+    }
+
+    /// Reads the results of finished frames, oldest first
+    fn resolve(&mut self) {
+        for i in 0..GPU_TIMER_FRAMES {
+            let frame = (self.next_frame + i) % GPU_TIMER_FRAMES;
+            if !self.in_flight[frame as usize] {
+                continue;
+            }
+            let mut timestamps = [0u64; 2];
+            let available = self
+                .query_pool
+                .queries_range(frame * 2..frame * 2 + 2)
+                .unwrap()
+                .get_results(&mut timestamps, QueryResultFlags::empty());
+            if let Ok(true) = available {
+                self.in_flight[frame as usize] = false;
+                let ticks = timestamps[1].wrapping_sub(timestamps[0]) & self.timestamp_mask;
+                self.last_time = Some(Duration::from_nanos(
+                    (ticks as f64 * self.timestamp_period as f64) as u64,
+                ));
+            }
+        }
+    }
+
+    /// Writes the starting timestamp, unless the next frame's queries are still in flight
+    fn begin(&mut self, builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>) {
+        self.resolve();
+        let frame = self.next_frame;
+        if self.in_flight[frame as usize] {
+            self.current_frame = None;
+            return;
+        }
+        // Safety: the queries of this frame are not in use, their previous results have been read
+        let written = unsafe {
+            builder.reset_query_pool(self.query_pool.clone(), frame * 2..frame * 2 + 2).is_ok()
+                && builder
+                    .write_timestamp(self.query_pool.clone(), frame * 2, PipelineStage::TopOfPipe)
+                    .is_ok()
+        };
+        // Timing is best effort, a frame whose queries can't be written isn't timed
+        if !written {
+            self.current_frame = None;
+            return;
+        }
+        self.in_flight[frame as usize] = true;
+        self.next_frame = (frame + 1) % GPU_TIMER_FRAMES;
+        self.current_frame = Some(frame);
+    }
+
+    /// Writes the ending timestamp of the frame begun with `begin`
+    fn end(&mut self, builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>) {
+        if let Some(frame) = self.current_frame.take() {
+            // Safety: the query was reset in `begin`
+            let written = unsafe {
+                builder
+                    .write_timestamp(
+                        self.query_pool.clone(),
+                        frame * 2 + 1,
+                        PipelineStage::BottomOfPipe,
+                    )
+                    .is_ok()
+            };
+            if !written {
+                self.in_flight[frame as usize] = false;
+            }
+        }
     }
 }
 
+/// Returns the format of the first color attachment of `subpass`, if it has one
+/// Approximate memory of `image` with all its mip levels & array layers
+fn image_memory(image: &dyn ImageAccess) -> u64 {
+    let format = image.format();
+    let (Some(block_size), [block_width, block_height, _]) =
+        (format.block_size(), format.block_extent())
+    else {
+        return 0;
+    };
+    let dimensions = image.dimensions();
+    (0..image.mip_levels())
+        .map(|level| {
+            let width = (dimensions.width() >> level).max(1);
+            let height = (dimensions.height() >> level).max(1);
+            let blocks_wide = (width + block_width - 1) / block_width;
+            let blocks_high = (height + block_height - 1) / block_height;
+            blocks_wide as u64 * blocks_high as u64 * block_size
+        })
+        .sum::<u64>()
+        * dimensions.array_layers() as u64
+}
+
+pub(crate) fn subpass_color_format(subpass: &Subpass) -> Option<Format> {
+    let color_attachment = subpass.subpass_desc().color_attachments.first()?.as_ref()?;
+    subpass.render_pass().attachments()[color_attachment.attachment as usize].format
+}
+
 /// A set of objects used to perform custom rendering in a `PaintCallback`. It
 /// includes [`RenderResources`] for constructing a subpass pipeline and a secondary
 /// command buffer for pushing render commands onto it.
 ///
 /// # Example
 ///
-/// See the `triangle` demo source for a detailed usage example.
+/// See the `paint_callback` example for a detailed usage example.
 pub struct CallbackContext<'a> {
     pub builder: &'a mut AutoCommandBufferBuilder<SecondaryAutoCommandBuffer>,
     pub resources: RenderResources<'a>,
@@ -707,7 +3086,7 @@ pub struct CallbackContext<'a> {
 ///
 /// # Example
 ///
-/// See the `triangle` demo source for a detailed usage example.
+/// See the `paint_callback` example for a detailed usage example.
 #[derive(Clone)]
 pub struct RenderResources<'a> {
     pub memory_allocator: Arc<StandardMemoryAllocator>,
@@ -727,7 +3106,7 @@ pub type CallbackFnDef = dyn Fn(PaintCallbackInfo, &mut CallbackContext) + Sync
 ///
 /// # Example
 ///
-/// See the `triangle` demo source for a detailed usage example.
+/// See the `paint_callback` example for a detailed usage example.
 pub struct CallbackFn {
     pub(crate) f: Box<CallbackFnDef>,
 }
@@ -756,6 +3135,7 @@ layout(location = 1) out vec2 v_tex_coords;
 layout(push_constant) uniform PushConstants {
     vec2 screen_size;
     int need_srgb_conv;
+    int texture_conv;
 } push_constants;
 
 // 0-1 linear  from  0-255 sRGB
@@ -798,6 +3178,7 @@ layout(binding = 0, set = 0) uniform sampler2D font_texture;
 layout(push_constant) uniform PushConstants {
     vec2 screen_size;
     int need_srgb_conv;
+    int texture_conv;
 } push_constants;
 
 // 0-255 sRGB  from  0-1 linear
@@ -826,6 +3207,12 @@ vec4 linear_from_srgba(vec4 srgba) {
 
 void main() {
     vec4 texture_color = texture(font_texture, v_tex_coords);
+    // Reinterpret user textures whose texels aren't encoded as their format says
+    if (push_constants.texture_conv == 1) {
+        texture_color = linear_from_srgba(texture_color);
+    } else if (push_constants.texture_conv == 2) {
+        texture_color = vec4(srgb_from_linear(texture_color.rgb) / 255.0, texture_color.a);
+    }
 
     if (push_constants.need_srgb_conv == 0) {
         f_color = v_color * texture_color;
@@ -836,3 +3223,317 @@ void main() {
 }"
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mesh(num_vertices: usize) -> Mesh {
+        Mesh {
+            indices: (0..num_vertices as u32).collect(),
+            vertices: vec![Default::default(); num_vertices],
+            texture_id: egui::TextureId::default(),
+        }
+    }
+
+    fn batch(mesh: &Mesh) -> MeshBatch<'_> {
+        MeshBatch::new(mesh.texture_id, false, Rect::EVERYTHING, mesh)
+    }
+
+    fn append<'a>(batch: &mut MeshBatch<'a>, mesh: &'a Mesh) -> bool {
+        batch.try_append(mesh.texture_id, false, Rect::EVERYTHING, mesh)
+    }
+
+    #[test]
+    fn batches_up_to_u16_indexable_vertices_use_u16_indices() {
+        assert_eq!(U16_INDEXABLE_VERTICES, 65_536);
+        for (num_vertices, uses_u16_indices) in [(65_535, true), (65_536, true), (65_537, false)] {
+            let mesh = mesh(num_vertices);
+            assert_eq!(batch(&mesh).uses_u16_indices(), uses_u16_indices, "{}", num_vertices);
+        }
+    }
+
+    #[test]
+    fn meshes_are_batched_up_to_u16_indexable_vertices() {
+        let (first, fits, one_more) = (mesh(65_535), mesh(1), mesh(1));
+        let mut full = batch(&first);
+        assert!(append(&mut full, &fits));
+        assert_eq!(full.num_vertices, 65_536);
+        assert!(full.uses_u16_indices());
+        assert!(!append(&mut full, &one_more));
+        assert_eq!((full.meshes.len(), full.num_vertices, full.num_indices), (2, 65_536, 65_536));
+
+        let (first, too_many) = (mesh(65_534), mesh(3));
+        let mut batch = batch(&first);
+        assert!(!append(&mut batch, &too_many));
+        assert!(batch.uses_u16_indices());
+    }
+
+    #[test]
+    fn large_meshes_get_their_own_u32_batch() {
+        let (small, large) = (mesh(3), mesh(70_000));
+        let mut small_batch = batch(&small);
+        assert!(!append(&mut small_batch, &large));
+        let mut large_batch = batch(&large);
+        assert!(!large_batch.uses_u16_indices());
+        assert!(!append(&mut large_batch, &small));
+    }
+
+    #[test]
+    fn batch_indices_are_rebased_onto_the_batch_vertices() {
+        let first = Mesh { indices: vec![0, 1, 2, 2, 1, 3], ..mesh(4) };
+        let second = Mesh { indices: vec![2, 0, 1], ..mesh(3) };
+        let third = mesh(2);
+        let mut batch = batch(&first);
+        assert!(append(&mut batch, &second));
+        assert!(append(&mut batch, &third));
+        let indices: Vec<u32> = batch.indices().collect();
+        assert_eq!(indices, vec![0, 1, 2, 2, 1, 3, 6, 4, 5, 7, 8]);
+        assert_eq!(indices.len(), batch.num_indices);
+        assert!(indices.iter().all(|&i| (i as usize) < batch.num_vertices));
+    }
+
+    #[test]
+    fn large_mesh_indices_are_kept() {
+        let large = Mesh { indices: vec![70_000, 0, 65_536], ..mesh(70_001) };
+        let batch = batch(&large);
+        assert_eq!(batch.indices().collect::<Vec<_>>(), vec![70_000, 0, 65_536]);
+    }
+
+    #[test]
+    fn meshes_with_other_textures_or_clip_rects_are_not_batched() {
+        let (first, second) = (mesh(3), mesh(3));
+        let mut batch = batch(&first);
+        let user_texture = egui::TextureId::User(1);
+        assert!(!batch.try_append(user_texture, false, Rect::EVERYTHING, &second));
+        assert!(!batch.try_append(first.texture_id, true, Rect::EVERYTHING, &second));
+        let clip_rect = Rect::from_min_size(egui::Pos2::ZERO, egui::vec2(10.0, 10.0));
+        assert!(!batch.try_append(first.texture_id, false, clip_rect, &second));
+        assert_eq!(batch.meshes.len(), 1);
+    }
+
+    // Batch of the given size without meshes, `mesh_segments` only looks at its size
+    fn sized_batch(num_vertices: usize, num_indices: usize) -> MeshBatch<'static> {
+        MeshBatch {
+            texture_id: egui::TextureId::default(),
+            placeholder: false,
+            clip_rect: Rect::EVERYTHING,
+            meshes: vec![],
+            num_vertices,
+            num_indices,
+        }
+    }
+
+    // Sizes of the batches of each segment
+    fn segment_sizes(
+        batches: &[MeshBatch],
+        max_size: DeviceSize,
+    ) -> Result<Vec<Vec<(usize, usize)>>, EguiVulkanoError> {
+        let batches: Vec<_> = batches.iter().collect();
+        let segments = mesh_segments(&batches, max_size)?;
+        Ok(segments
+            .iter()
+            .map(|segment| {
+                segment.iter().map(|batch| (batch.num_vertices, batch.num_indices)).collect()
+            })
+            .collect())
+    }
+
+    #[test]
+    fn segments_split_when_vertices_are_full() {
+        assert_eq!(std::mem::size_of::<EguiVertex>(), 20);
+        // 10 vertices or 50 u16 indices fit
+        let batches = [sized_batch(4, 6), sized_batch(4, 6), sized_batch(4, 6)];
+        assert_eq!(
+            segment_sizes(&batches, 200).unwrap(),
+            vec![vec![(4, 6), (4, 6)], vec![(4, 6)],]
+        );
+        let batches = [sized_batch(5, 6), sized_batch(5, 6)];
+        assert_eq!(segment_sizes(&batches, 200).unwrap(), vec![vec![(5, 6), (5, 6)]]);
+        assert!(segment_sizes(&[], 200).unwrap().is_empty());
+    }
+
+    #[test]
+    fn segments_split_when_u16_indices_are_full() {
+        let batches = [sized_batch(1, 30), sized_batch(1, 30), sized_batch(1, 20)];
+        assert_eq!(segment_sizes(&batches, 100).unwrap(), vec![vec![(1, 30)], vec![
+            (1, 30),
+            (1, 20)
+        ],]);
+    }
+
+    #[test]
+    fn u16_and_u32_indices_are_counted_separately() {
+        // A u32 batch's vertices take 1.4 MB and its indices 1.6 MB, a u16 batch's indices 1.2 MB
+        let max_size = 3_000_000;
+        let u32_batch = || sized_batch(70_000, 400_000);
+        let u16_batch = || sized_batch(1, 600_000);
+        let batches = [u32_batch(), u16_batch(), u32_batch()];
+        assert_eq!(segment_sizes(&batches, max_size).unwrap(), vec![
+            vec![(70_000, 400_000), (1, 600_000)],
+            vec![(70_000, 400_000)],
+        ]);
+        let batches = [u16_batch(), u32_batch(), u16_batch(), u16_batch()];
+        assert_eq!(segment_sizes(&batches, max_size).unwrap(), vec![
+            vec![(1, 600_000), (70_000, 400_000), (1, 600_000)],
+            vec![(1, 600_000)],
+        ]);
+    }
+
+    #[test]
+    fn oversized_batches_are_too_large() {
+        let batches = [sized_batch(4, 6), sized_batch(11, 6)];
+        assert!(matches!(
+            segment_sizes(&batches, 200),
+            Err(EguiVulkanoError::FrameTooLarge { vertices: 11, indices: 6, max_buffer_size: 200 })
+        ));
+        assert!(matches!(
+            segment_sizes(&[sized_batch(1, 51)], 100),
+            Err(EguiVulkanoError::FrameTooLarge { vertices: 1, indices: 51, .. })
+        ));
+        assert!(matches!(
+            segment_sizes(&[sized_batch(70_000, 400_000)], 1_500_000),
+            Err(EguiVulkanoError::FrameTooLarge { vertices: 70_000, indices: 400_000, .. })
+        ));
+    }
+
+    fn retired_at(frame: u64) -> RetiredTexture {
+        RetiredTexture { frame, _desc_set: None, _image: None, _sampler: None }
+    }
+
+    fn retired_frames(retired: &RetiredTextures) -> Vec<u64> {
+        retired.textures.iter().map(|retired| retired.frame).collect()
+    }
+
+    #[test]
+    fn retired_textures_are_kept_for_frames_in_flight() {
+        let frames_in_flight = 2;
+        let mut retired = RetiredTextures::default();
+        // A texture is unregistered (or registered & replaced) before every draw
+        for frame_count in 0..300 {
+            retired.retire(retired_at(frame_count));
+            retired.retire(retired_at(frame_count));
+            // The draw before may still use them
+            retired.drop_unused(frame_count + 1, frames_in_flight);
+            assert_eq!(retired_frames(&retired), vec![frame_count; 2]);
+        }
+    }
+
+    #[test]
+    fn retired_textures_are_kept_until_their_draws_completed() {
+        let frames_in_flight = 2;
+        let mut retired = RetiredTextures::default();
+        // The GPU is 3 draws behind, more than frames in flight
+        for frame_count in 1..300u64 {
+            retired.retire(retired_at(frame_count - 1));
+            if let Some(completed) = frame_count.checked_sub(4) {
+                retired.frame_completed(completed, frame_count);
+            }
+            retired.drop_unused(frame_count, frames_in_flight);
+            if frame_count >= 4 {
+                // Draw `completed + 1` already used textures retired before it only
+                let oldest = frame_count - 2;
+                assert_eq!(retired_frames(&retired), (oldest..frame_count).collect::<Vec<_>>());
+            }
+        }
+    }
+
+    #[test]
+    fn retired_textures_fall_back_to_frames_in_flight_without_completions() {
+        let frames_in_flight = 2;
+        let mut retired = RetiredTextures::default();
+        retired.frame_completed(0, 1);
+        for frame_count in 1..300 {
+            retired.retire(retired_at(frame_count));
+            retired.drop_unused(frame_count + 1, frames_in_flight);
+            assert!(retired.textures.len() <= frames_in_flight as usize, "{}", frame_count);
+        }
+        // Completions are used again once they arrive
+        retired.retire(retired_at(299));
+        retired.retire(retired_at(300));
+        retired.frame_completed(298, 300);
+        retired.drop_unused(301, frames_in_flight);
+        assert_eq!(retired_frames(&retired), vec![300]);
+    }
+
+    fn scissor(origin: [u32; 2], dimensions: [u32; 2]) -> Option<Scissor> {
+        Some(Scissor { origin, dimensions })
+    }
+
+    #[test]
+    fn scissors_round_outwards_at_fractional_scale_factors() {
+        let rect = Rect::from_min_max(egui::pos2(1.0, 1.0), egui::pos2(11.0, 11.0));
+        let cases = [
+            (1.0, scissor([1, 1], [10, 10])),
+            // 1.25..13.75
+            (1.25, scissor([1, 1], [13, 13])),
+            // 1.5..16.5
+            (1.5, scissor([1, 1], [16, 16])),
+            (2.0, scissor([2, 2], [20, 20])),
+        ];
+        for (scale_factor, expected) in cases {
+            assert_eq!(get_rect_scissor(scale_factor, [0, 0], [100, 80], rect), expected);
+        }
+        assert_eq!(get_rect_scissor(1.5, [5, 7], [100, 80], rect), scissor([6, 8], [16, 16]));
+    }
+
+    #[test]
+    fn scissors_touching_edges_cover_the_last_pixel() {
+        let dimensions = [100, 80];
+        for scale_factor in [1.0, 1.25, 1.5, 2.0] {
+            let screen =
+                Rect::from_min_size(egui::Pos2::ZERO, egui::vec2(100.0, 80.0) / scale_factor);
+            assert_eq!(
+                get_rect_scissor(scale_factor, [0, 0], dimensions, screen),
+                scissor([0, 0], dimensions),
+                "{}",
+                scale_factor
+            );
+        }
+        // 79.9 * 1.25 = 99.875
+        let right = Rect::from_min_max(egui::pos2(70.0, 0.0), egui::pos2(79.9, 10.0));
+        assert_eq!(get_rect_scissor(1.25, [0, 0], dimensions, right), scissor([87, 0], [13, 13]));
+    }
+
+    #[test]
+    fn scissors_are_clamped_to_the_framebuffer() {
+        let dimensions = [100, 80];
+        let overhanging = Rect::from_min_max(egui::pos2(-10.0, -10.0), egui::pos2(200.0, 200.0));
+        assert_eq!(
+            get_rect_scissor(1.25, [0, 0], dimensions, overhanging),
+            scissor([0, 0], dimensions)
+        );
+        let offset = [10, 20];
+        assert_eq!(
+            get_rect_scissor(1.0, offset, dimensions, overhanging),
+            scissor(offset, dimensions)
+        );
+    }
+
+    #[test]
+    fn invisible_scissors_are_skipped() {
+        let dimensions = [100, 80];
+        let outside = Rect::from_min_max(egui::pos2(150.0, 0.0), egui::pos2(160.0, 10.0));
+        assert_eq!(get_rect_scissor(1.0, [0, 0], dimensions, outside), None);
+        let above = Rect::from_min_max(egui::pos2(0.0, -20.0), egui::pos2(10.0, -10.0));
+        assert_eq!(get_rect_scissor(1.5, [0, 0], dimensions, above), None);
+        let empty = Rect::from_min_max(egui::pos2(10.0, 10.0), egui::pos2(10.0, 20.0));
+        assert_eq!(get_rect_scissor(2.0, [0, 0], dimensions, empty), None);
+    }
+
+    #[test]
+    fn arenas_double_until_frames_fit() {
+        let max = 1 << 30;
+        assert_eq!(grown_arena_size(1024, 1000, max), 1024);
+        assert_eq!(grown_arena_size(1024, 1024, max), 1024);
+        assert_eq!(grown_arena_size(1024, 1025, max), 2048);
+        assert_eq!(grown_arena_size(1024, 5000, max), 8192);
+    }
+
+    #[test]
+    fn arenas_dont_grow_beyond_max_size() {
+        assert_eq!(grown_arena_size(1024, 5000, 4096), 4096);
+        // Arenas the allocator already grew past the max stay as they are
+        assert_eq!(grown_arena_size(8192, 10_000, 4096), 8192);
+    }
+}
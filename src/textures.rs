@@ -0,0 +1,198 @@
+// Copyright (c) 2021 Okko Hakola
+// Licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT
+// license <LICENSE-MIT or https://opensource.org/licenses/MIT>,
+// at your option. All files in the project carrying such
+// notice may not be copied, modified, or distributed except
+// according to those terms.
+
+use std::sync::{Arc, Mutex};
+
+use ahash::AHashMap;
+use vulkano::image::ImageViewAbstract;
+
+/// Images of egui's textures by id: font atlases (`TextureId::Managed`) and user images
+/// (`TextureId::User`). Renderers create what they need to draw an image (descriptor sets,
+/// samplers) themselves, so a registry can be shared between guis with separate renderers, see
+/// `GuiConfig::texture_registry`. Each renderer creates a private one by default.
+///
+/// User images registered, replaced or unregistered here or by another renderer are picked up by
+/// a renderer on its next draw. Images it didn't register itself are sampled with egui's linear
+/// clamp-to-edge sampler. Font atlases aren't shared: guis with separate renderers use the same
+/// ids for theirs, so `get` returns the one uploaded last.
+#[derive(Default)]
+pub struct TextureRegistry {
+    textures: Mutex<Textures>,
+}
+
+#[derive(Default)]
+pub(crate) struct Textures {
+    pub(crate) images:
+        AHashMap<egui::TextureId, Arc<dyn ImageViewAbstract + Send + Sync + 'static>>,
+    /// Incremented on every change, so renderers only look for changes after one
+    pub(crate) generation: u64,
+    next_user_id: u64,
+}
+
+impl TextureRegistry {
+    pub fn new() -> TextureRegistry {
+        TextureRegistry::default()
+    }
+
+    /// Returns the image of `texture_id`, if registered
+    pub fn get(
+        &self,
+        texture_id: egui::TextureId,
+    ) -> Option<Arc<dyn ImageViewAbstract + Send + Sync + 'static>> {
+        self.textures.lock().unwrap().images.get(&texture_id).cloned()
+    }
+
+    /// Whether `texture_id` has an image
+    pub fn contains(&self, texture_id: egui::TextureId) -> bool {
+        self.textures.lock().unwrap().images.contains_key(&texture_id)
+    }
+
+    /// Returns the registered textures and their images, as registered when called
+    pub fn iter(
+        &self,
+    ) -> impl Iterator<Item = (egui::TextureId, Arc<dyn ImageViewAbstract + Send + Sync + 'static>)>
+    {
+        let textures = self.textures.lock().unwrap();
+        let entries: Vec<_> =
+            textures.images.iter().map(|(id, image)| (*id, image.clone())).collect();
+        entries.into_iter()
+    }
+
+    /// Registers `image` as a new user texture. Use `Gui::register_user_image_view` instead to
+    /// choose its sampler.
+    pub fn register(
+        &self,
+        image: Arc<dyn ImageViewAbstract + Send + Sync + 'static>,
+    ) -> egui::TextureId {
+        let id = self.allocate_user_id();
+        self.insert(id, image);
+        id
+    }
+
+    /// Unregisters a texture, returning its image. Renderers keep the image until the frames
+    /// that may use it have finished.
+    pub fn unregister(
+        &self,
+        texture_id: egui::TextureId,
+    ) -> Option<Arc<dyn ImageViewAbstract + Send + Sync + 'static>> {
+        let mut textures = self.textures.lock().unwrap();
+        let image = textures.images.remove(&texture_id);
+        if image.is_some() {
+            textures.generation += 1;
+        }
+        image
+    }
+
+    /// Returns a new user texture id. Ids are never reused.
+    pub(crate) fn allocate_user_id(&self) -> egui::TextureId {
+        let mut textures = self.textures.lock().unwrap();
+        textures.next_user_id += 1;
+        egui::TextureId::User(textures.next_user_id - 1)
+    }
+
+    /// Sets the image of `texture_id`, returning the previous one
+    pub(crate) fn insert(
+        &self,
+        texture_id: egui::TextureId,
+        image: Arc<dyn ImageViewAbstract + Send + Sync + 'static>,
+    ) -> Option<Arc<dyn ImageViewAbstract + Send + Sync + 'static>> {
+        let mut textures = self.textures.lock().unwrap();
+        textures.generation += 1;
+        textures.images.insert(texture_id, image)
+    }
+
+    /// Runs `f` with the textures locked
+    pub(crate) fn with_textures<R>(&self, f: impl FnOnce(&Textures) -> R) -> R {
+        f(&self.textures.lock().unwrap())
+    }
+}
+
+/// A user image registered with `Gui::load_image`, which knows its size and is unregistered
+/// when dropped. Shows the image at its size in pixels, or scaled keeping its aspect ratio.
+///
+/// The texture is unregistered through the texture registry, so dropping the image while the
+/// gui is drawing is fine: renderers drop it on their next draw, once the frames that may use it
+/// have finished.
+pub struct RegisteredImage {
+    texture_id: egui::TextureId,
+    /// Size in pixels
+    dimensions: [u32; 2],
+    textures: Arc<TextureRegistry>,
+}
+
+impl RegisteredImage {
+    pub(crate) fn new(
+        texture_id: egui::TextureId,
+        dimensions: [u32; 2],
+        textures: Arc<TextureRegistry>,
+    ) -> RegisteredImage {
+        RegisteredImage { texture_id, dimensions, textures }
+    }
+
+    pub fn texture_id(&self) -> egui::TextureId {
+        self.texture_id
+    }
+
+    /// Returns the width & height of the image in pixels
+    pub fn dimensions(&self) -> [u32; 2] {
+        self.dimensions
+    }
+
+    /// Returns the size in points the image covers its own pixels at with `pixels_per_point`
+    pub fn size(&self, pixels_per_point: f32) -> egui::Vec2 {
+        egui::vec2(self.dimensions[0] as f32, self.dimensions[1] as f32) / pixels_per_point
+    }
+
+    /// Shows the image `desired_width` points wide keeping its aspect ratio, or pixel for pixel
+    /// if `None`
+    pub fn show(&self, ui: &mut egui::Ui, desired_width: Option<f32>) -> egui::Response {
+        let size = self.size(ui.ctx().pixels_per_point());
+        let size = match desired_width {
+            Some(width) => size * (width / size.x),
+            None => size,
+        };
+        ui.image(self.texture_id, size)
+    }
+
+    /// Shows the image pixel for pixel, scaled down keeping its aspect ratio if it's larger than
+    /// `max_size` points
+    pub fn show_max_size(&self, ui: &mut egui::Ui, max_size: egui::Vec2) -> egui::Response {
+        let size = self.size(ui.ctx().pixels_per_point());
+        let scale = (max_size.x / size.x).min(max_size.y / size.y).min(1.0);
+        ui.image(self.texture_id, size * scale)
+    }
+}
+
+impl Drop for RegisteredImage {
+    fn drop(&mut self) {
+        self.textures.unregister(self.texture_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unregistered_ids_are_not_reused() {
+        let textures = TextureRegistry::new();
+        let first = textures.allocate_user_id();
+        assert!(textures.unregister(first).is_none());
+        let second = textures.allocate_user_id();
+        assert_ne!(first, second);
+        assert!(!textures.contains(first) && !textures.contains(second));
+        // Unregistering a stale id again leaves the registry as is
+        let generation = textures.with_textures(|textures| textures.generation);
+        assert!(textures.unregister(first).is_none());
+        assert_eq!(textures.with_textures(|textures| textures.generation), generation);
+        let ids: Vec<_> = (0..300).map(|_| textures.allocate_user_id()).collect();
+        assert!(!ids.contains(&first) && !ids.contains(&second));
+        assert_eq!(ids.iter().collect::<ahash::AHashSet<_>>().len(), ids.len());
+    }
+}
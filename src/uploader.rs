@@ -0,0 +1,145 @@
+// Copyright (c) 2021 Okko Hakola
+// Licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT
+// license <LICENSE-MIT or https://opensource.org/licenses/MIT>,
+// at your option. All files in the project carrying such
+// notice may not be copied, modified, or distributed except
+// according to those terms.
+
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc, Mutex,
+};
+
+use vulkano::{
+    device::Queue,
+    format::Format,
+    image::ImageViewAbstract,
+    sampler::SamplerCreateInfo,
+    sync::{future::FenceSignalFuture, GpuFuture},
+};
+
+use crate::{
+    integration::mipmap_sampler,
+    utils::{
+        immutable_texture_from_bytes_deferred, immutable_texture_from_file_with_options_deferred,
+        Allocators, DeferredTexture, ImageRegistrationError, TextureLoadOptions,
+    },
+};
+
+/// Identifies an upload started with a `TextureUploader`, until `Gui::finalize_uploads` returns
+/// its `TextureId`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct UploadToken(u64);
+
+/// Creates user images on any thread, e.g. loader threads decoding images, see
+/// `Gui::texture_uploader`. Cloned handles share their uploads.
+///
+/// Each upload is recorded into its own command buffer and submitted right away on the calling
+/// thread. The image only gets a `TextureId` in `Gui::finalize_uploads` once the GPU has
+/// finished the upload, so egui can't sample it before.
+#[derive(Clone)]
+pub struct TextureUploader {
+    allocators: Arc<Allocators>,
+    /// Queue for uploads without mipmaps, the transfer queue if there is one
+    upload_queue: Arc<Queue>,
+    /// Queue for uploads generating mipmaps, which need blits
+    gfx_queue: Arc<Queue>,
+    next_token: Arc<AtomicU64>,
+    pending: Arc<Mutex<Vec<PendingUpload>>>,
+}
+
+/// A submitted upload and what's needed to register its image once finished
+pub(crate) struct PendingUpload {
+    pub(crate) token: UploadToken,
+    pub(crate) image: Arc<dyn ImageViewAbstract + Send + Sync + 'static>,
+    pub(crate) sampler_create_info: SamplerCreateInfo,
+    fence: FenceSignalFuture<Box<dyn GpuFuture + Send + Sync>>,
+}
+
+impl TextureUploader {
+    pub(crate) fn new(
+        allocators: Arc<Allocators>,
+        upload_queue: Arc<Queue>,
+        gfx_queue: Arc<Queue>,
+    ) -> TextureUploader {
+        TextureUploader {
+            allocators,
+            upload_queue,
+            gfx_queue,
+            next_token: Arc::new(AtomicU64::new(0)),
+            pending: Arc::new(Mutex::new(vec![])),
+        }
+    }
+
+    /// Decodes image file bytes and uploads them, see `Gui::register_user_image_with_options`
+    pub fn upload_file(
+        &self,
+        image_file_bytes: &[u8],
+        sampler_create_info: SamplerCreateInfo,
+        options: TextureLoadOptions,
+    ) -> Result<UploadToken, ImageRegistrationError> {
+        let queue = if options.mipmaps { &self.gfx_queue } else { &self.upload_queue };
+        let texture = immutable_texture_from_file_with_options_deferred(
+            &self.allocators,
+            queue.clone(),
+            image_file_bytes,
+            options,
+        )?;
+        self.submit(texture, mipmap_sampler(sampler_create_info, options.mipmaps))
+    }
+
+    /// Uploads raw pixel data, see `Gui::try_register_user_image_from_bytes` for the supported
+    /// formats
+    pub fn upload_raw(
+        &self,
+        image_byte_data: &[u8],
+        dimensions: [u32; 2],
+        format: Format,
+        sampler_create_info: SamplerCreateInfo,
+    ) -> Result<UploadToken, ImageRegistrationError> {
+        let texture = immutable_texture_from_bytes_deferred(
+            &self.allocators,
+            self.upload_queue.clone(),
+            image_byte_data,
+            dimensions,
+            format,
+            false,
+        )?;
+        self.submit(texture, sampler_create_info)
+    }
+
+    fn submit(
+        &self,
+        (image, upload): DeferredTexture,
+        sampler_create_info: SamplerCreateInfo,
+    ) -> Result<UploadToken, ImageRegistrationError> {
+        let fence = upload.then_signal_fence_and_flush()?;
+        let token = UploadToken(self.next_token.fetch_add(1, Ordering::Relaxed));
+        self.pending.lock().unwrap().push(PendingUpload {
+            token,
+            image,
+            sampler_create_info,
+            fence,
+        });
+        Ok(token)
+    }
+
+    /// Drops the submitted uploads without waiting for them, as waiting on a lost device panics
+    pub(crate) fn forget_pending(&self) {
+        for upload in self.pending.lock().unwrap().drain(..) {
+            std::mem::forget(upload.fence);
+        }
+    }
+
+    /// Removes the uploads the GPU has finished, in submission order
+    pub(crate) fn take_finished(&self) -> Vec<PendingUpload> {
+        let mut pending = self.pending.lock().unwrap();
+        // Dropping an upload whose fence can't be queried waits for it
+        let (finished, unfinished) =
+            pending.drain(..).partition(|upload| upload.fence.is_signaled().unwrap_or(true));
+        *pending = unfinished;
+        finished
+    }
+}
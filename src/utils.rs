@@ -7,77 +7,1425 @@
 // notice may not be copied, modified, or distributed except
 // according to those terms.
 
-use std::sync::Arc;
+use std::{
+    fmt,
+    panic::AssertUnwindSafe,
+    sync::{mpsc, Arc, Mutex},
+    thread::JoinHandle,
+};
 
 use image::RgbaImage;
 use vulkano::{
+    buffer::{Buffer, BufferCreateInfo, BufferError, BufferUsage},
     command_buffer::{
-        allocator::StandardCommandBufferAllocator, AutoCommandBufferBuilder, CommandBufferUsage,
-        PrimaryCommandBufferAbstract,
+        allocator::StandardCommandBufferAllocator, AutoCommandBufferBuilder, BufferImageCopy,
+        BuildError, ClearError, CommandBufferBeginError, CommandBufferExecError,
+        CommandBufferUsage, CopyError, CopyImageToBufferInfo, ExecuteCommandsError,
+        PipelineExecutionError, PrimaryAutoCommandBuffer, PrimaryCommandBufferAbstract,
+        RenderPassError,
     },
-    descriptor_set::allocator::StandardDescriptorSetAllocator,
-    device::{Device, Queue},
+    descriptor_set::{allocator::StandardDescriptorSetAllocator, DescriptorSetCreationError},
+    device::{Device, Queue, QueueFlags},
+    format::{ClearValue, Format, FormatFeatures, NumericType},
     image::{
-        immutable::ImmutableImageCreationError, view::ImageView, ImageDimensions,
-        ImageViewAbstract, ImmutableImage, MipmapsCount,
+        immutable::ImmutableImageCreationError,
+        view::{ImageView, ImageViewCreateInfo, ImageViewCreationError},
+        ImageAspects, ImageDimensions, ImageError, ImageLayout, ImageSubresourceLayers, ImageUsage,
+        ImageViewAbstract, ImmutableImage, MipmapsCount, SampleCount,
+    },
+    memory::allocator::{
+        AllocationCreateInfo, AllocationCreationError, MemoryUsage, StandardMemoryAllocator,
+    },
+    pipeline::graphics::{
+        color_blend::{AttachmentBlend, BlendFactor, BlendOp},
+        GraphicsPipelineCreationError,
     },
-    memory::allocator::StandardMemoryAllocator,
+    render_pass::{
+        AttachmentDescription, AttachmentReference, FramebufferCreationError, LoadOp, RenderPass,
+        RenderPassCreateInfo, RenderPassCreationError, StoreOp, SubpassDescription,
+    },
+    sampler::{ComponentMapping, ComponentSwizzle, SamplerCreationError},
+    sync::{FlushError, GpuFuture},
+    OomError, VulkanError,
 };
 
+/// Error that can happen when creating or registering a user image
+#[derive(Debug)]
+pub enum ImageRegistrationError {
+    /// Image file bytes could not be decoded
+    Decode(image::ImageError),
+    /// Image file bytes are not in a file format the image crate can decode
+    UnrecognizedFileFormat,
+    /// Decoded image has a color type that can't be converted to rgba
+    UnsupportedColorType(image::ColorType),
+    /// Image could not be created, e.g. allocation failed or format is unsupported
+    Creation(ImmutableImageCreationError),
+    /// Format can't be uploaded from pixel data (e.g. compressed or depth formats), or can't be
+    /// drawn by egui's shader (e.g. integer or stencil formats)
+    UnsupportedFormat(Format),
+    /// Image view can't be sampled, because its image lacks sampled usage or its format doesn't
+    /// support sampling
+    NotSampleable(Format),
+    /// Mipmaps were requested, but the format doesn't support generating them with linear blits
+    MipmapsUnsupported(Format),
+    /// Staging buffer for image data could not be created
+    Buffer(BufferError),
+    /// Upload command buffer could not be started
+    Begin(CommandBufferBeginError),
+    /// Image data could not be copied to the image
+    Copy(Box<CopyError>),
+    /// Upload command buffer could not be built
+    Build(BuildError),
+    /// Upload command buffer could not be executed
+    Execution(CommandBufferExecError),
+    /// Upload could not be submitted
+    Flush(FlushError),
+    /// Image view could not be created
+    View(ImageViewCreationError),
+    /// Sampler could not be created
+    Sampler(SamplerCreationError),
+    /// Descriptor set for the image could not be created
+    DescriptorSet(DescriptorSetCreationError),
+    /// No user image is registered with the texture id
+    UnknownTexture(egui::TextureId),
+    /// Size of the image data doesn't match the size of the existing image
+    SizeMismatch { expected: u64, actual: u64 },
+    /// Image has a width or height of zero
+    ZeroSize([u32; 2]),
+    /// Image is wider or higher than the device's maximum 2D image size
+    ImageTooLarge { max: u32, actual: [u32; 2] },
+    /// Image of the texture lacks transfer dst usage, so its pixels can't be updated
+    NotUpdatable(egui::TextureId),
+    /// Updated region doesn't fit within the image
+    RegionOutOfBounds { offset: [u32; 2], extent: [u32; 2], dimensions: [u32; 2] },
+    /// Image at `index` of a batch failed, see `Gui::register_user_images_from_rgba`
+    InBatch { index: usize, error: Box<ImageRegistrationError> },
+}
+
+impl std::error::Error for ImageRegistrationError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Decode(err) => Some(err),
+            Self::UnsupportedColorType(_)
+            | Self::UnrecognizedFileFormat
+            | Self::UnsupportedFormat(_)
+            | Self::NotSampleable(_)
+            | Self::MipmapsUnsupported(_) => None,
+            Self::Creation(err) => Some(err),
+            Self::Buffer(err) => Some(err),
+            Self::Begin(err) => Some(err),
+            Self::Copy(err) => Some(err.as_ref()),
+            Self::Build(err) => Some(err),
+            Self::Execution(err) => Some(err),
+            Self::Flush(err) => Some(err),
+            Self::View(err) => Some(err),
+            Self::Sampler(err) => Some(err),
+            Self::DescriptorSet(err) => Some(err),
+            Self::InBatch { error, .. } => Some(error.as_ref()),
+            Self::UnknownTexture(_)
+            | Self::SizeMismatch { .. }
+            | Self::ZeroSize(_)
+            | Self::ImageTooLarge { .. }
+            | Self::NotUpdatable(_)
+            | Self::RegionOutOfBounds { .. } => None,
+        }
+    }
+}
+
+impl fmt::Display for ImageRegistrationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Decode(_) => write!(f, "failed to decode image"),
+            Self::UnrecognizedFileFormat => write!(f, "unrecognized image file format"),
+            Self::UnsupportedColorType(color_type) => {
+                write!(f, "unsupported image color type {:?}", color_type)
+            }
+            Self::Creation(_) => write!(f, "failed to create image"),
+            Self::UnsupportedFormat(format) => write!(f, "unsupported image format {:?}", format),
+            Self::NotSampleable(format) => {
+                write!(f, "image view with format {:?} can't be sampled", format)
+            }
+            Self::MipmapsUnsupported(format) => {
+                write!(f, "format {:?} doesn't support mipmap generation", format)
+            }
+            Self::Buffer(_) => write!(f, "failed to create image data buffer"),
+            Self::Begin(_) => write!(f, "failed to begin image upload command buffer"),
+            Self::Copy(_) => write!(f, "failed to copy image data to image"),
+            Self::Build(_) => write!(f, "failed to build image upload command buffer"),
+            Self::Execution(_) => write!(f, "failed to execute image upload command buffer"),
+            Self::Flush(_) => write!(f, "failed to submit image upload"),
+            Self::View(_) => write!(f, "failed to create image view"),
+            Self::Sampler(_) => write!(f, "failed to create sampler"),
+            Self::DescriptorSet(_) => write!(f, "failed to create descriptor set"),
+            Self::UnknownTexture(texture_id) => {
+                write!(f, "no user image registered with id {:?}", texture_id)
+            }
+            Self::SizeMismatch { expected, actual } => write!(
+                f,
+                "image data size ({} bytes) doesn't match image size ({} bytes)",
+                actual, expected
+            ),
+            Self::ZeroSize(dimensions) => {
+                write!(f, "image dimensions {:?} can't be zero", dimensions)
+            }
+            Self::ImageTooLarge { max, actual } => write!(
+                f,
+                "image dimensions {:?} exceed the device's maximum image size of {}",
+                actual, max
+            ),
+            Self::NotUpdatable(texture_id) => write!(
+                f,
+                "image of texture {:?} can't be updated without transfer dst usage",
+                texture_id
+            ),
+            Self::RegionOutOfBounds { offset, extent, dimensions } => write!(
+                f,
+                "region at {:?} of size {:?} doesn't fit in image of size {:?}",
+                offset, extent, dimensions
+            ),
+            Self::InBatch { index, error } => write!(f, "image {} of batch: {}", index, error),
+        }
+    }
+}
+
+impl From<image::ImageError> for ImageRegistrationError {
+    fn from(err: image::ImageError) -> Self {
+        Self::Decode(err)
+    }
+}
+
+impl From<ImmutableImageCreationError> for ImageRegistrationError {
+    fn from(err: ImmutableImageCreationError) -> Self {
+        Self::Creation(err)
+    }
+}
+
+impl From<BufferError> for ImageRegistrationError {
+    fn from(err: BufferError) -> Self {
+        Self::Buffer(err)
+    }
+}
+
+impl From<CommandBufferBeginError> for ImageRegistrationError {
+    fn from(err: CommandBufferBeginError) -> Self {
+        Self::Begin(err)
+    }
+}
+
+impl From<CopyError> for ImageRegistrationError {
+    fn from(err: CopyError) -> Self {
+        Self::Copy(Box::new(err))
+    }
+}
+
+impl From<BuildError> for ImageRegistrationError {
+    fn from(err: BuildError) -> Self {
+        Self::Build(err)
+    }
+}
+
+impl From<CommandBufferExecError> for ImageRegistrationError {
+    fn from(err: CommandBufferExecError) -> Self {
+        Self::Execution(err)
+    }
+}
+
+impl From<ImageViewCreationError> for ImageRegistrationError {
+    fn from(err: ImageViewCreationError) -> Self {
+        Self::View(err)
+    }
+}
+
+impl From<SamplerCreationError> for ImageRegistrationError {
+    fn from(err: SamplerCreationError) -> Self {
+        Self::Sampler(err)
+    }
+}
+
+impl From<FlushError> for ImageRegistrationError {
+    fn from(err: FlushError) -> Self {
+        Self::Flush(err)
+    }
+}
+
+impl From<DescriptorSetCreationError> for ImageRegistrationError {
+    fn from(err: DescriptorSetCreationError) -> Self {
+        Self::DescriptorSet(err)
+    }
+}
+
+/// An image view and the unflushed future of its upload, which can be flushed on any thread
+pub(crate) type DeferredTexture =
+    (Arc<dyn ImageViewAbstract + Send + Sync + 'static>, Box<dyn GpuFuture + Send + Sync>);
+
+/// Image views and the unflushed future of their shared upload
+pub(crate) type DeferredTextures =
+    (Vec<Arc<dyn ImageViewAbstract + Send + Sync + 'static>>, Box<dyn GpuFuture + Send + Sync>);
+
+/// Creates an immutable image from raw pixel data, e.g. procedurally generated textures.
+/// `byte_data` must be tightly packed rows of `dimensions` pixels in `format`, an uncompressed
+/// color format.
+///
+/// Returns an error if the format is unsupported, `byte_data` doesn't match `dimensions` or a
+/// dimension is zero.
 pub fn immutable_texture_from_bytes(
     allocators: &Allocators,
     queue: Arc<Queue>,
     byte_data: &[u8],
     dimensions: [u32; 2],
-    format: vulkano::format::Format,
-) -> Result<Arc<dyn ImageViewAbstract + Send + Sync + 'static>, ImmutableImageCreationError> {
-    let vko_dims =
-        ImageDimensions::Dim2d { width: dimensions[0], height: dimensions[1], array_layers: 1 };
+    format: Format,
+) -> Result<Arc<dyn ImageViewAbstract + Send + Sync + 'static>, ImageRegistrationError> {
+    // Dropping the upload future waits for the upload to finish
+    let (texture, _upload) = immutable_texture_from_bytes_deferred(
+        allocators, queue, byte_data, dimensions, format, false,
+    )?;
+    Ok(texture)
+}
+
+/// Like `immutable_texture_from_bytes`, but returns the upload as an unflushed future instead of
+/// waiting for it. The image must not be sampled before the future has been submitted ahead of
+/// the sampling commands.
+///
+/// With `mipmaps`, the image gets a full mip chain which is generated on the GPU by blitting each
+/// level from the previous one (halving and rounding down the size per level). The format must
+/// support linear blits, and the image should be sampled with a sampler that has
+/// `mipmap_mode: SamplerMipmapMode::Linear` and a lod range covering the mip levels.
+pub(crate) fn immutable_texture_from_bytes_deferred(
+    allocators: &Allocators,
+    queue: Arc<Queue>,
+    byte_data: &[u8],
+    dimensions: [u32; 2],
+    format: Format,
+    mipmaps: bool,
+) -> Result<DeferredTexture, ImageRegistrationError> {
+    let (texture, upload) =
+        immutable_image_from_bytes(allocators, queue, byte_data, dimensions, format, mipmaps)?;
+    Ok((ImageView::new_default(texture)?, upload))
+}
 
+fn immutable_image_from_bytes(
+    allocators: &Allocators,
+    queue: Arc<Queue>,
+    byte_data: &[u8],
+    dimensions: [u32; 2],
+    format: Format,
+    mipmaps: bool,
+) -> Result<(Arc<ImmutableImage>, Box<dyn GpuFuture + Send + Sync>), ImageRegistrationError> {
     let mut cbb = AutoCommandBufferBuilder::primary(
         &allocators.command_buffer,
         queue.queue_family_index(),
         CommandBufferUsage::OneTimeSubmit,
     )?;
-    let texture = ImmutableImage::from_iter(
+    let texture = record_immutable_image(
+        allocators, &queue, byte_data, dimensions, format, mipmaps, &mut cbb,
+    )?;
+    let upload = cbb.build()?.execute(queue)?;
+
+    Ok((texture, Box::new(upload)))
+}
+
+// Creates an immutable image, recording its upload into `cbb`
+fn record_immutable_image(
+    allocators: &Allocators,
+    queue: &Queue,
+    byte_data: &[u8],
+    dimensions: [u32; 2],
+    format: Format,
+    mipmaps: bool,
+    cbb: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+) -> Result<Arc<ImmutableImage>, ImageRegistrationError> {
+    check_image_bytes(byte_data, dimensions, format)?;
+    let max = max_image_dimension(queue);
+    if dimensions.iter().any(|&dimension| dimension > max) {
+        return Err(ImageRegistrationError::ImageTooLarge { max, actual: dimensions });
+    }
+    let vko_dims =
+        ImageDimensions::Dim2d { width: dimensions[0], height: dimensions[1], array_layers: 1 };
+    let mip_levels = if mipmaps {
+        let blit_features = FormatFeatures::BLIT_SRC
+            | FormatFeatures::BLIT_DST
+            | FormatFeatures::SAMPLED_IMAGE_FILTER_LINEAR;
+        let supported = queue
+            .device()
+            .physical_device()
+            .format_properties(format)
+            .map(|properties| properties.optimal_tiling_features.contains(blit_features))
+            .unwrap_or(false);
+        if !supported {
+            return Err(ImageRegistrationError::MipmapsUnsupported(format));
+        }
+        MipmapsCount::Log2
+    } else {
+        MipmapsCount::One
+    };
+
+    Ok(ImmutableImage::from_iter(
         &allocators.memory,
         byte_data.iter().cloned(),
         vko_dims,
-        MipmapsCount::One,
+        mip_levels,
         format,
-        &mut cbb,
-    )?;
-    let _fut = cbb.build().unwrap().execute(queue).unwrap();
-
-    Ok(ImageView::new_default(texture).unwrap())
+        cbb,
+    )?)
 }
 
+/// Decodes image file bytes and creates an immutable rgba image from them. Use
+/// `immutable_texture_from_file_with_options` for mipmaps.
 pub fn immutable_texture_from_file(
     allocators: &Allocators,
     queue: Arc<Queue>,
     file_bytes: &[u8],
-    format: vulkano::format::Format,
-) -> Result<Arc<dyn ImageViewAbstract + Send + Sync + 'static>, ImmutableImageCreationError> {
-    use image::GenericImageView;
+    format: Format,
+) -> Result<Arc<dyn ImageViewAbstract + Send + Sync + 'static>, ImageRegistrationError> {
+    // Dropping the upload future waits for the upload to finish
+    let (texture, _upload) =
+        immutable_texture_from_file_deferred(allocators, queue, file_bytes, format)?;
+    Ok(texture)
+}
 
-    let img = image::load_from_memory(file_bytes).expect("Failed to load image from bytes");
-    let rgba = if let Some(rgba) = img.as_rgba8() {
-        rgba.to_owned().to_vec()
+/// Like `immutable_texture_from_file`, but returns the upload future without waiting, see
+/// `immutable_texture_from_bytes_deferred`
+pub(crate) fn immutable_texture_from_file_deferred(
+    allocators: &Allocators,
+    queue: Arc<Queue>,
+    file_bytes: &[u8],
+    format: Format,
+) -> Result<DeferredTexture, ImageRegistrationError> {
+    let rgba = decode_rgba(file_bytes, false, false, false)?;
+    immutable_texture_from_bytes_deferred(
+        allocators,
+        queue,
+        rgba.as_raw(),
+        [rgba.width(), rgba.height()],
+        format,
+        false,
+    )
+}
+
+/// Decodes image file bytes like `immutable_texture_from_file`, with `options` controlling the
+/// encoding of the created image and how the pixels are prepared on load
+pub fn immutable_texture_from_file_with_options(
+    allocators: &Allocators,
+    queue: Arc<Queue>,
+    file_bytes: &[u8],
+    options: TextureLoadOptions,
+) -> Result<Arc<dyn ImageViewAbstract + Send + Sync + 'static>, ImageRegistrationError> {
+    // Dropping the upload future waits for the upload to finish
+    let (texture, _upload) =
+        immutable_texture_from_file_with_options_deferred(allocators, queue, file_bytes, options)?;
+    Ok(texture)
+}
+
+/// Like `immutable_texture_from_file_with_options`, but returns the upload future without
+/// waiting, see `immutable_texture_from_bytes_deferred`
+pub(crate) fn immutable_texture_from_file_with_options_deferred(
+    allocators: &Allocators,
+    queue: Arc<Queue>,
+    file_bytes: &[u8],
+    options: TextureLoadOptions,
+) -> Result<DeferredTexture, ImageRegistrationError> {
+    let mut rgba =
+        decode_rgba(file_bytes, options.flip_vertically, options.premultiply_alpha, options.srgb)?;
+    if options.allow_downscale {
+        rgba = downscale_to_fit(rgba, max_image_dimension(&queue));
+    }
+    let format = if options.srgb { Format::R8G8B8A8_SRGB } else { Format::R8G8B8A8_UNORM };
+    immutable_texture_from_bytes_deferred(
+        allocators,
+        queue,
+        rgba.as_raw(),
+        [rgba.width(), rgba.height()],
+        format,
+        options.mipmaps,
+    )
+}
+
+/// Checks that the device of `queue` supports what egui's pipeline & textures need: a graphics
+/// queue, blending into `target_format`, rendering with `samples`, a linearly sampled & blitted
+/// font atlas and, with several color attachments, independent blending. The error lists
+/// everything that's missing.
+pub(crate) fn check_device_support(
+    queue: &Queue,
+    target_format: Format,
+    samples: SampleCount,
+    color_attachments: usize,
+) -> Result<(), GuiCreationError> {
+    let physical_device = queue.device().physical_device();
+    let features = |format| {
+        physical_device
+            .format_properties(format)
+            .map_or(FormatFeatures::empty(), |properties| properties.optimal_tiling_features)
+    };
+    let mut missing = vec![];
+    let queue_flags =
+        physical_device.queue_family_properties()[queue.queue_family_index() as usize].queue_flags;
+    if !queue_flags.intersects(QueueFlags::GRAPHICS) {
+        missing.push("the queue doesn't support graphics".to_string());
+    }
+    if !features(target_format)
+        .contains(FormatFeatures::COLOR_ATTACHMENT | FormatFeatures::COLOR_ATTACHMENT_BLEND)
+    {
+        missing.push(format!("{:?} can't be blended into as a color attachment", target_format));
+    }
+    if !physical_device.properties().framebuffer_color_sample_counts.contains_enum(samples) {
+        missing.push(format!("sample count {:?} isn't supported for color attachments", samples));
+    }
+    let font_features = FormatFeatures::SAMPLED_IMAGE
+        | FormatFeatures::SAMPLED_IMAGE_FILTER_LINEAR
+        | FormatFeatures::BLIT_SRC
+        | FormatFeatures::BLIT_DST;
+    if !features(Format::R8G8B8A8_SRGB).contains(font_features) {
+        missing.push(format!(
+            "the font atlas format {:?} can't be sampled linearly and blitted",
+            Format::R8G8B8A8_SRGB
+        ));
+    }
+    if color_attachments > 1 && !queue.device().enabled_features().independent_blend {
+        missing.push(
+            "the independent_blend feature isn't enabled, which subpasses with several color \
+             attachments need"
+                .to_string(),
+        );
+    }
+    if missing.is_empty() {
+        Ok(())
+    } else {
+        Err(GuiCreationError::UnsupportedDevice { reason: missing.join("; ") })
+    }
+}
+
+/// Largest width or height of 2D images the device of `queue` supports
+pub(crate) fn max_image_dimension(queue: &Queue) -> u32 {
+    queue.device().physical_device().properties().max_image_dimension2_d
+}
+
+// Scales `rgba` down to fit `max` pixels per side, keeping its aspect ratio
+fn downscale_to_fit(rgba: RgbaImage, max: u32) -> RgbaImage {
+    let (width, height) = rgba.dimensions();
+    if width <= max && height <= max {
+        return rgba;
+    }
+    let scale = max as f64 / width.max(height) as f64;
+    let fit = |dimension: u32| ((dimension as f64 * scale).round() as u32).clamp(1, max);
+    image::imageops::resize(&rgba, fit(width), fit(height), image::imageops::FilterType::Lanczos3)
+}
+
+// Decodes any file format & color type the image crate supports to rgba8. Gray images are
+// expanded, missing alpha is opaque and 16 bit & float channels are converted to 8 bit. Gifs
+// decode to their first frame.
+fn decode_rgba(
+    file_bytes: &[u8],
+    flip_vertically: bool,
+    premultiply_alpha: bool,
+    srgb: bool,
+) -> Result<RgbaImage, ImageRegistrationError> {
+    let file_format = image::guess_format(file_bytes)
+        .map_err(|_| ImageRegistrationError::UnrecognizedFileFormat)?;
+    let mut rgba = image::load_from_memory_with_format(file_bytes, file_format)?.into_rgba8();
+    if flip_vertically {
+        image::imageops::flip_vertical_in_place(&mut rgba);
+    }
+    if premultiply_alpha {
+        for pixel in rgba.pixels_mut() {
+            let [r, g, b, a] = pixel.0;
+            pixel.0 = if srgb {
+                // Premultiplied in linear space like egui's own colors
+                egui::Color32::from_rgba_unmultiplied(r, g, b, a).to_array()
+            } else {
+                let premultiply = |c: u8| ((c as u32 * a as u32 + 127) / 255) as u8;
+                [premultiply(r), premultiply(g), premultiply(b), a]
+            };
+        }
+    }
+    Ok(rgba)
+}
+
+/// Like `immutable_texture_from_bytes_deferred` for many images of `format`, whose uploads are
+/// recorded into one command buffer. Errors are tagged with the index of the failing image.
+pub(crate) fn immutable_textures_from_bytes_deferred<'a>(
+    allocators: &Allocators,
+    queue: Arc<Queue>,
+    images: impl IntoIterator<Item = (&'a [u8], [u32; 2])>,
+    format: Format,
+) -> Result<DeferredTextures, ImageRegistrationError> {
+    let mut cbb = AutoCommandBufferBuilder::primary(
+        &allocators.command_buffer,
+        queue.queue_family_index(),
+        CommandBufferUsage::OneTimeSubmit,
+    )?;
+    let mut views: Vec<Arc<dyn ImageViewAbstract + Send + Sync + 'static>> = vec![];
+    for (index, (byte_data, dimensions)) in images.into_iter().enumerate() {
+        let view = record_immutable_image(
+            allocators, &queue, byte_data, dimensions, format, false, &mut cbb,
+        )
+        .and_then(|texture| Ok(ImageView::new_default(texture)?))
+        .map_err(|error| ImageRegistrationError::InBatch { index, error: Box::new(error) })?;
+        views.push(view);
+    }
+    let upload = cbb.build()?.execute(queue)?;
+    Ok((views, Box::new(upload)))
+}
+
+// Checks that pixel data of `format` fills an image of `dimensions`. Only uncompressed color
+// formats of a single plane can be uploaded as tightly packed pixels.
+fn check_image_bytes(
+    byte_data: &[u8],
+    dimensions: [u32; 2],
+    format: Format,
+) -> Result<(), ImageRegistrationError> {
+    let block_size = match format.block_size() {
+        Some(block_size)
+            if format.compression().is_none()
+                && format.planes().is_empty()
+                && format.aspects().intersects(ImageAspects::COLOR) =>
+        {
+            block_size
+        }
+        _ => return Err(ImageRegistrationError::UnsupportedFormat(format)),
+    };
+    if dimensions.contains(&0) {
+        return Err(ImageRegistrationError::ZeroSize(dimensions));
+    }
+    let expected = dimensions[0] as u64 * dimensions[1] as u64 * block_size;
+    if byte_data.len() as u64 != expected {
+        return Err(ImageRegistrationError::SizeMismatch {
+            expected,
+            actual: byte_data.len() as u64,
+        });
+    }
+    Ok(())
+}
+
+/// How user images with a single channel (e.g. `R8_UNORM` or depth) are drawn
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SingleChannelMode {
+    /// Gray with full alpha, e.g. to inspect a depth or occlusion buffer
+    Grayscale,
+    /// White with the channel as alpha (like egui's font texture), so it can be tinted in egui
+    Alpha,
+}
+
+/// Options for how a registered user image view is drawn, see
+/// `Gui::register_user_image_view_with_options`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UserImageOptions {
+    /// Swizzle applied to single channel images whose view has the identity component mapping
+    pub single_channel: SingleChannelMode,
+    /// Whether the texels are sRGB encoded (`Some(true)`) or linear (`Some(false)`), e.g. for a
+    /// linear render target viewed with an `_SRGB` format or sRGB data in an `_UNORM` image.
+    /// The shader converts texels whose encoding doesn't match the view's format. Defaults to
+    /// `None`, which trusts the format.
+    pub srgb: Option<bool>,
+}
+
+impl Default for UserImageOptions {
+    fn default() -> Self {
+        UserImageOptions { single_channel: SingleChannelMode::Grayscale, srgb: None }
+    }
+}
+
+/// Options for decoding image files, see `immutable_texture_from_file_with_options`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TextureLoadOptions {
+    /// Whether the image is created as `R8G8B8A8_SRGB` (e.g. for color maps & ui images) or
+    /// `R8G8B8A8_UNORM` (e.g. for data textures). Defaults to true.
+    pub srgb: bool,
+    /// Flips the rows, e.g. for images authored with the origin at the bottom left
+    pub flip_vertically: bool,
+    /// Multiplies the colors by alpha on load. Egui blends premultiplied colors, so this avoids
+    /// dark or bright fringes at the edges of transparent regions.
+    pub premultiply_alpha: bool,
+    /// Generates a full mip chain on upload, so large images shown small don't shimmer. Costs
+    /// upload time and a third more memory. Each mip level is blitted from the previous one on
+    /// the GPU, halving and rounding down the size. Images registered with
+    /// `Gui::register_user_image_with_options` are then sampled with linear mipmap filtering
+    /// over all mip levels; sample images of `immutable_texture_from_file_with_options` with
+    /// `mipmap_mode: SamplerMipmapMode::Linear` and a lod range covering the levels yourself.
+    /// Defaults to false.
+    pub mipmaps: bool,
+    /// Scales images larger than the device's maximum image size down to fit (keeping the
+    /// aspect ratio), instead of returning `ImageRegistrationError::ImageTooLarge`. Defaults to
+    /// true.
+    pub allow_downscale: bool,
+}
+
+impl Default for TextureLoadOptions {
+    fn default() -> Self {
+        TextureLoadOptions {
+            srgb: true,
+            flip_vertically: false,
+            premultiply_alpha: false,
+            mipmaps: false,
+            allow_downscale: true,
+        }
+    }
+}
+
+/// Checks that egui can sample `view` and swizzles single channel views according to `options`.
+/// Other color formats, e.g. `B8G8R8A8_UNORM`, are already sampled in rgba order.
+pub(crate) fn user_image_view(
+    view: Arc<dyn ImageViewAbstract + Send + Sync + 'static>,
+    options: UserImageOptions,
+) -> Result<Arc<dyn ImageViewAbstract + Send + Sync + 'static>, ImageRegistrationError> {
+    let format = match view.format() {
+        Some(format) => format,
+        None => return Ok(view),
+    };
+    if !view.usage().intersects(ImageUsage::SAMPLED)
+        || !view.format_features().intersects(FormatFeatures::SAMPLED_IMAGE)
+    {
+        return Err(ImageRegistrationError::NotSampleable(format));
+    }
+    let aspects = view.subresource_range().aspects;
+    let is_color = aspects.intersects(ImageAspects::COLOR);
+    let numeric_type = if is_color {
+        format.type_color()
+    } else if aspects.intersects(ImageAspects::DEPTH) {
+        format.type_depth()
     } else {
-        // Convert rgb to rgba
-        let rgb = img.as_rgb8().unwrap().to_owned();
-        let mut raw_data = vec![];
-        for val in rgb.chunks(3) {
-            raw_data.push(val[0]);
-            raw_data.push(val[1]);
-            raw_data.push(val[2]);
-            raw_data.push(255);
-        }
-        let new_rgba = RgbaImage::from_raw(rgb.width(), rgb.height(), raw_data).unwrap();
-        new_rgba.to_vec()
+        None
+    };
+    // The shader samples floats
+    if matches!(numeric_type, None | Some(NumericType::UINT) | Some(NumericType::SINT)) {
+        return Err(ImageRegistrationError::UnsupportedFormat(format));
+    }
+    let single_channel = !is_color || format.components()[1..].iter().all(|&bits| bits == 0);
+    if !single_channel || !view.component_mapping().is_identity() {
+        return Ok(view);
+    }
+    let alpha = match options.single_channel {
+        SingleChannelMode::Grayscale => ComponentSwizzle::One,
+        SingleChannelMode::Alpha => ComponentSwizzle::Red,
+    };
+    Ok(ImageView::new(view.image(), ImageViewCreateInfo {
+        view_type: view.view_type(),
+        format: Some(format),
+        component_mapping: ComponentMapping {
+            r: ComponentSwizzle::Red,
+            g: ComponentSwizzle::Red,
+            b: ComponentSwizzle::Red,
+            a: alpha,
+        },
+        subresource_range: view.subresource_range().clone(),
+        ..Default::default()
+    })?)
+}
+
+/// What happens to the existing contents of the target image when a render pass begins
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ClearMode {
+    /// Load the existing contents, e.g. to draw the ui over your scene
+    Preserve,
+    /// Clear to a color. Use an alpha of 0 for a transparent background, e.g. when rendering
+    /// the ui to a texture which is composited later.
+    Clear([f32; 4]),
+}
+
+impl ClearMode {
+    /// Load op of the color attachment
+    pub fn load_op(&self) -> LoadOp {
+        match self {
+            ClearMode::Preserve => LoadOp::Load,
+            ClearMode::Clear(_) => LoadOp::Clear,
+        }
+    }
+
+    /// Clear value of the color attachment to pass to `begin_render_pass`
+    pub fn clear_value(&self) -> Option<ClearValue> {
+        match self {
+            ClearMode::Preserve => None,
+            ClearMode::Clear(color) => Some(ClearValue::Float(*color)),
+        }
+    }
+}
+
+/// Options for `create_gui_render_pass`
+#[derive(Debug, Clone, Copy)]
+pub struct RenderPassOptions {
+    /// Whether the color attachment is loaded or cleared at the start of the render pass
+    pub clear_mode: ClearMode,
+    /// Format of an optional depth attachment, which is cleared and not stored. Egui doesn't use
+    /// depth, but you may want it for your own draws in the same render pass.
+    pub depth_format: Option<Format>,
+    /// Sample count of the attachments
+    pub samples: SampleCount,
+}
+
+impl Default for RenderPassOptions {
+    fn default() -> Self {
+        RenderPassOptions {
+            clear_mode: ClearMode::Clear([0.0; 4]),
+            depth_format: None,
+            samples: SampleCount::Sample1,
+        }
+    }
+}
+
+impl RenderPassOptions {
+    /// Clear values matching the attachments of a render pass created with these options. Pass
+    /// these to `RenderPassBeginInfo::clear_values`, mismatches fail validation on begin.
+    pub fn clear_values(&self) -> Vec<Option<ClearValue>> {
+        let mut clear_values = vec![self.clear_mode.clear_value()];
+        if let Some(depth_format) = self.depth_format {
+            let aspects = depth_format.aspects();
+            clear_values.push(Some(
+                match (
+                    aspects.intersects(ImageAspects::DEPTH),
+                    aspects.intersects(ImageAspects::STENCIL),
+                ) {
+                    (true, true) => ClearValue::DepthStencil((1.0, 0)),
+                    (false, true) => ClearValue::Stencil(0),
+                    _ => ClearValue::Depth(1.0),
+                },
+            ));
+        }
+        clear_values
+    }
+}
+
+/// Creates a single subpass render pass that egui can draw on, with a color attachment of
+/// `color_format` (e.g. your swapchain format) at attachment 0 and an optional depth attachment
+/// at attachment 1. Use it with `Gui::new_from_render_pass`.
+pub fn create_gui_render_pass(
+    device: Arc<Device>,
+    color_format: Format,
+    options: RenderPassOptions,
+) -> Result<Arc<RenderPass>, RenderPassCreationError> {
+    let mut attachments = vec![AttachmentDescription {
+        format: Some(color_format),
+        samples: options.samples,
+        load_op: options.clear_mode.load_op(),
+        store_op: StoreOp::Store,
+        initial_layout: ImageLayout::ColorAttachmentOptimal,
+        final_layout: ImageLayout::ColorAttachmentOptimal,
+        ..Default::default()
+    }];
+    let depth_stencil_attachment = options.depth_format.map(|depth_format| {
+        attachments.push(AttachmentDescription {
+            format: Some(depth_format),
+            samples: options.samples,
+            load_op: LoadOp::Clear,
+            store_op: StoreOp::DontCare,
+            stencil_load_op: LoadOp::Clear,
+            stencil_store_op: StoreOp::DontCare,
+            initial_layout: ImageLayout::DepthStencilAttachmentOptimal,
+            final_layout: ImageLayout::DepthStencilAttachmentOptimal,
+            ..Default::default()
+        });
+        AttachmentReference {
+            attachment: 1,
+            layout: ImageLayout::DepthStencilAttachmentOptimal,
+            ..Default::default()
+        }
+    });
+    RenderPass::new(device, RenderPassCreateInfo {
+        attachments,
+        subpasses: vec![SubpassDescription {
+            color_attachments: vec![Some(AttachmentReference {
+                attachment: 0,
+                layout: ImageLayout::ColorAttachmentOptimal,
+                ..Default::default()
+            })],
+            depth_stencil_attachment,
+            ..Default::default()
+        }],
+        ..Default::default()
+    })
+}
+
+/// Blending of premultiplied alpha, which egui's colors use and the integration draws with in
+/// every mode. Use it in your own pipeline to composite the texture of `Gui::draw_on_texture`
+/// over your scene. Blending that texture as straight alpha (`AttachmentBlend::alpha`)
+/// multiplies its colors by alpha twice, which darkens the edges of text and shapes.
+pub fn premultiplied_alpha_blend() -> AttachmentBlend {
+    AttachmentBlend {
+        color_op: BlendOp::Add,
+        color_source: BlendFactor::One,
+        color_destination: BlendFactor::OneMinusSrcAlpha,
+        alpha_op: BlendOp::Add,
+        alpha_source: BlendFactor::One,
+        alpha_destination: BlendFactor::OneMinusSrcAlpha,
+    }
+}
+
+/// Error that can happen while the integration draws, e.g. when the device runs out of memory
+/// or is lost. The device is usually unusable afterwards, so recreate it and move the
+/// integration to it with `Gui::rebuild`.
+#[derive(Debug)]
+pub enum EguiVulkanoError {
+    /// An egui texture (e.g. the font atlas) could not be uploaded
+    Texture { texture_id: egui::TextureId, error: Box<EguiVulkanoError> },
+    /// An egui texture is wider or higher than the device's maximum 2D image size
+    TextureTooLarge { max: u32, actual: [u32; 2] },
+    /// Vertex or index buffer memory could not be allocated
+    Allocation(AllocationCreationError),
+    /// Buffer could not be created or written
+    Buffer(BufferError),
+    /// Texture image could not be created
+    ImageCreation(ImmutableImageCreationError),
+    /// Offscreen render target could not be created
+    Image(ImageError),
+    /// Image view could not be created
+    View(ImageViewCreationError),
+    /// Descriptor set of a texture could not be created
+    DescriptorSet(DescriptorSetCreationError),
+    /// Framebuffer of the target image could not be created
+    Framebuffer(FramebufferCreationError),
+    /// Command buffer could not be started
+    Begin(CommandBufferBeginError),
+    /// Copy or blit could not be recorded
+    Copy(Box<CopyError>),
+    /// Clearing the target could not be recorded
+    Clear(Box<ClearError>),
+    /// Render pass could not be begun or ended
+    RenderPass(Box<RenderPassError>),
+    /// Draw command buffer could not be executed in the render pass
+    ExecuteCommands(Box<ExecuteCommandsError>),
+    /// Draw could not be recorded
+    Draw(Box<PipelineExecutionError>),
+    /// Command buffer could not be built
+    Build(BuildError),
+    /// Command buffer could not be executed
+    Execution(CommandBufferExecError),
+    /// Submission could not be flushed or waited on
+    Flush(FlushError),
+    /// User image could not be registered
+    ImageRegistration(ImageRegistrationError),
+    /// No frame was drawn yet that could be redrawn
+    NoFrameToRedraw,
+    /// The last frame was drawn on an image of other dimensions than the one to redraw it on
+    RedrawSizeChanged { cached: [u32; 2], actual: [u32; 2] },
+    /// `Gui::render` was called without a frame prepared with `Gui::prepare`
+    FrameNotPrepared,
+    /// Uploads were submitted after `Gui::prepare`, which must be called again before rendering
+    UnpreparedUploads,
+    /// A mesh of the frame is too large for the largest vertex or index buffer the renderer
+    /// allocates, half of the device's maximum allocation size. Frames of many meshes are split
+    /// into several buffers instead.
+    FrameTooLarge { vertices: usize, indices: usize, max_buffer_size: u64 },
+}
+
+impl EguiVulkanoError {
+    /// Whether the error was caused by losing the device, e.g. a GPU switch or driver reset.
+    /// Recreate the device and move the integration to it with `Gui::rebuild`.
+    pub fn is_device_lost(&self) -> bool {
+        self.caused_by(|err| {
+            matches!(err.downcast_ref(), Some(FlushError::DeviceLost))
+                || matches!(err.downcast_ref(), Some(VulkanError::DeviceLost))
+        })
+    }
+
+    /// Whether the error was caused by the host or device running out of memory
+    pub fn is_out_of_memory(&self) -> bool {
+        self.caused_by(|err| {
+            err.is::<OomError>()
+                || matches!(
+                    err.downcast_ref(),
+                    Some(VulkanError::OutOfHostMemory | VulkanError::OutOfDeviceMemory)
+                )
+        })
+    }
+
+    // Whether `f` holds for the error or any error in its chain of sources
+    fn caused_by(&self, f: impl Fn(&(dyn std::error::Error + 'static)) -> bool) -> bool {
+        let mut error: Option<&(dyn std::error::Error + 'static)> = Some(self);
+        while let Some(err) = error {
+            if f(err) {
+                return true;
+            }
+            error = err.source();
+        }
+        false
+    }
+}
+
+impl std::error::Error for EguiVulkanoError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Texture { error, .. } => Some(error.as_ref()),
+            Self::TextureTooLarge { .. } => None,
+            Self::Allocation(err) => Some(err),
+            Self::Buffer(err) => Some(err),
+            Self::ImageCreation(err) => Some(err),
+            Self::Image(err) => Some(err),
+            Self::View(err) => Some(err),
+            Self::DescriptorSet(err) => Some(err),
+            Self::Framebuffer(err) => Some(err),
+            Self::Begin(err) => Some(err),
+            Self::Copy(err) => Some(err.as_ref()),
+            Self::Clear(err) => Some(err.as_ref()),
+            Self::RenderPass(err) => Some(err.as_ref()),
+            Self::ExecuteCommands(err) => Some(err.as_ref()),
+            Self::Draw(err) => Some(err.as_ref()),
+            Self::Build(err) => Some(err),
+            Self::Execution(err) => Some(err),
+            Self::Flush(err) => Some(err),
+            Self::ImageRegistration(err) => Some(err),
+            Self::NoFrameToRedraw
+            | Self::RedrawSizeChanged { .. }
+            | Self::FrameNotPrepared
+            | Self::UnpreparedUploads
+            | Self::FrameTooLarge { .. } => None,
+        }
+    }
+}
+
+impl fmt::Display for EguiVulkanoError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Texture { texture_id, error } => {
+                write!(f, "failed to upload texture {:?}: {}", texture_id, error)
+            }
+            Self::TextureTooLarge { max, actual } => write!(
+                f,
+                "texture dimensions {:?} exceed the device's maximum image size of {}",
+                actual, max
+            ),
+            Self::Allocation(_) => write!(f, "failed to allocate vertex or index buffer"),
+            Self::Buffer(_) => write!(f, "failed to create or write buffer"),
+            Self::ImageCreation(_) => write!(f, "failed to create texture image"),
+            Self::Image(_) => write!(f, "failed to create offscreen render target"),
+            Self::View(_) => write!(f, "failed to create image view"),
+            Self::DescriptorSet(_) => write!(f, "failed to create descriptor set"),
+            Self::Framebuffer(_) => write!(f, "failed to create framebuffer"),
+            Self::Begin(_) => write!(f, "failed to begin command buffer"),
+            Self::Copy(_) => write!(f, "failed to record copy"),
+            Self::Clear(_) => write!(f, "failed to record clearing the render target"),
+            Self::RenderPass(_) => write!(f, "failed to record render pass"),
+            Self::ExecuteCommands(_) => write!(f, "failed to execute draw command buffer"),
+            Self::Draw(_) => write!(f, "failed to record draw"),
+            Self::Build(_) => write!(f, "failed to build command buffer"),
+            Self::Execution(_) => write!(f, "failed to execute command buffer"),
+            Self::Flush(_) => write!(f, "failed to submit or wait for gpu work"),
+            Self::ImageRegistration(err) => write!(f, "failed to register image: {}", err),
+            Self::NoFrameToRedraw => write!(f, "no frame was drawn yet to redraw"),
+            Self::RedrawSizeChanged { cached, actual } => write!(
+                f,
+                "last frame was drawn on an image of {:?}, can't redraw it on {:?}",
+                cached, actual
+            ),
+            Self::FrameNotPrepared => write!(f, "no frame was prepared to render"),
+            Self::UnpreparedUploads => {
+                write!(f, "uploads were submitted after the frame was prepared")
+            }
+            Self::FrameTooLarge { vertices, indices, max_buffer_size } => write!(
+                f,
+                "mesh of {} vertices & {} indices exceeds the maximum buffer size of {} bytes",
+                vertices, indices, max_buffer_size
+            ),
+        }
+    }
+}
+
+impl From<AllocationCreationError> for EguiVulkanoError {
+    fn from(err: AllocationCreationError) -> Self {
+        Self::Allocation(err)
+    }
+}
+
+impl From<BufferError> for EguiVulkanoError {
+    fn from(err: BufferError) -> Self {
+        Self::Buffer(err)
+    }
+}
+
+impl From<ImmutableImageCreationError> for EguiVulkanoError {
+    fn from(err: ImmutableImageCreationError) -> Self {
+        Self::ImageCreation(err)
+    }
+}
+
+impl From<ImageError> for EguiVulkanoError {
+    fn from(err: ImageError) -> Self {
+        Self::Image(err)
+    }
+}
+
+impl From<ImageViewCreationError> for EguiVulkanoError {
+    fn from(err: ImageViewCreationError) -> Self {
+        Self::View(err)
+    }
+}
+
+impl From<DescriptorSetCreationError> for EguiVulkanoError {
+    fn from(err: DescriptorSetCreationError) -> Self {
+        Self::DescriptorSet(err)
+    }
+}
+
+impl From<FramebufferCreationError> for EguiVulkanoError {
+    fn from(err: FramebufferCreationError) -> Self {
+        Self::Framebuffer(err)
+    }
+}
+
+impl From<CommandBufferBeginError> for EguiVulkanoError {
+    fn from(err: CommandBufferBeginError) -> Self {
+        Self::Begin(err)
+    }
+}
+
+impl From<ClearError> for EguiVulkanoError {
+    fn from(err: ClearError) -> Self {
+        Self::Clear(Box::new(err))
+    }
+}
+
+impl From<RenderPassError> for EguiVulkanoError {
+    fn from(err: RenderPassError) -> Self {
+        Self::RenderPass(Box::new(err))
+    }
+}
+
+impl From<ExecuteCommandsError> for EguiVulkanoError {
+    fn from(err: ExecuteCommandsError) -> Self {
+        Self::ExecuteCommands(Box::new(err))
+    }
+}
+
+impl From<PipelineExecutionError> for EguiVulkanoError {
+    fn from(err: PipelineExecutionError) -> Self {
+        Self::Draw(Box::new(err))
+    }
+}
+
+impl From<BuildError> for EguiVulkanoError {
+    fn from(err: BuildError) -> Self {
+        Self::Build(err)
+    }
+}
+
+impl From<CommandBufferExecError> for EguiVulkanoError {
+    fn from(err: CommandBufferExecError) -> Self {
+        Self::Execution(err)
+    }
+}
+
+impl From<FlushError> for EguiVulkanoError {
+    fn from(err: FlushError) -> Self {
+        Self::Flush(err)
+    }
+}
+
+impl From<ImageRegistrationError> for EguiVulkanoError {
+    fn from(err: ImageRegistrationError) -> Self {
+        Self::ImageRegistration(err)
+    }
+}
+
+impl From<CopyError> for EguiVulkanoError {
+    fn from(err: CopyError) -> Self {
+        Self::Copy(Box::new(err))
+    }
+}
+
+/// Error that can happen when creating the integration
+#[derive(Debug)]
+pub enum GuiCreationError {
+    /// Format has no color aspect or can't be rendered to as a color attachment
+    NotColorRenderable(Format),
+    /// A shader of `GuiShaders` has no `main` entry point
+    MissingEntryPoint,
+    /// The pipeline could not be created, e.g. because a shader of `GuiShaders` doesn't match
+    /// the interface of egui's pipeline
+    Pipeline(Box<GraphicsPipelineCreationError>),
+    /// The device or queue lacks what the integration needs, `reason` lists all that's missing
+    UnsupportedDevice { reason: String },
+    /// The integration's render pass could not be created
+    RenderPass(RenderPassCreationError),
+}
+
+impl std::error::Error for GuiCreationError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Pipeline(err) => Some(err.as_ref()),
+            Self::RenderPass(err) => Some(err),
+            Self::NotColorRenderable(_)
+            | Self::MissingEntryPoint
+            | Self::UnsupportedDevice { .. } => None,
+        }
+    }
+}
+
+impl fmt::Display for GuiCreationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotColorRenderable(format) => {
+                write!(f, "format {:?} can't be rendered to as a color attachment", format)
+            }
+            Self::MissingEntryPoint => write!(f, "shader has no `main` entry point"),
+            Self::Pipeline(err) => write!(f, "failed to create pipeline: {}", err),
+            Self::UnsupportedDevice { reason } => write!(f, "unsupported device: {}", reason),
+            Self::RenderPass(err) => write!(f, "failed to create render pass: {}", err),
+        }
+    }
+}
+
+impl From<GraphicsPipelineCreationError> for GuiCreationError {
+    fn from(err: GraphicsPipelineCreationError) -> Self {
+        Self::Pipeline(Box::new(err))
+    }
+}
+
+impl From<RenderPassCreationError> for GuiCreationError {
+    fn from(err: RenderPassCreationError) -> Self {
+        Self::RenderPass(err)
+    }
+}
+
+/// Error that can happen when adding a font
+#[derive(Debug)]
+pub enum FontError {
+    /// Font data isn't a valid TTF or OTF font
+    InvalidFont(ab_glyph::InvalidFont),
+}
+
+impl std::error::Error for FontError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::InvalidFont(err) => Some(err),
+        }
+    }
+}
+
+impl fmt::Display for FontError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidFont(_) => write!(f, "failed to parse font data"),
+        }
+    }
+}
+
+impl From<ab_glyph::InvalidFont> for FontError {
+    fn from(err: ab_glyph::InvalidFont) -> Self {
+        Self::InvalidFont(err)
+    }
+}
+
+/// Error that can happen when reading an image back to the CPU
+#[derive(Debug)]
+pub enum ReadbackError {
+    /// Only `R8G8B8A8` & `B8G8R8A8` formats can be read back
+    UnsupportedFormat(Format),
+    /// Multisampled images can't be copied, resolve them first
+    Multisampled,
+    /// The image wasn't created with transfer src usage
+    NotTransferSource,
+    /// No frame was drawn on an image with the integration's own render pass yet, see
+    /// `Gui::read_back_last_frame`
+    NoFrameDrawn,
+    /// Buffer to copy the image to could not be created or read
+    Buffer(BufferError),
+    /// Copy command buffer could not be started
+    Begin(CommandBufferBeginError),
+    /// Image could not be copied to the buffer
+    Copy(Box<CopyError>),
+    /// Copy command buffer could not be built
+    Build(BuildError),
+    /// Copy command buffer could not be executed
+    Execution(CommandBufferExecError),
+    /// Copy could not be submitted or waited on
+    Flush(FlushError),
+}
+
+impl std::error::Error for ReadbackError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::UnsupportedFormat(_)
+            | Self::Multisampled
+            | Self::NotTransferSource
+            | Self::NoFrameDrawn => None,
+            Self::Buffer(err) => Some(err),
+            Self::Begin(err) => Some(err),
+            Self::Copy(err) => Some(err.as_ref()),
+            Self::Build(err) => Some(err),
+            Self::Execution(err) => Some(err),
+            Self::Flush(err) => Some(err),
+        }
+    }
+}
+
+impl fmt::Display for ReadbackError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnsupportedFormat(format) => {
+                write!(f, "can't read back image with format {:?}", format)
+            }
+            Self::Multisampled => write!(f, "can't read back multisampled image"),
+            Self::NotTransferSource => write!(f, "image lacks transfer src usage"),
+            Self::NoFrameDrawn => write!(f, "no frame was drawn on an image yet"),
+            Self::Buffer(_) => write!(f, "failed to create or read readback buffer"),
+            Self::Begin(_) => write!(f, "failed to begin readback command buffer"),
+            Self::Copy(_) => write!(f, "failed to copy image to readback buffer"),
+            Self::Build(_) => write!(f, "failed to build readback command buffer"),
+            Self::Execution(_) => write!(f, "failed to execute readback command buffer"),
+            Self::Flush(_) => write!(f, "failed to wait for readback"),
+        }
+    }
+}
+
+impl From<BufferError> for ReadbackError {
+    fn from(err: BufferError) -> Self {
+        Self::Buffer(err)
+    }
+}
+
+impl From<CommandBufferBeginError> for ReadbackError {
+    fn from(err: CommandBufferBeginError) -> Self {
+        Self::Begin(err)
+    }
+}
+
+impl From<CopyError> for ReadbackError {
+    fn from(err: CopyError) -> Self {
+        Self::Copy(Box::new(err))
+    }
+}
+
+impl From<BuildError> for ReadbackError {
+    fn from(err: BuildError) -> Self {
+        Self::Build(err)
+    }
+}
+
+impl From<CommandBufferExecError> for ReadbackError {
+    fn from(err: CommandBufferExecError) -> Self {
+        Self::Execution(err)
+    }
+}
+
+impl From<FlushError> for ReadbackError {
+    fn from(err: FlushError) -> Self {
+        Self::Flush(err)
+    }
+}
+
+/// Copies the first mip level & array layer of `image` to the CPU after `before_future` (e.g.
+/// the future of drawing to it) and waits for the copy. Returns the width, height and tightly
+/// packed rgba8 pixels, which are swizzled from bgra8 if needed. Color values are copied as
+/// stored, i.e. sRGB encoded for `_SRGB` formats.
+///
+/// The image must be single sampled and have transfer src usage.
+pub fn read_texture_to_vec(
+    allocators: &Allocators,
+    queue: Arc<Queue>,
+    image: Arc<dyn ImageViewAbstract>,
+    before_future: impl GpuFuture + 'static,
+) -> Result<(u32, u32, Vec<u8>), ReadbackError> {
+    let format = image.format().unwrap();
+    let bgra = match format {
+        Format::R8G8B8A8_UNORM | Format::R8G8B8A8_SRGB => false,
+        Format::B8G8R8A8_UNORM | Format::B8G8R8A8_SRGB => true,
+        _ => return Err(ReadbackError::UnsupportedFormat(format)),
     };
-    let dimensions = img.dimensions();
-    immutable_texture_from_bytes(allocators, queue, &rgba, [dimensions.0, dimensions.1], format)
+    if image.image().samples() != SampleCount::Sample1 {
+        return Err(ReadbackError::Multisampled);
+    }
+    if !image.usage().intersects(ImageUsage::TRANSFER_SRC) {
+        return Err(ReadbackError::NotTransferSource);
+    }
+    let subresource_range = image.subresource_range();
+    let mip_level = subresource_range.mip_levels.start;
+    let [width, height] =
+        image.image().dimensions().mip_level_dimensions(mip_level).unwrap().width_height();
+
+    let buffer = Buffer::new_slice::<u8>(
+        &allocators.memory,
+        BufferCreateInfo { usage: BufferUsage::TRANSFER_DST, ..Default::default() },
+        AllocationCreateInfo { usage: MemoryUsage::Download, ..Default::default() },
+        width as u64 * height as u64 * 4,
+    )?;
+    let mut cbb = AutoCommandBufferBuilder::primary(
+        &allocators.command_buffer,
+        queue.queue_family_index(),
+        CommandBufferUsage::OneTimeSubmit,
+    )?;
+    // Rows are tightly packed in the buffer, whatever the image's row pitch is
+    cbb.copy_image_to_buffer(CopyImageToBufferInfo {
+        regions: [BufferImageCopy {
+            image_subresource: ImageSubresourceLayers {
+                aspects: ImageAspects::COLOR,
+                mip_level,
+                array_layers: subresource_range.array_layers.start
+                    ..subresource_range.array_layers.start + 1,
+            },
+            image_extent: [width, height, 1],
+            ..Default::default()
+        }]
+        .into(),
+        ..CopyImageToBufferInfo::image_buffer(image.image(), buffer.clone())
+    })?;
+    let command_buffer = cbb.build()?;
+    before_future.then_execute(queue, command_buffer)?.then_signal_fence_and_flush()?.wait(None)?;
+
+    let mut pixels = buffer.read()?.to_vec();
+    if bgra {
+        pixels.chunks_exact_mut(4).for_each(|pixel| pixel.swap(0, 2));
+    }
+    Ok((width, height, pixels))
+}
+
+type WorkerJob = Box<dyn FnOnce() + Send>;
+
+/// Threads kept for the lifetime of their owner, e.g. of parallel recording or of
+/// `Gui::end_frame_async`, so work done each frame doesn't spawn threads. Each takes the next
+/// job off a shared queue.
+pub(crate) struct WorkerThreads {
+    jobs: Option<mpsc::Sender<WorkerJob>>,
+    threads: Vec<JoinHandle<()>>,
+}
+
+impl WorkerThreads {
+    /// Starts `threads` threads named `name` and their index
+    pub(crate) fn new(threads: usize, name: &str) -> WorkerThreads {
+        let (jobs, queue) = mpsc::channel::<WorkerJob>();
+        let queue = Arc::new(Mutex::new(queue));
+        let threads = (0..threads)
+            .map(|index| {
+                let queue = queue.clone();
+                std::thread::Builder::new()
+                    .name(format!("{}-{}", name, index))
+                    .spawn(move || loop {
+                        // The lock is released before the job runs, so others take the next one
+                        let job = queue.lock().unwrap().recv();
+                        match job {
+                            Ok(job) => job(),
+                            Err(_) => break,
+                        }
+                    })
+                    .expect("Failed to spawn gui worker thread")
+            })
+            .collect();
+        WorkerThreads { jobs: Some(jobs), threads }
+    }
+
+    pub(crate) fn threads(&self) -> usize {
+        self.threads.len()
+    }
+
+    /// Runs `job` on the next idle thread and returns the receiver of its result, or of its
+    /// panic, which doesn't stop the thread
+    pub(crate) fn run<T: Send + 'static>(
+        &self,
+        job: impl FnOnce() -> T + Send + 'static,
+    ) -> mpsc::Receiver<std::thread::Result<T>> {
+        let (sender, receiver) = mpsc::sync_channel(1);
+        let job: WorkerJob = Box::new(move || {
+            let _ = sender.send(std::panic::catch_unwind(AssertUnwindSafe(job)));
+        });
+        let _ = self.jobs.as_ref().expect("Gui worker threads stopped").send(job);
+        receiver
+    }
+}
+
+impl Drop for WorkerThreads {
+    fn drop(&mut self) {
+        // Closing the queue stops the threads once their jobs are done
+        self.jobs = None;
+        for thread in self.threads.drain(..) {
+            let _ = thread.join();
+        }
+    }
 }
 
 pub struct Allocators {
@@ -95,3 +1443,138 @@ impl Allocators {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use image::{
+        DynamicImage, GrayAlphaImage, GrayImage, ImageBuffer, ImageOutputFormat, Luma, Rgb,
+        RgbImage, Rgba,
+    };
+
+    use super::*;
+
+    fn png(image: DynamicImage) -> Vec<u8> {
+        let mut bytes = Cursor::new(vec![]);
+        image.write_to(&mut bytes, ImageOutputFormat::Png).unwrap();
+        bytes.into_inner()
+    }
+
+    fn pixels(image: &RgbaImage) -> Vec<[u8; 4]> {
+        image.pixels().map(|pixel| pixel.0).collect()
+    }
+
+    fn decode(file_bytes: &[u8]) -> RgbaImage {
+        decode_rgba(file_bytes, false, false, false).unwrap()
+    }
+
+    #[test]
+    fn gray_images_are_expanded() {
+        let luma = GrayImage::from_raw(2, 1, vec![10, 200]).unwrap();
+        let rgba = decode(&png(DynamicImage::ImageLuma8(luma)));
+        assert_eq!(rgba.dimensions(), (2, 1));
+        assert_eq!(pixels(&rgba), vec![[10, 10, 10, 255], [200, 200, 200, 255]]);
+
+        let luma_alpha = GrayAlphaImage::from_raw(1, 1, vec![50, 128]).unwrap();
+        let rgba = decode(&png(DynamicImage::ImageLumaA8(luma_alpha)));
+        assert_eq!(pixels(&rgba), vec![[50, 50, 50, 128]]);
+    }
+
+    #[test]
+    fn missing_alpha_is_opaque() {
+        let rgb = RgbImage::from_raw(2, 1, vec![1, 2, 3, 4, 5, 6]).unwrap();
+        let rgba = decode(&png(DynamicImage::ImageRgb8(rgb)));
+        assert_eq!(pixels(&rgba), vec![[1, 2, 3, 255], [4, 5, 6, 255]]);
+    }
+
+    #[test]
+    fn sixteen_bit_channels_are_converted_to_eight_bit() {
+        let rgba16: ImageBuffer<Rgba<u16>, _> =
+            ImageBuffer::from_raw(1, 1, vec![u16::MAX, 0, 0x8080, 0x4040]).unwrap();
+        let rgba = decode(&png(DynamicImage::ImageRgba16(rgba16)));
+        assert_eq!(pixels(&rgba), vec![[255, 0, 128, 64]]);
+
+        let rgb16: ImageBuffer<Rgb<u16>, _> = ImageBuffer::from_raw(1, 1, vec![0x0101; 3]).unwrap();
+        let rgba = decode(&png(DynamicImage::ImageRgb16(rgb16)));
+        assert_eq!(pixels(&rgba), vec![[1, 1, 1, 255]]);
+
+        let luma16: ImageBuffer<Luma<u16>, _> = ImageBuffer::from_raw(1, 1, vec![0xffff]).unwrap();
+        let rgba = decode(&png(DynamicImage::ImageLuma16(luma16)));
+        assert_eq!(pixels(&rgba), vec![[255, 255, 255, 255]]);
+    }
+
+    #[test]
+    fn images_are_flipped_vertically() {
+        let luma = GrayImage::from_raw(2, 2, vec![1, 2, 3, 4]).unwrap();
+        let rgba = decode_rgba(&png(DynamicImage::ImageLuma8(luma)), true, false, false).unwrap();
+        let red: Vec<u8> = pixels(&rgba).iter().map(|pixel| pixel[0]).collect();
+        assert_eq!(red, vec![3, 4, 1, 2]);
+    }
+
+    #[test]
+    fn alpha_is_premultiplied() {
+        let colors = vec![200, 100, 0, 128, 10, 20, 30, 255, 90, 80, 70, 0];
+        let file_bytes = png(DynamicImage::ImageRgba8(RgbaImage::from_raw(3, 1, colors).unwrap()));
+        let linear = decode_rgba(&file_bytes, false, true, false).unwrap();
+        assert_eq!(pixels(&linear), vec![[100, 50, 0, 128], [10, 20, 30, 255], [0, 0, 0, 0]]);
+
+        let srgb = decode_rgba(&file_bytes, false, true, true).unwrap();
+        let expected = [[200, 100, 0, 128], [10, 20, 30, 255], [90, 80, 70, 0]]
+            .map(|[r, g, b, a]| egui::Color32::from_rgba_unmultiplied(r, g, b, a).to_array());
+        assert_eq!(pixels(&srgb), expected);
+        // Premultiplying in linear space keeps more of sRGB colors than premultiplying their bytes
+        assert!(pixels(&srgb)[0][0] > 100);
+        assert_eq!(pixels(&srgb)[1], [10, 20, 30, 255]);
+
+        let flipped = decode_rgba(&file_bytes, true, true, false).unwrap();
+        assert_eq!(pixels(&flipped), pixels(&linear));
+    }
+
+    #[test]
+    fn image_bytes_must_fill_the_image() {
+        assert!(check_image_bytes(&[0; 16], [2, 2], Format::R8G8B8A8_SRGB).is_ok());
+        assert!(check_image_bytes(&[0; 4], [2, 2], Format::R8_UNORM).is_ok());
+        assert!(check_image_bytes(&[0; 32], [2, 2], Format::R16G16B16A16_SFLOAT).is_ok());
+        assert!(matches!(
+            check_image_bytes(&[0; 15], [2, 2], Format::R8G8B8A8_UNORM),
+            Err(ImageRegistrationError::SizeMismatch { expected: 16, actual: 15 })
+        ));
+        assert!(matches!(
+            check_image_bytes(&[], [0, 2], Format::R8G8B8A8_UNORM),
+            Err(ImageRegistrationError::ZeroSize([0, 2]))
+        ));
+        for format in
+            [Format::BC1_RGBA_UNORM_BLOCK, Format::D32_SFLOAT, Format::G8_B8R8_2PLANE_420_UNORM]
+        {
+            assert!(
+                matches!(
+                    check_image_bytes(&[0; 16], [2, 2], format),
+                    Err(ImageRegistrationError::UnsupportedFormat(f)) if f == format
+                ),
+                "{:?}",
+                format
+            );
+        }
+    }
+
+    #[test]
+    fn unknown_formats_are_unrecognized() {
+        assert!(matches!(
+            decode_rgba(b"not an image", false, false, false),
+            Err(ImageRegistrationError::UnrecognizedFileFormat)
+        ));
+    }
+
+    #[test]
+    fn worker_threads_survive_panicking_jobs() {
+        let workers = WorkerThreads::new(1, "test-worker");
+        let panicked = workers.run(|| -> u32 { panic!("job panicked") });
+        assert!(panicked.recv().unwrap().is_err());
+        // The same thread runs the next job
+        let results: Vec<_> = (0..4).map(|job| workers.run(move || job * 2)).collect();
+        let results: Vec<_> =
+            results.iter().map(|result| result.recv().unwrap().unwrap()).collect();
+        assert_eq!(results, vec![0, 2, 4, 6]);
+    }
+}
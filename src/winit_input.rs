@@ -0,0 +1,498 @@
+// Copyright (c) 2021 Okko Hakola
+// Licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT
+// license <LICENSE-MIT or https://opensource.org/licenses/MIT>,
+// at your option. All files in the project carrying such
+// notice may not be copied, modified, or distributed except
+// according to those terms.
+
+//! Winit window event translation into egui input, shared by `Gui` and usable on its own, e.g.
+//! by another renderer backend.
+
+pub use egui_winit::EventResponse;
+use raw_window_handle::HasRawDisplayHandle;
+use winit::{
+    event::{
+        Force, KeyboardInput, ModifiersState, MouseScrollDelta, Touch, TouchPhase, VirtualKeyCode,
+        WindowEvent,
+    },
+    window::Window,
+};
+
+/// Points egui-winit scrolls per line of mouse wheel scrolling
+pub(crate) const EGUI_WINIT_SCROLL_LINE_HEIGHT: f32 = 50.0;
+
+/// Collects egui input from winit window events like `Gui::update` does, without a renderer.
+/// Wraps egui-winit's `State` and adds the integration's fixes on top of it:
+/// - Numpad enter is passed on as enter
+/// - Line scrolls are scaled to `set_scroll_line_height`, unless zooming with ctrl / cmd held
+/// - Touch forces are normalized to 0..=1, and touches without a force touch at full force
+/// - Characters are only passed on as text when they are printable and typed without a
+///   shortcut modifier, see `Gui::update`
+///
+/// Window state such as the scale factor is left to egui-winit, see `egui_winit`.
+pub struct WinitInputState {
+    pub(crate) egui_winit: egui_winit::State,
+    translation: InputTranslation,
+}
+
+impl WinitInputState {
+    /// Creates an input state for windows of `display_target`, e.g. the event loop
+    pub fn new(display_target: &dyn HasRawDisplayHandle) -> WinitInputState {
+        WinitInputState {
+            egui_winit: egui_winit::State::new(display_target),
+            translation: InputTranslation::new(),
+        }
+    }
+
+    /// Returns the wrapped egui-winit state, e.g. to set the pixels per point or handle egui's
+    /// platform output
+    pub fn egui_winit(&mut self) -> &mut egui_winit::State {
+        &mut self.egui_winit
+    }
+
+    /// Sets how many points egui scrolls per line of mouse wheel scrolling. Defaults to 50.
+    pub fn set_scroll_line_height(&mut self, points_per_line: f32) {
+        self.translation.scroll_line_height = points_per_line;
+    }
+
+    /// Passes `event` on to egui-winit after translating it. Characters that aren't text input
+    /// aren't passed on, and aren't consumed.
+    pub fn on_event(&mut self, egui_ctx: &egui::Context, event: &WindowEvent<'_>) -> EventResponse {
+        self.translation.on_event(&mut self.egui_winit, egui_ctx, event)
+    }
+
+    /// Takes the input collected since the last call, to begin the next egui frame with
+    pub fn take_raw_input(&mut self, window: &Window) -> egui::RawInput {
+        self.egui_winit.take_egui_input(window)
+    }
+}
+
+/// State of the translation `WinitInputState` does on top of egui-winit, for `Gui`, which
+/// keeps its egui-winit state in its own field
+pub(crate) struct InputTranslation {
+    modifiers: ModifiersState,
+    scroll_line_height: f32,
+}
+
+impl InputTranslation {
+    pub(crate) fn new() -> InputTranslation {
+        InputTranslation {
+            modifiers: ModifiersState::empty(),
+            scroll_line_height: EGUI_WINIT_SCROLL_LINE_HEIGHT,
+        }
+    }
+
+    pub(crate) fn set_scroll_line_height(&mut self, points_per_line: f32) {
+        self.scroll_line_height = points_per_line;
+    }
+
+    /// Passes `event` on to `egui_winit` after translating it, see `WinitInputState::on_event`
+    pub(crate) fn on_event(
+        &mut self,
+        egui_winit: &mut egui_winit::State,
+        egui_ctx: &egui::Context,
+        event: &WindowEvent<'_>,
+    ) -> EventResponse {
+        match event {
+            WindowEvent::ModifiersChanged(modifiers) => self.modifiers = *modifiers,
+            // Modifier releases happening while unfocused aren't reported
+            WindowEvent::Focused(false) => self.modifiers = ModifiersState::empty(),
+            WindowEvent::ReceivedCharacter(ch) if !is_text_input(*ch, self.modifiers) => {
+                return EventResponse { consumed: false, repaint: false };
+            }
+            _ => (),
+        }
+        let translated = translate_event(event, self.modifiers, self.scroll_line_height);
+        egui_winit.on_event(egui_ctx, translated.as_ref().unwrap_or(event))
+    }
+}
+
+/// Rewrites events egui-winit would translate differently than the integration, see
+/// `WinitInputState`. Returns `None` for events passed on as they are.
+#[allow(deprecated)]
+fn translate_event(
+    event: &WindowEvent<'_>,
+    modifiers: ModifiersState,
+    scroll_line_height: f32,
+) -> Option<WindowEvent<'static>> {
+    match event {
+        // Egui-winit doesn't map numpad enter, so it is passed on as the main enter key
+        WindowEvent::KeyboardInput { device_id, input, is_synthetic }
+            if input.virtual_keycode == Some(VirtualKeyCode::NumpadEnter) =>
+        {
+            Some(WindowEvent::KeyboardInput {
+                device_id: *device_id,
+                input: KeyboardInput { virtual_keycode: Some(VirtualKeyCode::Return), ..*input },
+                is_synthetic: *is_synthetic,
+            })
+        }
+        // Egui-winit scrolls a fixed amount of points per line, so lines are scaled to match
+        // the configured line height. Zooming with ctrl / cmd held keeps its speed.
+        WindowEvent::MouseWheel {
+            device_id,
+            delta: MouseScrollDelta::LineDelta(x, y),
+            phase,
+            modifiers: event_modifiers,
+        } if scroll_line_height != EGUI_WINIT_SCROLL_LINE_HEIGHT
+            && !(modifiers.ctrl() || modifiers.logo()) =>
+        {
+            let scale = scroll_line_height / EGUI_WINIT_SCROLL_LINE_HEIGHT;
+            Some(WindowEvent::MouseWheel {
+                device_id: *device_id,
+                delta: MouseScrollDelta::LineDelta(x * scale, y * scale),
+                phase: *phase,
+                modifiers: *event_modifiers,
+            })
+        }
+        // Egui-winit takes forces as is, so a missing force would draw invisible strokes
+        WindowEvent::Touch(touch) => Some(WindowEvent::Touch(Touch {
+            force: Some(Force::Normalized(normalized_force(touch))),
+            ..*touch
+        })),
+        _ => None,
+    }
+}
+
+/// Whether a received character is text input rather than a side effect of a key or shortcut
+fn is_text_input(ch: char, modifiers: ModifiersState) -> bool {
+    let is_private_use = ('\u{e000}'..='\u{f8ff}').contains(&ch)
+        || ('\u{f0000}'..='\u{ffffd}').contains(&ch)
+        || ('\u{100000}'..='\u{10fffd}').contains(&ch);
+    // Windows reports AltGr as ctrl + alt, which types characters on many layouts
+    let is_shortcut = if cfg!(target_os = "macos") {
+        modifiers.logo() || modifiers.ctrl()
+    } else {
+        modifiers.ctrl() && !modifiers.alt()
+    };
+    // Enter, tab, backspace, delete and escape also arrive as control characters besides their
+    // key events, as do chords like ctrl + a, which egui would otherwise handle twice
+    !ch.is_control() && !is_private_use && !is_shortcut
+}
+
+/// Force of `touch` in 0..=1, both for calibrated & normalized forces. Devices that don't report
+/// forces (e.g. touch screens) touch at full force.
+fn normalized_force(touch: &Touch) -> f64 {
+    let force = match touch.force {
+        Some(Force::Normalized(force)) => force,
+        Some(Force::Calibrated { force, max_possible_force, .. }) if max_possible_force > 0.0 => {
+            force / max_possible_force
+        }
+        _ => match touch.phase {
+            TouchPhase::Started | TouchPhase::Moved => 1.0,
+            TouchPhase::Ended | TouchPhase::Cancelled => 0.0,
+        },
+    };
+    if force.is_nan() {
+        0.0
+    } else {
+        force.clamp(0.0, 1.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use raw_window_handle::{RawDisplayHandle, WindowsDisplayHandle};
+    use winit::{
+        dpi::PhysicalPosition,
+        event::{DeviceId, ElementState},
+    };
+
+    use super::*;
+
+    struct TestDisplay;
+
+    unsafe impl HasRawDisplayHandle for TestDisplay {
+        fn raw_display_handle(&self) -> RawDisplayHandle {
+            RawDisplayHandle::Windows(WindowsDisplayHandle::empty())
+        }
+    }
+
+    fn device_id() -> DeviceId {
+        unsafe { DeviceId::dummy() }
+    }
+
+    #[allow(deprecated)]
+    fn key(keycode: VirtualKeyCode, state: ElementState) -> WindowEvent<'static> {
+        WindowEvent::KeyboardInput {
+            device_id: device_id(),
+            input: KeyboardInput {
+                scancode: 0,
+                state,
+                virtual_keycode: Some(keycode),
+                modifiers: ModifiersState::empty(),
+            },
+            is_synthetic: false,
+        }
+    }
+
+    #[allow(deprecated)]
+    fn line_scroll(y: f32) -> WindowEvent<'static> {
+        WindowEvent::MouseWheel {
+            device_id: device_id(),
+            delta: MouseScrollDelta::LineDelta(0.0, y),
+            phase: TouchPhase::Moved,
+            modifiers: ModifiersState::empty(),
+        }
+    }
+
+    fn touch(phase: TouchPhase, force: Option<Force>) -> Touch {
+        Touch {
+            device_id: device_id(),
+            phase,
+            location: PhysicalPosition::new(10.0, 20.0),
+            force,
+            id: 0,
+        }
+    }
+
+    // Events egui-winit collected from `events`
+    fn egui_events(events: &[WindowEvent<'_>]) -> Vec<egui::Event> {
+        let egui_ctx = egui::Context::default();
+        let mut input = WinitInputState::new(&TestDisplay);
+        for event in events {
+            let _ = input.on_event(&egui_ctx, event);
+        }
+        input.egui_winit.egui_input().events.clone()
+    }
+
+    fn texts(events: &[egui::Event]) -> Vec<String> {
+        events
+            .iter()
+            .filter_map(|event| match event {
+                egui::Event::Text(text) => Some(text.clone()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn numpad_enter_is_enter() {
+        let events = egui_events(&[
+            key(VirtualKeyCode::NumpadEnter, ElementState::Pressed),
+            key(VirtualKeyCode::NumpadEnter, ElementState::Released),
+        ]);
+        let keys: Vec<_> = events
+            .iter()
+            .filter_map(|event| match event {
+                egui::Event::Key { key, pressed, .. } => Some((*key, *pressed)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(keys, vec![(egui::Key::Enter, true), (egui::Key::Enter, false)]);
+    }
+
+    #[test]
+    fn line_scrolls_are_scaled_to_line_height() {
+        let egui_ctx = egui::Context::default();
+        let mut input = WinitInputState::new(&TestDisplay);
+        input.set_scroll_line_height(100.0);
+        let _ = input.on_event(&egui_ctx, &line_scroll(1.5));
+        assert!(input
+            .egui_winit
+            .egui_input()
+            .events
+            .contains(&egui::Event::Scroll(egui::vec2(0.0, 150.0))));
+    }
+
+    #[test]
+    fn line_scrolls_keep_egui_winit_line_height_by_default() {
+        assert!(translate_event(&line_scroll(1.0), ModifiersState::empty(), 50.0).is_none());
+        let events = egui_events(&[line_scroll(1.0)]);
+        assert!(events.contains(&egui::Event::Scroll(egui::vec2(0.0, 50.0))));
+    }
+
+    #[test]
+    fn zooming_line_scrolls_are_not_scaled() {
+        for modifiers in [ModifiersState::CTRL, ModifiersState::LOGO] {
+            assert!(translate_event(&line_scroll(1.0), modifiers, 100.0).is_none());
+        }
+        let scaled = translate_event(&line_scroll(1.0), ModifiersState::SHIFT, 100.0);
+        assert!(matches!(
+            scaled,
+            Some(WindowEvent::MouseWheel { delta: MouseScrollDelta::LineDelta(x, y), .. })
+                if x == 0.0 && y == 2.0
+        ));
+
+        let egui_ctx = egui::Context::default();
+        let mut input = WinitInputState::new(&TestDisplay);
+        input.set_scroll_line_height(100.0);
+        let _ = input.on_event(&egui_ctx, &WindowEvent::ModifiersChanged(ModifiersState::CTRL));
+        let _ = input.on_event(&egui_ctx, &line_scroll(1.0));
+        let zoom = (50.0f32 / 200.0).exp();
+        assert!(input.egui_winit.egui_input().events.contains(&egui::Event::Zoom(zoom)));
+    }
+
+    #[test]
+    fn forces_are_normalized() {
+        let calibrated = |force, max_possible_force| {
+            Some(Force::Calibrated { force, max_possible_force, altitude_angle: None })
+        };
+        let cases = [
+            (TouchPhase::Moved, Some(Force::Normalized(0.25)), 0.25),
+            (TouchPhase::Moved, Some(Force::Normalized(1.5)), 1.0),
+            (TouchPhase::Moved, Some(Force::Normalized(f64::NAN)), 0.0),
+            (TouchPhase::Moved, calibrated(3.0, 4.0), 0.75),
+            (TouchPhase::Moved, calibrated(6.0, 4.0), 1.0),
+            (TouchPhase::Moved, calibrated(-1.0, 4.0), 0.0),
+            (TouchPhase::Moved, calibrated(1.0, 0.0), 1.0),
+            (TouchPhase::Started, None, 1.0),
+            (TouchPhase::Moved, None, 1.0),
+            (TouchPhase::Ended, None, 0.0),
+            (TouchPhase::Cancelled, None, 0.0),
+        ];
+        for (phase, force, expected) in cases {
+            assert_eq!(normalized_force(&touch(phase, force)), expected, "{:?} {:?}", phase, force);
+        }
+    }
+
+    #[test]
+    fn touches_without_force_touch_at_full_force() {
+        let events = egui_events(&[WindowEvent::Touch(touch(TouchPhase::Started, None))]);
+        assert!(events
+            .iter()
+            .any(|event| matches!(event, egui::Event::Touch { force, .. } if *force == 1.0)));
+    }
+
+    #[test]
+    fn printable_characters_are_text() {
+        for ch in ['a', 'Z', '7', ' ', '@', 'é', 'ß', '€', '漢'] {
+            assert!(is_text_input(ch, ModifiersState::empty()), "{:?}", ch);
+            assert!(is_text_input(ch, ModifiersState::SHIFT), "{:?}", ch);
+        }
+    }
+
+    #[test]
+    fn shortcut_characters_are_not_text() {
+        let command =
+            if cfg!(target_os = "macos") { ModifiersState::LOGO } else { ModifiersState::CTRL };
+        assert!(!is_text_input('c', command));
+        assert!(!is_text_input('c', command | ModifiersState::SHIFT));
+        assert!(!is_text_input('\u{3}', ModifiersState::CTRL));
+    }
+
+    #[test]
+    fn control_characters_are_not_text() {
+        // Ctrl + c, tab, enter (windows & macOS / X11), backspace, escape and delete
+        for ch in ['\u{3}', '\t', '\r', '\n', '\u{8}', '\u{1b}', '\u{7f}'] {
+            assert!(!is_text_input(ch, ModifiersState::empty()), "{:?}", ch);
+        }
+    }
+
+    #[test]
+    fn private_use_characters_are_not_text() {
+        // macOS function keys: arrows, F1, home and the `fn` + delete key
+        for ch in ['\u{f700}', '\u{f703}', '\u{f704}', '\u{f729}', '\u{f728}'] {
+            assert!(!is_text_input(ch, ModifiersState::empty()), "{:?}", ch);
+        }
+        assert!(!is_text_input('\u{f0000}', ModifiersState::empty()));
+        assert!(!is_text_input('\u{10fffd}', ModifiersState::empty()));
+    }
+
+    #[cfg(not(target_os = "macos"))]
+    #[test]
+    fn alt_gr_types_text() {
+        // Windows reports AltGr as ctrl + alt, e.g. for `@` and `€` on german layouts
+        let alt_gr = ModifiersState::CTRL | ModifiersState::ALT;
+        for ch in ['@', '€', '{', '\\'] {
+            assert!(is_text_input(ch, alt_gr), "{:?}", ch);
+        }
+        assert!(!is_text_input('a', ModifiersState::CTRL));
+        assert!(!is_text_input('A', ModifiersState::CTRL | ModifiersState::SHIFT));
+        // The logo key isn't a shortcut modifier outside macOS
+        assert!(is_text_input('a', ModifiersState::LOGO));
+    }
+
+    #[cfg(target_os = "macos")]
+    #[test]
+    fn cmd_and_ctrl_chords_are_not_text() {
+        assert!(!is_text_input('c', ModifiersState::LOGO));
+        assert!(!is_text_input('a', ModifiersState::CTRL));
+        assert!(!is_text_input('a', ModifiersState::CTRL | ModifiersState::ALT));
+        // Option types characters
+        assert!(is_text_input('å', ModifiersState::ALT));
+        assert!(is_text_input('@', ModifiersState::ALT | ModifiersState::SHIFT));
+    }
+
+    #[test]
+    fn filtered_characters_are_not_consumed() {
+        let egui_ctx = egui::Context::default();
+        let mut input = WinitInputState::new(&TestDisplay);
+        let response = input.on_event(&egui_ctx, &WindowEvent::ReceivedCharacter('\u{1b}'));
+        assert!(!response.consumed && !response.repaint);
+        assert!(input.egui_winit.egui_input().events.is_empty());
+    }
+
+    fn pressed_keys(events: &[egui::Event]) -> Vec<(egui::Key, egui::Modifiers)> {
+        events
+            .iter()
+            .filter_map(|event| match event {
+                egui::Event::Key { key, pressed: true, modifiers, .. } => Some((*key, *modifiers)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn enter_is_a_key_without_text() {
+        // Winit sends the carriage return between the press and release
+        let events = egui_events(&[
+            key(VirtualKeyCode::Return, ElementState::Pressed),
+            WindowEvent::ReceivedCharacter('\r'),
+            key(VirtualKeyCode::Return, ElementState::Released),
+        ]);
+        assert_eq!(pressed_keys(&events), vec![(egui::Key::Enter, egui::Modifiers::NONE)]);
+        assert!(texts(&events).is_empty());
+    }
+
+    #[test]
+    fn ctrl_a_is_a_shortcut_without_text() {
+        let events = egui_events(&[
+            WindowEvent::ModifiersChanged(ModifiersState::CTRL),
+            key(VirtualKeyCode::LControl, ElementState::Pressed),
+            key(VirtualKeyCode::A, ElementState::Pressed),
+            // Windows & X11 send the control character, macOS the letter
+            WindowEvent::ReceivedCharacter('\u{1}'),
+            WindowEvent::ReceivedCharacter('a'),
+            key(VirtualKeyCode::A, ElementState::Released),
+            key(VirtualKeyCode::LControl, ElementState::Released),
+            WindowEvent::ModifiersChanged(ModifiersState::empty()),
+        ]);
+        let ctrl = egui::Modifiers {
+            ctrl: true,
+            command: !cfg!(target_os = "macos"),
+            ..Default::default()
+        };
+        assert_eq!(pressed_keys(&events), vec![(egui::Key::A, ctrl)]);
+        assert!(texts(&events).is_empty());
+    }
+
+    #[test]
+    fn typing_is_text() {
+        let mut events = vec![];
+        for (keycode, ch) in [(VirtualKeyCode::H, 'h'), (VirtualKeyCode::I, 'i')] {
+            events.push(key(keycode, ElementState::Pressed));
+            events.push(WindowEvent::ReceivedCharacter(ch));
+            events.push(key(keycode, ElementState::Released));
+        }
+        events.push(WindowEvent::ModifiersChanged(ModifiersState::SHIFT));
+        events.push(key(VirtualKeyCode::LShift, ElementState::Pressed));
+        events.push(key(VirtualKeyCode::Key1, ElementState::Pressed));
+        events.push(WindowEvent::ReceivedCharacter('!'));
+        let events = egui_events(&events);
+        assert_eq!(texts(&events), vec!["h", "i", "!"]);
+        let keys: Vec<_> = pressed_keys(&events).into_iter().map(|(key, _)| key).collect();
+        assert_eq!(keys, vec![egui::Key::H, egui::Key::I, egui::Key::Num1]);
+    }
+
+    #[test]
+    fn focus_loss_clears_modifiers() {
+        let events = egui_events(&[
+            WindowEvent::ModifiersChanged(ModifiersState::CTRL),
+            WindowEvent::Focused(false),
+            WindowEvent::ReceivedCharacter('c'),
+        ]);
+        assert_eq!(texts(&events), vec!["c"]);
+    }
+}